@@ -0,0 +1,90 @@
+//! The audience (aud) claim of a token, which may be a single string or an array of
+//! strings. Serializes back in the same form it was created or decoded from.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Serialize, Clone, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+/// The intended recipients of a token.
+/// `https://datatracker.ietf.org/doc/html/rfc7519#section-4.1.3`
+pub enum Audience {
+    /// A single audience, encoded as a string.
+    Single(String),
+    /// Any number of audiences, encoded as an array of strings.
+    Multiple(Vec<String>),
+}
+
+impl Audience {
+    /// All of the audiences of this claim.
+    pub fn as_slice(&self) -> &[String] {
+        match self {
+            Audience::Single(aud) => std::slice::from_ref(aud),
+            Audience::Multiple(auds) => auds.as_slice(),
+        }
+    }
+
+    /// Returns true if this audience is one of the audiences of this claim.
+    pub fn contains(&self, aud: &str) -> bool {
+        self.as_slice().iter().any(|a| a == aud)
+    }
+
+    /// Returns true if this claim lists more than one audience.
+    pub fn is_multiple(&self) -> bool {
+        self.as_slice().len() > 1
+    }
+}
+
+impl fmt::Display for Audience {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_slice().join(" "))
+    }
+}
+
+impl From<&str> for Audience {
+    fn from(aud: &str) -> Self {
+        Audience::Single(aud.to_string())
+    }
+}
+
+impl From<String> for Audience {
+    fn from(aud: String) -> Self {
+        Audience::Single(aud)
+    }
+}
+
+impl From<Vec<String>> for Audience {
+    fn from(auds: Vec<String>) -> Self {
+        Audience::Multiple(auds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::audience::Audience;
+
+    #[test]
+    fn test_audience_forms() {
+        let aud: Audience = serde_json::from_str("\"client\"").expect("Invalid audience");
+        assert!(aud == Audience::Single("client".to_string()));
+        assert!(aud.contains("client"));
+        assert!(!aud.contains("other"));
+        assert!(!aud.is_multiple());
+        assert!(serde_json::to_string(&aud).unwrap() == "\"client\"");
+
+        let aud: Audience =
+            serde_json::from_str("[\"client\",\"other\"]").expect("Invalid audience");
+        assert!(aud.contains("client"));
+        assert!(aud.contains("other"));
+        assert!(!aud.contains("third"));
+        assert!(aud.is_multiple());
+        assert!(serde_json::to_string(&aud).unwrap() == "[\"client\",\"other\"]");
+
+        // A single element array stays an array.
+        let aud: Audience = serde_json::from_str("[\"client\"]").expect("Invalid audience");
+        assert!(!aud.is_multiple());
+        assert!(serde_json::to_string(&aud).unwrap() == "[\"client\"]");
+
+        assert!(serde_json::from_str::<Audience>("1").is_err());
+    }
+}
@@ -0,0 +1,139 @@
+//! Sources of the current time for validating the time claims of a token. The system
+//! clock is used in production, while fixed and offset clocks allow edge cases of
+//! expiry and not-before to be tested deterministically.
+
+use crate::numeric_date::NumericDate;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::{Duration, SystemTime};
+
+/// A source of the current time.
+pub trait Clock {
+    /// The current time.
+    fn now(&self) -> NumericDate;
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> NumericDate {
+        (**self).now()
+    }
+}
+
+/// A fixed time, as seconds since the unix epoch.
+impl Clock for i64 {
+    fn now(&self) -> NumericDate {
+        NumericDate::from_secs(*self)
+    }
+}
+
+/// A fixed time.
+impl Clock for NumericDate {
+    fn now(&self) -> NumericDate {
+        *self
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// The current system time.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> NumericDate {
+        NumericDate::from(SystemTime::now())
+    }
+}
+
+#[derive(Debug, Default)]
+/// A clock that only changes when it is told to. Useful for tests.
+pub struct FixedClock {
+    now: AtomicI64,
+}
+
+impl FixedClock {
+    /// Create a clock that is fixed at this time.
+    pub fn new(now: NumericDate) -> Self {
+        FixedClock {
+            now: AtomicI64::new(now.as_secs()),
+        }
+    }
+
+    /// Set the time of this clock.
+    pub fn set(&self, now: NumericDate) {
+        self.now.store(now.as_secs(), Ordering::SeqCst);
+    }
+
+    /// Move the time of this clock forward.
+    pub fn advance(&self, by: Duration) {
+        let by = i64::try_from(by.as_secs()).unwrap_or(i64::MAX);
+        let _ = self
+            .now
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |now| {
+                Some(now.saturating_add(by))
+            });
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> NumericDate {
+        NumericDate::from_secs(self.now.load(Ordering::SeqCst))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// The current system time, shifted by a number of seconds. Useful to simulate clock
+/// skew between an issuer and a validator.
+pub struct OffsetClock {
+    offset: i64,
+}
+
+impl OffsetClock {
+    /// A clock that is ahead of the system time.
+    pub fn ahead(by: Duration) -> Self {
+        OffsetClock {
+            offset: i64::try_from(by.as_secs()).unwrap_or(i64::MAX),
+        }
+    }
+
+    /// A clock that is behind the system time.
+    pub fn behind(by: Duration) -> Self {
+        OffsetClock {
+            offset: i64::try_from(by.as_secs())
+                .unwrap_or(i64::MAX)
+                .saturating_neg(),
+        }
+    }
+}
+
+impl Clock for OffsetClock {
+    fn now(&self) -> NumericDate {
+        NumericDate::from_secs(SystemClock.now().as_secs().saturating_add(self.offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::{Clock, FixedClock, OffsetClock, SystemClock};
+    use crate::numeric_date::NumericDate;
+    use std::time::Duration;
+
+    #[test]
+    fn test_clocks() {
+        let clock = FixedClock::new(NumericDate::from_secs(100));
+        assert!(clock.now().as_secs() == 100);
+        clock.advance(Duration::from_secs(50));
+        assert!(clock.now().as_secs() == 150);
+        clock.set(NumericDate::from_secs(10));
+        assert!(clock.now().as_secs() == 10);
+
+        assert!(42.now().as_secs() == 42);
+
+        let now = SystemClock.now().as_secs();
+        let ahead = OffsetClock::ahead(Duration::from_secs(3600))
+            .now()
+            .as_secs();
+        let behind = OffsetClock::behind(Duration::from_secs(3600))
+            .now()
+            .as_secs();
+        assert!(ahead >= now + 3600 && ahead <= now + 3601);
+        assert!(behind >= now - 3600 && behind <= now - 3599);
+    }
+}
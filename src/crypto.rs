@@ -1,17 +1,20 @@
 //! JWS Cryptographic Operations
 
-use openssl::{bn, ec, ecdsa, hash, nid, pkey, rand, rsa, sign};
+use openssl::{bn, ec, ecdsa, hash, memcmp, nid, pkey, rand, rsa, sign};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
 use std::fmt;
 use std::str::FromStr;
+use std::sync::Arc;
 use url::Url;
 
 use crate::base64_data::Base64UrlSafeData;
 use crate::error::JwtError;
+use crate::jwe::JweAlg;
 
 const RSA_MIN_SIZE: u32 = 3072;
-const RSA_SIG_SIZE: i32 = 384;
 
 // https://datatracker.ietf.org/doc/html/rfc7515
 
@@ -29,12 +32,75 @@ pub enum EcCurve {
     #[serde(rename = "P-256")]
     /// Nist P-256
     P256,
+    #[serde(rename = "P-384")]
+    /// Nist P-384
+    P384,
+    #[serde(rename = "P-521")]
+    /// Nist P-521
+    P521,
+}
+
+impl EcCurve {
+    pub(crate) fn to_nid(&self) -> nid::Nid {
+        match self {
+            EcCurve::P256 => nid::Nid::X9_62_PRIME256V1,
+            EcCurve::P384 => nid::Nid::SECP384R1,
+            EcCurve::P521 => nid::Nid::SECP521R1,
+        }
+    }
+
+    fn from_nid(curve: nid::Nid) -> Option<Self> {
+        match curve {
+            nid::Nid::X9_62_PRIME256V1 => Some(EcCurve::P256),
+            nid::Nid::SECP384R1 => Some(EcCurve::P384),
+            nid::Nid::SECP521R1 => Some(EcCurve::P521),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Deserialize, PartialEq)]
+/// Valid Octet Key Pair Curves
+/// https://datatracker.ietf.org/doc/html/rfc8037#section-2
+pub enum OkpCurve {
+    /// Edwards Curve 25519
+    Ed25519,
+    /// Edwards Curve 448
+    Ed448,
+    /// Curve25519 for key agreement. This can not be used for signing.
+    X25519,
+    /// Curve448 for key agreement. This can not be used for signing.
+    X448,
+}
+
+impl OkpCurve {
+    fn to_id(&self) -> pkey::Id {
+        match self {
+            OkpCurve::Ed25519 => pkey::Id::ED25519,
+            OkpCurve::Ed448 => pkey::Id::ED448,
+            OkpCurve::X25519 => pkey::Id::X25519,
+            OkpCurve::X448 => pkey::Id::X448,
+        }
+    }
+
+    fn is_signing(&self) -> bool {
+        matches!(self, OkpCurve::Ed25519 | OkpCurve::Ed448)
+    }
+
+    fn from_id(id: pkey::Id) -> Option<Self> {
+        match id {
+            pkey::Id::ED25519 => Some(OkpCurve::Ed25519),
+            pkey::Id::ED448 => Some(OkpCurve::Ed448),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Clone, Deserialize, PartialEq)]
 #[allow(non_camel_case_types)]
 #[serde(tag = "kty")]
-/// A JWK formatted public key that can be used to validate a signature
+/// A JWK formatted public key that can be used to validate a signature, or a symmetric
+/// key for encryption
 pub enum Jwk {
     /// An Eliptic Curve Public Key
     EC {
@@ -49,7 +115,23 @@ pub enum Jwk {
         // d: Base64UrlSafeData
         /// The algorithm in use for this key
         #[serde(skip_serializing_if = "Option::is_none")]
-        alg: Option<JwaAlg>,
+        alg: Option<JwkAlg>,
+        #[serde(rename = "use", skip_serializing_if = "Option::is_none")]
+        /// The usage of this key
+        use_: Option<JwkUse>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        /// The key id
+        kid: Option<String>,
+    },
+    /// An Octet Key Pair Public Key
+    OKP {
+        /// The Edwards Curve in use
+        crv: OkpCurve,
+        /// The public key
+        x: Base64UrlSafeData,
+        /// The algorithm in use for this key
+        #[serde(skip_serializing_if = "Option::is_none")]
+        alg: Option<JwkAlg>,
         #[serde(rename = "use", skip_serializing_if = "Option::is_none")]
         /// The usage of this key
         use_: Option<JwkUse>,
@@ -65,7 +147,22 @@ pub enum Jwk {
         e: Base64UrlSafeData,
         /// The algorithm in use for this key
         #[serde(skip_serializing_if = "Option::is_none")]
-        alg: Option<JwaAlg>,
+        alg: Option<JwkAlg>,
+        #[serde(rename = "use", skip_serializing_if = "Option::is_none")]
+        /// The usage of this key
+        use_: Option<JwkUse>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        /// The key id
+        kid: Option<String>,
+    },
+    /// A symmetric key. Unlike the other key types this is secret, and must never be
+    /// published in a key set.
+    oct {
+        /// The key value
+        k: Base64UrlSafeData,
+        /// The algorithm in use for this key
+        #[serde(skip_serializing_if = "Option::is_none")]
+        alg: Option<JwkAlg>,
         #[serde(rename = "use", skip_serializing_if = "Option::is_none")]
         /// The usage of this key
         use_: Option<JwkUse>,
@@ -75,6 +172,53 @@ pub enum Jwk {
     },
 }
 
+impl Jwk {
+    /// Compute the RFC 7638 thumbprint of this key with the requested digest. This is a
+    /// stable identifier of the key material, ignoring the alg, use and kid of the key.
+    /// https://datatracker.ietf.org/doc/html/rfc7638
+    pub fn thumbprint(&self, digest: hash::MessageDigest) -> Result<Base64UrlSafeData, JwtError> {
+        // Only the required members are included, and they must be in lexicographic
+        // order. BTreeMap guarantees that ordering for us.
+        let mut members: BTreeMap<&str, serde_json::Value> = BTreeMap::new();
+        match self {
+            Jwk::EC { crv, x, y, .. } => {
+                members.insert("crv", json_value(crv)?);
+                members.insert("kty", "EC".into());
+                members.insert("x", json_value(x)?);
+                members.insert("y", json_value(y)?);
+            }
+            Jwk::OKP { crv, x, .. } => {
+                members.insert("crv", json_value(crv)?);
+                members.insert("kty", "OKP".into());
+                members.insert("x", json_value(x)?);
+            }
+            Jwk::RSA { n, e, .. } => {
+                members.insert("e", json_value(e)?);
+                members.insert("kty", "RSA".into());
+                members.insert("n", json_value(n)?);
+            }
+            Jwk::oct { k, .. } => {
+                members.insert("k", json_value(k)?);
+                members.insert("kty", "oct".into());
+            }
+        }
+
+        let canonical = serde_json::to_vec(&members).map_err(|e| JwtError::InvalidJwt(e.into()))?;
+        trace!(
+            "thumbprint input -> {:?}",
+            String::from_utf8_lossy(&canonical)
+        );
+
+        hash::hash(digest, &canonical)
+            .map(|hashout| Base64UrlSafeData(hashout.to_vec()))
+            .map_err(|e| JwtError::OpenSSLError(e.into()))
+    }
+}
+
+fn json_value<T: Serialize>(value: &T) -> Result<serde_json::Value, JwtError> {
+    serde_json::to_value(value).map_err(|e| JwtError::InvalidJwt(e.into()))
+}
+
 #[derive(Debug, Serialize, Clone, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
 /// What this key is used for
@@ -85,16 +229,59 @@ pub enum JwkUse {
     Enc,
 }
 
+#[derive(Debug, Serialize, Clone, Deserialize, PartialEq)]
+#[serde(untagged)]
+/// The algorithm a JWK is intended to be used with. Keys for signing carry a signature
+/// algorithm, and keys for encryption carry a key management algorithm.
+pub enum JwkAlg {
+    /// A signature algorithm
+    Jws(JwaAlg),
+    /// A key management algorithm
+    Jwe(JweAlg),
+}
+
+impl From<JwaAlg> for JwkAlg {
+    fn from(alg: JwaAlg) -> Self {
+        JwkAlg::Jws(alg)
+    }
+}
+
+impl From<JweAlg> for JwkAlg {
+    fn from(alg: JweAlg) -> Self {
+        JwkAlg::Jwe(alg)
+    }
+}
+
 #[derive(Debug, Serialize, Clone, Deserialize, PartialEq)]
 #[allow(non_camel_case_types)]
 /// Cryptographic algorithm
 pub enum JwaAlg {
     /// ECDSA with P-256 and SHA256
     ES256,
+    /// ECDSA with P-384 and SHA384
+    ES384,
+    /// ECDSA with P-521 and SHA512
+    ES512,
     /// RSASSA-PKCS1-v1_5 with SHA-256
     RS256,
+    /// RSASSA-PKCS1-v1_5 with SHA-384
+    RS384,
+    /// RSASSA-PKCS1-v1_5 with SHA-512
+    RS512,
+    /// RSASSA-PSS with SHA-256 and MGF1 with SHA-256
+    PS256,
+    /// RSASSA-PSS with SHA-384 and MGF1 with SHA-384
+    PS384,
+    /// RSASSA-PSS with SHA-512 and MGF1 with SHA-512
+    PS512,
     /// HMAC SHA256
     HS256,
+    /// HMAC SHA384
+    HS384,
+    /// HMAC SHA512
+    HS512,
+    /// EdDSA with Ed25519 or Ed448
+    EdDSA,
 }
 
 #[derive(Clone)]
@@ -107,6 +294,20 @@ pub enum JwsSigner {
         /// The matching digest.
         digest: hash::MessageDigest,
     },
+    /// Eliptic Curve P-384
+    ES384 {
+        /// Private Key
+        skey: ec::EcKey<pkey::Private>,
+        /// The matching digest.
+        digest: hash::MessageDigest,
+    },
+    /// Eliptic Curve P-521
+    ES512 {
+        /// Private Key
+        skey: ec::EcKey<pkey::Private>,
+        /// The matching digest.
+        digest: hash::MessageDigest,
+    },
     /// RSASSA-PKCS1-v1_5 with SHA-256
     RS256 {
         /// Private Key
@@ -114,6 +315,41 @@ pub enum JwsSigner {
         /// The matching digest.
         digest: hash::MessageDigest,
     },
+    /// RSASSA-PKCS1-v1_5 with SHA-384
+    RS384 {
+        /// Private Key
+        skey: rsa::Rsa<pkey::Private>,
+        /// The matching digest.
+        digest: hash::MessageDigest,
+    },
+    /// RSASSA-PKCS1-v1_5 with SHA-512
+    RS512 {
+        /// Private Key
+        skey: rsa::Rsa<pkey::Private>,
+        /// The matching digest.
+        digest: hash::MessageDigest,
+    },
+    /// RSASSA-PSS with SHA-256
+    PS256 {
+        /// Private Key
+        skey: rsa::Rsa<pkey::Private>,
+        /// The matching digest.
+        digest: hash::MessageDigest,
+    },
+    /// RSASSA-PSS with SHA-384
+    PS384 {
+        /// Private Key
+        skey: rsa::Rsa<pkey::Private>,
+        /// The matching digest.
+        digest: hash::MessageDigest,
+    },
+    /// RSASSA-PSS with SHA-512
+    PS512 {
+        /// Private Key
+        skey: rsa::Rsa<pkey::Private>,
+        /// The matching digest.
+        digest: hash::MessageDigest,
+    },
     /// HMAC SHA256
     HS256 {
         /// Private Key
@@ -121,6 +357,25 @@ pub enum JwsSigner {
         /// The matching digest
         digest: hash::MessageDigest,
     },
+    /// HMAC SHA384
+    HS384 {
+        /// Private Key
+        skey: pkey::PKey<pkey::Private>,
+        /// The matching digest
+        digest: hash::MessageDigest,
+    },
+    /// HMAC SHA512
+    HS512 {
+        /// Private Key
+        skey: pkey::PKey<pkey::Private>,
+        /// The matching digest
+        digest: hash::MessageDigest,
+    },
+    /// Edwards Curve Ed25519 or Ed448. EdDSA does not use a separate digest.
+    EdDSA {
+        /// Private Key
+        skey: pkey::PKey<pkey::Private>,
+    },
 }
 
 #[derive(Clone)]
@@ -133,6 +388,20 @@ pub enum JwsValidator {
         /// The matching digest.
         digest: hash::MessageDigest,
     },
+    /// Eliptic Curve P-384
+    ES384 {
+        /// Public Key
+        pkey: ec::EcKey<pkey::Public>,
+        /// The matching digest.
+        digest: hash::MessageDigest,
+    },
+    /// Eliptic Curve P-521
+    ES512 {
+        /// Public Key
+        pkey: ec::EcKey<pkey::Public>,
+        /// The matching digest.
+        digest: hash::MessageDigest,
+    },
     /// RSASSA-PKCS1-v1_5 with SHA-256
     RS256 {
         /// Public Key
@@ -140,6 +409,41 @@ pub enum JwsValidator {
         /// The matching digest.
         digest: hash::MessageDigest,
     },
+    /// RSASSA-PKCS1-v1_5 with SHA-384
+    RS384 {
+        /// Public Key
+        pkey: rsa::Rsa<pkey::Public>,
+        /// The matching digest.
+        digest: hash::MessageDigest,
+    },
+    /// RSASSA-PKCS1-v1_5 with SHA-512
+    RS512 {
+        /// Public Key
+        pkey: rsa::Rsa<pkey::Public>,
+        /// The matching digest.
+        digest: hash::MessageDigest,
+    },
+    /// RSASSA-PSS with SHA-256
+    PS256 {
+        /// Public Key
+        pkey: rsa::Rsa<pkey::Public>,
+        /// The matching digest.
+        digest: hash::MessageDigest,
+    },
+    /// RSASSA-PSS with SHA-384
+    PS384 {
+        /// Public Key
+        pkey: rsa::Rsa<pkey::Public>,
+        /// The matching digest.
+        digest: hash::MessageDigest,
+    },
+    /// RSASSA-PSS with SHA-512
+    PS512 {
+        /// Public Key
+        pkey: rsa::Rsa<pkey::Public>,
+        /// The matching digest.
+        digest: hash::MessageDigest,
+    },
     /// HMAC SHA256
     HS256 {
         /// Private Key (Yes, this is correct)
@@ -147,6 +451,25 @@ pub enum JwsValidator {
         /// The matching digest.
         digest: hash::MessageDigest,
     },
+    /// HMAC SHA384
+    HS384 {
+        /// Private Key (Yes, this is correct)
+        skey: pkey::PKey<pkey::Private>,
+        /// The matching digest.
+        digest: hash::MessageDigest,
+    },
+    /// HMAC SHA512
+    HS512 {
+        /// Private Key (Yes, this is correct)
+        skey: pkey::PKey<pkey::Private>,
+        /// The matching digest.
+        digest: hash::MessageDigest,
+    },
+    /// Edwards Curve Ed25519 or Ed448
+    EdDSA {
+        /// Public Key
+        pkey: pkey::PKey<pkey::Public>,
+    },
 }
 
 impl fmt::Debug for JwsValidator {
@@ -155,9 +478,217 @@ impl fmt::Debug for JwsValidator {
     }
 }
 
+impl JwsValidator {
+    fn alg(&self) -> Result<JwaAlg, JwtError> {
+        match self {
+            JwsValidator::ES256 { pkey, digest }
+            | JwsValidator::ES384 { pkey, digest }
+            | JwsValidator::ES512 { pkey, digest } => ecdsa_alg(pkey.group(), digest),
+            JwsValidator::RS256 { pkey: _, digest }
+            | JwsValidator::RS384 { pkey: _, digest }
+            | JwsValidator::RS512 { pkey: _, digest } => rsa_pkcs1_alg(digest),
+            JwsValidator::PS256 { pkey: _, digest }
+            | JwsValidator::PS384 { pkey: _, digest }
+            | JwsValidator::PS512 { pkey: _, digest } => rsa_pss_alg(digest),
+            JwsValidator::HS256 { skey: _, digest }
+            | JwsValidator::HS384 { skey: _, digest }
+            | JwsValidator::HS512 { skey: _, digest } => hmac_alg(digest),
+            JwsValidator::EdDSA { pkey: _ } => Ok(JwaAlg::EdDSA),
+        }
+    }
+
+    /// Compute the OpenID Connect at_hash or c_hash of this access token or authorization
+    /// code, using the hash function of this validator's algorithm.
+    pub fn oidc_token_hash(&self, value: &str) -> Result<String, JwtError> {
+        let digest = match self {
+            JwsValidator::ES256 { digest, .. }
+            | JwsValidator::ES384 { digest, .. }
+            | JwsValidator::ES512 { digest, .. }
+            | JwsValidator::RS256 { digest, .. }
+            | JwsValidator::RS384 { digest, .. }
+            | JwsValidator::RS512 { digest, .. }
+            | JwsValidator::PS256 { digest, .. }
+            | JwsValidator::PS384 { digest, .. }
+            | JwsValidator::PS512 { digest, .. }
+            | JwsValidator::HS256 { digest, .. }
+            | JwsValidator::HS384 { digest, .. }
+            | JwsValidator::HS512 { digest, .. } => hash_value(*digest, value.as_bytes())?,
+            JwsValidator::EdDSA { pkey } => eddsa_hash_value(pkey.id(), value.as_bytes())?,
+        };
+        Ok(oidc_left_half(digest))
+    }
+}
+
+fn ecdsa_alg(ec_group: &ec::EcGroupRef, digest: &hash::MessageDigest) -> Result<JwaAlg, JwtError> {
+    // For ECDSA the curve and the digest are fixed together by the alg.
+    match (ec_group.curve_name(), digest.type_()) {
+        (Some(nid::Nid::X9_62_PRIME256V1), nid::Nid::SHA256) => Ok(JwaAlg::ES256),
+        (Some(nid::Nid::SECP384R1), nid::Nid::SHA384) => Ok(JwaAlg::ES384),
+        (Some(nid::Nid::SECP521R1), nid::Nid::SHA512) => Ok(JwaAlg::ES512),
+        _ => Err(JwtError::ValidatorAlgMismatch),
+    }
+}
+
+fn rsa_pkcs1_alg(digest: &hash::MessageDigest) -> Result<JwaAlg, JwtError> {
+    match digest.type_() {
+        nid::Nid::SHA256 => Ok(JwaAlg::RS256),
+        nid::Nid::SHA384 => Ok(JwaAlg::RS384),
+        nid::Nid::SHA512 => Ok(JwaAlg::RS512),
+        _ => Err(JwtError::ValidatorAlgMismatch),
+    }
+}
+
+fn rsa_pss_alg(digest: &hash::MessageDigest) -> Result<JwaAlg, JwtError> {
+    match digest.type_() {
+        nid::Nid::SHA256 => Ok(JwaAlg::PS256),
+        nid::Nid::SHA384 => Ok(JwaAlg::PS384),
+        nid::Nid::SHA512 => Ok(JwaAlg::PS512),
+        _ => Err(JwtError::ValidatorAlgMismatch),
+    }
+}
+
+fn hmac_alg(digest: &hash::MessageDigest) -> Result<JwaAlg, JwtError> {
+    match digest.type_() {
+        nid::Nid::SHA256 => Ok(JwaAlg::HS256),
+        nid::Nid::SHA384 => Ok(JwaAlg::HS384),
+        nid::Nid::SHA512 => Ok(JwaAlg::HS512),
+        _ => Err(JwtError::ValidatorAlgMismatch),
+    }
+}
+
+fn hash_value(digest: hash::MessageDigest, value: &[u8]) -> Result<Vec<u8>, JwtError> {
+    hash::hash(digest, value)
+        .map(|d| d.to_vec())
+        .map_err(|e| JwtError::OpenSSLError(e.into()))
+}
+
+fn eddsa_hash_value(id: pkey::Id, value: &[u8]) -> Result<Vec<u8>, JwtError> {
+    // OpenID Connect uses SHA-512 for Ed25519, and SHAKE256 with a 114 byte output for Ed448.
+    match OkpCurve::from_id(id) {
+        Some(OkpCurve::Ed25519) => hash_value(hash::MessageDigest::sha512(), value),
+        Some(OkpCurve::Ed448) => {
+            let mut buf = vec![0; 114];
+            hash::hash_xof(hash::MessageDigest::shake_256(), value, &mut buf)
+                .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+            Ok(buf)
+        }
+        _ => Err(JwtError::InvalidKey),
+    }
+}
+
+fn oidc_left_half(mut digest: Vec<u8>) -> String {
+    // The base64url encoding of the left-most half of the hash.
+    // https://openid.net/specs/openid-connect-core-1_0.html#CodeIDToken
+    digest.truncate(digest.len() / 2);
+    Base64UrlSafeData(digest).to_string()
+}
+
+/// The size in bytes of a single field element (and so of each of r, s, x and y) on this curve.
+pub(crate) fn ec_field_size(ec_group: &ec::EcGroupRef) -> usize {
+    ec_group.degree().div_ceil(8) as usize
+}
+
+/// Left pad a big endian integer to the fixed width required by JWS and JWK.
+pub(crate) fn bn_to_fixed_width(bn: &bn::BigNumRef, width: usize) -> Result<Vec<u8>, JwtError> {
+    bn.to_vec_padded(width as i32)
+        .map_err(|e| JwtError::OpenSSLError(e.into()))
+}
+
+/// Load an EC private key from DER, which must be on this curve so that it can't be used
+/// with the digest of another alg.
+pub(crate) fn ec_key_from_der(
+    der: &[u8],
+    curve: EcCurve,
+) -> Result<ec::EcKey<pkey::Private>, JwtError> {
+    let skey =
+        ec::EcKey::private_key_from_der(der).map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+    if skey.group().curve_name() != Some(curve.to_nid()) {
+        return Err(JwtError::InvalidKey);
+    }
+
+    skey.check_key()
+        .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+    Ok(skey)
+}
+
+pub(crate) fn rsa_public_key(
+    skey: &rsa::Rsa<pkey::Private>,
+) -> Result<rsa::Rsa<pkey::Public>, JwtError> {
+    let n = skey
+        .n()
+        .to_owned()
+        .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+    let e = skey
+        .e()
+        .to_owned()
+        .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+    rsa::Rsa::from_public_components(n, e).map_err(|e| JwtError::OpenSSLError(e.into()))
+}
+
+pub(crate) fn rsa_key_from_der(der: &[u8]) -> Result<rsa::Rsa<pkey::Private>, JwtError> {
+    let skey = rsa::Rsa::private_key_from_der(der).map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+    skey.check_key()
+        .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+    Ok(skey)
+}
+
+pub(crate) fn rsa_key_generate() -> Result<rsa::Rsa<pkey::Private>, JwtError> {
+    let skey = rsa::Rsa::generate(RSA_MIN_SIZE).map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+    skey.check_key()
+        .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+    Ok(skey)
+}
+
+fn okp_key_from_der(der: &[u8], curve: OkpCurve) -> Result<pkey::PKey<pkey::Private>, JwtError> {
+    let skey =
+        pkey::PKey::private_key_from_der(der).map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+    if skey.id() != curve.to_id() {
+        return Err(JwtError::InvalidKey);
+    }
+
+    Ok(skey)
+}
+
+fn hmac_key_generate(size: usize) -> Result<pkey::PKey<pkey::Private>, JwtError> {
+    // The key must be at least the size of the digest output.
+    // https://datatracker.ietf.org/doc/html/rfc7518#section-3.2
+    let mut buf = vec![0; size];
+    rand::rand_bytes(&mut buf).map_err(|e| {
+        error!("{:?}", e);
+        JwtError::OpenSSLError(e.into())
+    })?;
+
+    // Can it become a pkey?
+    pkey::PKey::hmac(&buf).map_err(|e| {
+        error!("{:?}", e);
+        JwtError::OpenSSLError(e.into())
+    })
+}
+
+pub(crate) fn ec_key_generate(curve: EcCurve) -> Result<ec::EcKey<pkey::Private>, JwtError> {
+    let ec_group = ec::EcGroup::from_curve_name(curve.to_nid())
+        .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+    let skey = ec::EcKey::generate(&ec_group).map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+    skey.check_key()
+        .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+    Ok(skey)
+}
+
 #[derive(Debug, Serialize, Clone, Deserialize)]
-struct ProtectedHeader {
+/// The protected header of a token, which may be inspected before the token is validated,
+/// for example to select the key or policy to validate it with. None of these values are
+/// trustworthy until the token is validated.
+pub struct ProtectedHeader {
     alg: JwaAlg,
+    // https://datatracker.ietf.org/doc/html/rfc7797
+    #[serde(skip_serializing_if = "Option::is_none")]
+    b64: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     jku: Option<Url>,
     // https://datatracker.ietf.org/doc/html/rfc7517
@@ -177,15 +708,70 @@ struct ProtectedHeader {
     x5u: Option<()>,
     #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
     x5c: Option<()>,
-    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
-    x5t: Option<()>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    x5t: Option<Base64UrlSafeData>,
     #[serde(
         skip_deserializing,
         rename = "x5t#S256",
         skip_serializing_if = "Option::is_none"
     )]
     x5t_s256: Option<()>,
-    // Don't allow extra header names?
+
+    // Any other header parameters.
+    #[serde(flatten)]
+    extra: BTreeMap<String, serde_json::Value>,
+}
+
+impl ProtectedHeader {
+    /// The algorithm (alg) the token is signed with.
+    pub fn alg(&self) -> &JwaAlg {
+        &self.alg
+    }
+
+    /// The media type (typ) of the token.
+    pub fn typ(&self) -> Option<&str> {
+        self.typ.as_deref()
+    }
+
+    /// The content type (cty) of the payload.
+    pub fn cty(&self) -> Option<&str> {
+        self.cty.as_deref()
+    }
+
+    /// The key id (kid) of the key the token is signed with.
+    pub fn kid(&self) -> Option<&str> {
+        self.kid.as_deref()
+    }
+
+    /// The URL (jku) of a key set containing the key the token is signed with.
+    pub fn jku(&self) -> Option<&Url> {
+        self.jku.as_ref()
+    }
+
+    /// The public key (jwk) the token is signed with.
+    pub fn jwk(&self) -> Option<&Jwk> {
+        self.jwk.as_ref()
+    }
+
+    /// The SHA-1 thumbprint (x5t) of the certificate of the key the token is signed with.
+    pub fn x5t(&self) -> Option<&Base64UrlSafeData> {
+        self.x5t.as_ref()
+    }
+
+    /// If the payload is base64url encoded (b64). This is false for an unencoded payload.
+    pub fn b64(&self) -> bool {
+        self.b64.unwrap_or(true)
+    }
+
+    /// The critical header parameters (crit) that must be understood.
+    pub fn crit(&self) -> &[String] {
+        self.crit.as_deref().unwrap_or_default()
+    }
+
+    /// Any other header parameters, by name.
+    pub fn extra(&self) -> &BTreeMap<String, serde_json::Value> {
+        &self.extra
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -194,48 +780,212 @@ pub(crate) struct JwsCompact {
     payload: Vec<u8>,
     sign_input: Vec<u8>,
     signature: Vec<u8>,
+    detached: bool,
 }
 
-#[derive(Debug, Clone)]
-struct Header {
+// Header parameters that are set by the signer, or that have their own setter.
+// https://datatracker.ietf.org/doc/html/rfc7515#section-4.1
+const REGISTERED_HEADER_PARAMETERS: &[&str] = &[
+    "alg", "jku", "jwk", "kid", "x5u", "x5c", "x5t", "x5t#S256", "typ", "cty", "crit",
+];
+
+#[derive(Debug, Clone, Default)]
+/// The header parameters to sign a token with. The alg is always derived from the signer.
+pub struct JwsHeader {
     kid: Option<String>,
     typ: Option<String>,
     cty: Option<String>,
+    jku: Option<Url>,
+    x5t: Option<Base64UrlSafeData>,
+    unencoded: bool,
+    crit: Vec<String>,
+    extra: BTreeMap<String, serde_json::Value>,
 }
 
-impl From<&ProtectedHeader> for Header {
-    fn from(phdr: &ProtectedHeader) -> Self {
-        Header {
-            kid: phdr.kid.clone(),
-            typ: phdr.typ.clone(),
-            cty: phdr.cty.clone(),
-        }
+impl JwsHeader {
+    /// Create an empty header.
+    pub fn new() -> Self {
+        JwsHeader::default()
     }
-}
 
-#[derive(Debug, Clone)]
-pub(crate) struct JwsInner {
-    header: Header,
-    payload: Vec<u8>,
-}
+    /// Set the key id (kid) of the signing key.
+    pub fn set_kid(mut self, kid: &str) -> Self {
+        self.kid = Some(kid.to_string());
+        self
+    }
 
-impl JwsInner {
-    pub fn new(payload: Vec<u8>) -> Self {
-        JwsInner {
-            header: Header {
-                kid: None,
-                typ: None,
-                cty: None,
-            },
+    /// Set the media type (typ) of the token, such as `JWT` or `at+jwt`.
+    pub fn set_typ(mut self, typ: &str) -> Self {
+        self.typ = Some(typ.to_string());
+        self
+    }
+
+    /// Set the content type (cty) of the payload.
+    pub fn set_cty(mut self, cty: &str) -> Self {
+        self.cty = Some(cty.to_string());
+        self
+    }
+
+    /// Set the URL (jku) of a key set containing the signing key.
+    pub fn set_jku(mut self, jku: Url) -> Self {
+        self.jku = Some(jku);
+        self
+    }
+
+    /// Set the SHA-1 thumbprint (x5t) of the certificate of the signing key.
+    pub fn set_x5t(mut self, thumbprint: &[u8]) -> Self {
+        self.x5t = Some(Base64UrlSafeData(thumbprint.to_vec()));
+        self
+    }
+
+    /// Set any other header parameter. Registered header parameters must be set with
+    /// their own setter, and will be rejected when signing.
+    pub fn set_extra(mut self, name: &str, value: serde_json::Value) -> Self {
+        self.extra.insert(name.to_string(), value);
+        self
+    }
+
+    /// Sign the payload without base64url encoding it (b64=false). In compact form an
+    /// attached payload must not contain a period.
+    /// `https://datatracker.ietf.org/doc/html/rfc7797`
+    pub fn set_unencoded_payload(mut self) -> Self {
+        self.unencoded = true;
+        if !self.crit.iter().any(|c| c == "b64") {
+            self.crit.push("b64".to_string());
+        }
+        self
+    }
+
+    /// Set a header parameter that must be understood by the recipient of the token. The
+    /// name is listed in the crit header parameter.
+    pub fn set_critical(mut self, name: &str, value: serde_json::Value) -> Self {
+        if !self.crit.iter().any(|c| c == name) {
+            self.crit.push(name.to_string());
+        }
+        self.set_extra(name, value)
+    }
+}
+
+/// A handler that validates the value of a critical header parameter.
+pub type CriticalHandler = Arc<dyn Fn(&serde_json::Value) -> Result<(), JwtError> + Send + Sync>;
+
+#[derive(Clone, Default)]
+/// The critical header parameters (crit) that are understood when parsing a token. A
+/// token is only accepted if every critical parameter it lists is understood, is present,
+/// and is accepted by its handler.
+/// `https://datatracker.ietf.org/doc/html/rfc7515#section-4.1.11`
+pub struct CriticalExtensions {
+    handlers: BTreeMap<String, CriticalHandler>,
+}
+
+impl fmt::Debug for CriticalExtensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.handlers.keys()).finish()
+    }
+}
+
+impl CriticalExtensions {
+    /// Create a set of critical extensions that understands no parameters.
+    pub fn new() -> Self {
+        CriticalExtensions::default()
+    }
+
+    /// Understand this critical header parameter, which is accepted if the handler returns
+    /// Ok for its value. Registered header parameters can not be critical extensions.
+    pub fn register<F>(mut self, name: &str, handler: F) -> Self
+    where
+        F: Fn(&serde_json::Value) -> Result<(), JwtError> + Send + Sync + 'static,
+    {
+        self.handlers.insert(name.to_string(), Arc::new(handler));
+        self
+    }
+
+    fn check(&self, header: &ProtectedHeader) -> Result<(), JwtError> {
+        // b64 is always understood, but must be listed as critical.
+        // https://datatracker.ietf.org/doc/html/rfc7797#section-6
+        if header.b64.is_some() && !header.crit().iter().any(|c| c == "b64") {
+            return Err(JwtError::InvalidHeaderParameter {
+                name: "b64".to_string(),
+            });
+        }
+
+        for name in header.crit() {
+            if name == "b64" {
+                if header.b64.is_none() {
+                    return Err(JwtError::CriticalExtensionMissing { name: name.clone() });
+                }
+                continue;
+            }
+
+            // Remember, anything in rfc7515 can NOT be in the crit field.
+            if REGISTERED_HEADER_PARAMETERS.contains(&name.as_str()) {
+                return Err(JwtError::CriticalExtension { name: name.clone() });
+            }
+
+            let handler = self
+                .handlers
+                .get(name)
+                .ok_or_else(|| JwtError::CriticalExtension { name: name.clone() })?;
+
+            let value = header
+                .extra
+                .get(name)
+                .ok_or_else(|| JwtError::CriticalExtensionMissing { name: name.clone() })?;
+
+            handler(value)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<&ProtectedHeader> for JwsHeader {
+    fn from(phdr: &ProtectedHeader) -> Self {
+        JwsHeader {
+            kid: phdr.kid.clone(),
+            typ: phdr.typ.clone(),
+            cty: phdr.cty.clone(),
+            jku: phdr.jku.clone(),
+            x5t: phdr.x5t.clone(),
+            unencoded: !phdr.b64(),
+            crit: phdr.crit().to_vec(),
+            // Registered parameters we don't model (x5u, x5c, x5t#S256) are left in extra by
+            // the parser, but can't be signed again from there.
+            extra: phdr
+                .extra
+                .iter()
+                .filter(|(name, _)| !REGISTERED_HEADER_PARAMETERS.contains(&name.as_str()))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct JwsInner {
+    header: JwsHeader,
+    payload: Vec<u8>,
+}
+
+impl JwsInner {
+    pub fn new(payload: Vec<u8>) -> Self {
+        JwsInner {
+            header: JwsHeader::default(),
             payload,
         }
     }
 
+    pub fn set_header(mut self, header: JwsHeader) -> Self {
+        self.header = header;
+        self
+    }
+
+    #[cfg(test)]
     pub fn set_kid(mut self, kid: String) -> Self {
         self.header.kid = Some(kid);
         self
     }
 
+    #[cfg(test)]
     pub fn set_typ(mut self, typ: String) -> Self {
         self.header.typ = Some(typ);
         self
@@ -250,99 +1000,183 @@ impl JwsInner {
     #[cfg(test)]
     pub fn sign_embed_public_jwk(&self, signer: &JwsSigner) -> Result<JwsCompact, JwtError> {
         let jwk = signer.public_key_as_jwk(None)?;
-        self.sign_inner(signer, None, Some(jwk))
+        self.sign_inner(signer, Some(jwk))
     }
 
     #[cfg(test)]
     pub fn sign(&self, signer: &JwsSigner) -> Result<JwsCompact, JwtError> {
-        self.sign_inner(signer, None, None)
+        self.sign_inner(signer, None)
+    }
+
+    #[cfg(test)]
+    pub fn sign_detached(&self, signer: &JwsSigner) -> Result<JwsCompact, JwtError> {
+        self.sign_detached_inner(signer, None)
     }
 
     pub(crate) fn sign_inner(
         &self,
         signer: &JwsSigner,
-        jku: Option<Url>,
         jwk: Option<Jwk>,
     ) -> Result<JwsCompact, JwtError> {
-        let alg = match signer {
-            JwsSigner::ES256 { skey: _, digest: _ } => JwaAlg::ES256,
-            JwsSigner::RS256 { skey: _, digest: _ } => JwaAlg::RS256,
-            JwsSigner::HS256 { skey: _, digest: _ } => JwaAlg::HS256,
-        };
+        self.sign_compact(signer, jwk, false)
+    }
+
+    /// Sign this payload, leaving it out of the compact form.
+    /// `https://datatracker.ietf.org/doc/html/rfc7515#appendix-F`
+    pub(crate) fn sign_detached_inner(
+        &self,
+        signer: &JwsSigner,
+        jwk: Option<Jwk>,
+    ) -> Result<JwsCompact, JwtError> {
+        self.sign_compact(signer, jwk, true)
+    }
+
+    fn sign_compact(
+        &self,
+        signer: &JwsSigner,
+        jwk: Option<Jwk>,
+        detached: bool,
+    ) -> Result<JwsCompact, JwtError> {
+        let alg = signer.alg()?;
+
+        if let Some(name) = self.header.extra.keys().find(|name| {
+            REGISTERED_HEADER_PARAMETERS.contains(&name.as_str()) || name.as_str() == "b64"
+        }) {
+            return Err(JwtError::InvalidHeaderParameter { name: name.clone() });
+        }
 
         let header = ProtectedHeader {
             alg,
-            jku,
+            b64: if self.header.unencoded {
+                Some(false)
+            } else {
+                None
+            },
+            jku: self.header.jku.clone(),
             jwk,
             kid: self.header.kid.clone(),
             typ: self.header.typ.clone(),
             cty: self.header.cty.clone(),
-            crit: None,
+            crit: if self.header.crit.is_empty() {
+                None
+            } else {
+                Some(self.header.crit.clone())
+            },
             x5u: None,
             x5c: None,
-            x5t: None,
+            x5t: self.header.x5t.clone(),
             x5t_s256: None,
+            extra: self.header.extra.clone(),
         };
 
         let payload = self.payload.clone();
 
         let hdr_b64 = serde_json::to_vec(&header)
-            .map_err(|_| JwtError::InvalidHeaderFormat)
+            .map_err(|e| JwtError::InvalidHeaderFormat(e.into()))
             .map(|bytes| base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD))?;
-        let payload_b64 = base64::encode_config(&self.payload, base64::URL_SAFE_NO_PAD);
-
-        // trace!("sinput -> {}", format!("{}.{}", hdr_b64, payload_b64));
+        let payload_enc = if !self.header.unencoded {
+            base64::encode_config(&self.payload, base64::URL_SAFE_NO_PAD).into_bytes()
+        } else if detached {
+            self.payload.clone()
+        } else {
+            // An unencoded payload in compact form must not be able to change the
+            // structure of the token.
+            // https://datatracker.ietf.org/doc/html/rfc7797#section-5.2
+            match std::str::from_utf8(&self.payload) {
+                Ok(p) if !p.contains('.') => self.payload.clone(),
+                _ => return Err(JwtError::InvalidCompactFormat),
+            }
+        };
 
-        let sign_input = format!("{}.{}", hdr_b64, payload_b64).as_bytes().to_vec();
+        let mut sign_input = hdr_b64.into_bytes();
+        sign_input.push(b'.');
+        sign_input.extend_from_slice(&payload_enc);
 
         trace!("sinput -> {:?}", sign_input);
 
         // Compute the signature!
         let signature = match signer {
-            JwsSigner::ES256 { skey, digest } => {
-                let hashout =
-                    hash::hash(*digest, &sign_input).map_err(|_| JwtError::OpenSSLError)?;
-                let ec_sig =
-                    ecdsa::EcdsaSig::sign(&hashout, skey).map_err(|_| JwtError::OpenSSLError)?;
-
-                let mut r = [0; 32];
-                let r_vec = ec_sig.r().to_vec();
-                let (_left, right) = r.split_at_mut(32 - r_vec.len());
-                right.copy_from_slice(r_vec.as_slice());
-                let mut s = [0; 32];
-                let s_vec = ec_sig.s().to_vec();
-                let (_left, right) = s.split_at_mut(32 - s_vec.len());
-                right.copy_from_slice(s_vec.as_slice());
+            JwsSigner::ES256 { skey, digest }
+            | JwsSigner::ES384 { skey, digest }
+            | JwsSigner::ES512 { skey, digest } => {
+                let hashout = hash::hash(*digest, &sign_input)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+                let ec_sig = ecdsa::EcdsaSig::sign(&hashout, skey)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+                // r and s are each encoded as a fixed width octet string of the curve
+                // field size. https://datatracker.ietf.org/doc/html/rfc7518#section-3.4
+                let field_size = ec_field_size(skey.group());
+                let r = bn_to_fixed_width(ec_sig.r(), field_size)?;
+                let s = bn_to_fixed_width(ec_sig.s(), field_size)?;
 
                 // trace!("r {:?}", r);
                 // trace!("s {:?}", s);
 
-                let mut signature = Vec::with_capacity(64);
+                let mut signature = Vec::with_capacity(field_size * 2);
                 signature.extend_from_slice(&r);
                 signature.extend_from_slice(&s);
                 signature
             }
-            JwsSigner::RS256 { skey, digest } => {
-                let key = pkey::PKey::from_rsa(skey.clone()).map_err(|_| JwtError::OpenSSLError)?;
+            JwsSigner::RS256 { skey, digest }
+            | JwsSigner::RS384 { skey, digest }
+            | JwsSigner::RS512 { skey, digest } => {
+                let key = pkey::PKey::from_rsa(skey.clone())
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
 
-                let mut signer =
-                    sign::Signer::new(*digest, &key).map_err(|_| JwtError::OpenSSLError)?;
+                let mut signer = sign::Signer::new(*digest, &key)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
 
                 signer
                     .set_rsa_padding(rsa::Padding::PKCS1)
-                    .map_err(|_| JwtError::OpenSSLError)?;
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+                signer
+                    .sign_oneshot_to_vec(&sign_input)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?
+            }
+            JwsSigner::PS256 { skey, digest }
+            | JwsSigner::PS384 { skey, digest }
+            | JwsSigner::PS512 { skey, digest } => {
+                let key = pkey::PKey::from_rsa(skey.clone())
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+                let mut signer = sign::Signer::new(*digest, &key)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+                // The salt is the same length as the digest, and MGF1 uses the same digest.
+                // https://datatracker.ietf.org/doc/html/rfc7518#section-3.5
+                signer
+                    .set_rsa_padding(rsa::Padding::PKCS1_PSS)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+                signer
+                    .set_rsa_pss_saltlen(sign::RsaPssSaltlen::DIGEST_LENGTH)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+                signer
+                    .set_rsa_mgf1_md(*digest)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+                signer
+                    .sign_oneshot_to_vec(&sign_input)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?
+            }
+            JwsSigner::HS256 { skey, digest }
+            | JwsSigner::HS384 { skey, digest }
+            | JwsSigner::HS512 { skey, digest } => {
+                let mut signer = sign::Signer::new(*digest, skey)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
 
                 signer
                     .sign_oneshot_to_vec(&sign_input)
-                    .map_err(|_| JwtError::OpenSSLError)?
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?
             }
-            JwsSigner::HS256 { skey, digest } => {
-                let mut signer =
-                    sign::Signer::new(*digest, &skey).map_err(|_| JwtError::OpenSSLError)?;
+            JwsSigner::EdDSA { skey } => {
+                let mut signer = sign::Signer::new_without_digest(skey)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
 
                 signer
                     .sign_oneshot_to_vec(&sign_input)
-                    .map_err(|_| JwtError::OpenSSLError)?
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?
             }
         };
 
@@ -351,6 +1185,7 @@ impl JwsInner {
             payload,
             sign_input,
             signature,
+            detached,
         })
     }
 
@@ -362,7 +1197,15 @@ impl JwsInner {
 impl JwsCompact {
     #[cfg(test)]
     fn check_vectors(&self, chk_input: &[u8], chk_sig: &[u8]) -> bool {
-        chk_input == &self.sign_input && chk_sig == &self.signature
+        chk_input == self.sign_input && chk_sig == self.signature
+    }
+
+    pub fn get_alg(&self) -> &JwaAlg {
+        &self.header.alg
+    }
+
+    pub fn header(&self) -> &ProtectedHeader {
+        &self.header
     }
 
     #[allow(dead_code)]
@@ -381,26 +1224,35 @@ impl JwsCompact {
     }
 
     pub(crate) fn validate(&self, validator: &JwsValidator) -> Result<JwsInner, JwtError> {
-        match (validator, &self.header.alg) {
-            (JwsValidator::ES256 { pkey, digest }, JwaAlg::ES256) => {
-                if self.signature.len() != 64 {
+        // The alg of the validator is derived from its key type and digest, and must be
+        // exactly what the header claims. This prevents a key being used for a different
+        // algorithm or digest than intended.
+        if validator.alg()? != self.header.alg {
+            return Err(JwtError::ValidatorAlgMismatch);
+        }
+
+        match validator {
+            JwsValidator::ES256 { pkey, digest }
+            | JwsValidator::ES384 { pkey, digest }
+            | JwsValidator::ES512 { pkey, digest } => {
+                let field_size = ec_field_size(pkey.group());
+                if self.signature.len() != field_size * 2 {
                     return Err(JwtError::InvalidSignature);
                 }
 
-                let r = bn::BigNum::from_slice(&self.signature[..32])
-                    .map_err(|_| JwtError::OpenSSLError)?;
-                let s = bn::BigNum::from_slice(&self.signature[32..64])
-                    .map_err(|_| JwtError::OpenSSLError)?;
+                let (r, s) = self.signature.split_at(field_size);
+                let r = bn::BigNum::from_slice(r).map_err(|e| JwtError::OpenSSLError(e.into()))?;
+                let s = bn::BigNum::from_slice(s).map_err(|e| JwtError::OpenSSLError(e.into()))?;
 
                 let sig = ecdsa::EcdsaSig::from_private_components(r, s)
-                    .map_err(|_| JwtError::OpenSSLError)?;
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
 
-                let hashout =
-                    hash::hash(*digest, &self.sign_input).map_err(|_| JwtError::OpenSSLError)?;
+                let hashout = hash::hash(*digest, &self.sign_input)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
 
                 if sig
                     .verify(&hashout, pkey)
-                    .map_err(|_| JwtError::OpenSSLError)?
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?
                 {
                     Ok(JwsInner {
                         header: (&self.header).into(),
@@ -410,25 +1262,67 @@ impl JwsCompact {
                     Err(JwtError::InvalidSignature)
                 }
             }
-            (JwsValidator::RS256 { pkey, digest }, JwaAlg::RS256) => {
+            JwsValidator::RS256 { pkey, digest }
+            | JwsValidator::RS384 { pkey, digest }
+            | JwsValidator::RS512 { pkey, digest } => {
                 if self.signature.len() < 256 {
                     return Err(JwtError::InvalidSignature);
                 }
 
-                let p = pkey::PKey::from_rsa(pkey.clone()).map_err(|_| JwtError::OpenSSLError)?;
+                let p = pkey::PKey::from_rsa(pkey.clone())
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
 
-                let mut verifier =
-                    sign::Verifier::new(*digest, &p).map_err(|_| JwtError::OpenSSLError)?;
+                let mut verifier = sign::Verifier::new(*digest, &p)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
                 verifier
                     .set_rsa_padding(rsa::Padding::PKCS1)
-                    .map_err(|_| JwtError::OpenSSLError)?;
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+                verifier
+                    .update(&self.sign_input)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+                verifier
+                    .verify(&self.signature)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))
+                    .and_then(|res| {
+                        if res {
+                            Ok(JwsInner {
+                                header: (&self.header).into(),
+                                payload: self.payload.clone(),
+                            })
+                        } else {
+                            Err(JwtError::InvalidSignature)
+                        }
+                    })
+            }
+            JwsValidator::PS256 { pkey, digest }
+            | JwsValidator::PS384 { pkey, digest }
+            | JwsValidator::PS512 { pkey, digest } => {
+                if self.signature.len() < 256 {
+                    return Err(JwtError::InvalidSignature);
+                }
+
+                let p = pkey::PKey::from_rsa(pkey.clone())
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+                let mut verifier = sign::Verifier::new(*digest, &p)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+                verifier
+                    .set_rsa_padding(rsa::Padding::PKCS1_PSS)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+                verifier
+                    .set_rsa_pss_saltlen(sign::RsaPssSaltlen::DIGEST_LENGTH)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+                verifier
+                    .set_rsa_mgf1_md(*digest)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
 
                 verifier
                     .update(&self.sign_input)
-                    .map_err(|_| JwtError::OpenSSLError)?;
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
                 verifier
                     .verify(&self.signature)
-                    .map_err(|_| JwtError::OpenSSLError)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))
                     .and_then(|res| {
                         if res {
                             Ok(JwsInner {
@@ -440,15 +1334,19 @@ impl JwsCompact {
                         }
                     })
             }
-            (JwsValidator::HS256 { skey, digest }, JwaAlg::HS256) => {
-                let mut signer =
-                    sign::Signer::new(*digest, &skey).map_err(|_| JwtError::OpenSSLError)?;
+            JwsValidator::HS256 { skey, digest }
+            | JwsValidator::HS384 { skey, digest }
+            | JwsValidator::HS512 { skey, digest } => {
+                let mut signer = sign::Signer::new(*digest, skey)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
 
                 let ver_sig = signer
                     .sign_oneshot_to_vec(&self.sign_input)
-                    .map_err(|_| JwtError::OpenSSLError)?;
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
 
-                if self.signature == ver_sig {
+                // The length of a mac is not secret, but the content must be compared in
+                // constant time to avoid leaking how much of the signature was correct.
+                if self.signature.len() == ver_sig.len() && memcmp::eq(&self.signature, &ver_sig) {
                     Ok(JwsInner {
                         header: (&self.header).into(),
                         payload: self.payload.clone(),
@@ -457,166 +1355,768 @@ impl JwsCompact {
                     Err(JwtError::InvalidSignature)
                 }
             }
-            _ => Err(JwtError::ValidatorAlgMismatch),
+            JwsValidator::EdDSA { pkey } => {
+                let mut verifier = sign::Verifier::new_without_digest(pkey)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+                verifier
+                    .verify_oneshot(&self.signature, &self.sign_input)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))
+                    .and_then(|res| {
+                        if res {
+                            Ok(JwsInner {
+                                header: (&self.header).into(),
+                                payload: self.payload.clone(),
+                            })
+                        } else {
+                            Err(JwtError::InvalidSignature)
+                        }
+                    })
+            }
         }
     }
-}
 
-impl FromStr for JwsCompact {
-    type Err = JwtError;
+    pub(crate) fn from_str_with_critical(
+        s: &str,
+        critical: &CriticalExtensions,
+    ) -> Result<Self, JwtError> {
+        JwsCompact::parse(s, None, critical)
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        // split on the ".".
-        let mut siter = s.splitn(3, '.');
+    /// Parse a compact jws with an empty payload section, where the payload is carried
+    /// separately.
+    pub(crate) fn from_str_detached(
+        s: &str,
+        payload: &[u8],
+        critical: &CriticalExtensions,
+    ) -> Result<Self, JwtError> {
+        JwsCompact::parse(s, Some(payload), critical)
+    }
 
-        let hdr_str = siter.next().ok_or(JwtError::InvalidCompactFormat)?;
+    fn parse(
+        s: &str,
+        detached_payload: Option<&[u8]>,
+        critical: &CriticalExtensions,
+    ) -> Result<Self, JwtError> {
+        // split on the ".", the payload may be unencoded so the signature is the last part.
+        let (hdr_str, rest) = s.split_once('.').ok_or(JwtError::InvalidCompactFormat)?;
+        let (payload_str, sig_str) = rest
+            .rsplit_once('.')
+            .ok_or(JwtError::InvalidCompactFormat)?;
+
+        if payload_str.contains('.') {
+            // Too much data.
+            return Err(JwtError::InvalidCompactFormat);
+        }
 
         let header: ProtectedHeader = base64::decode_config(hdr_str, base64::URL_SAFE_NO_PAD)
-            .map_err(|_| JwtError::InvalidBase64)
+            .map_err(|e| JwtError::InvalidBase64(e.into()))
             .and_then(|bytes| {
-                serde_json::from_slice(&bytes).map_err(|_| JwtError::InvalidHeaderFormat)
+                serde_json::from_slice(&bytes).map_err(|e| JwtError::InvalidHeaderFormat(e.into()))
             })?;
-        // Assert that from the critical field of the header, we have decoded all the needed types.
-        // Remember, anything in rfc7515 can NOT be in the crit field.
-        if let Some(crit) = &header.crit {
-            if !crit.is_empty() {
-                return Err(JwtError::CriticalExtension);
-            }
-        }
+        // Assert that from the critical field of the header, we understand all the needed
+        // types.
+        critical.check(&header)?;
 
         // Now we have a header, lets get the rest.
-        let payload_str = siter.next().ok_or(JwtError::InvalidCompactFormat)?;
-
-        let sig_str = siter.next().ok_or(JwtError::InvalidCompactFormat)?;
-
-        if siter.next().is_some() {
-            // Too much data.
-            return Err(JwtError::InvalidCompactFormat);
-        }
-
-        let payload = base64::decode_config(payload_str, base64::URL_SAFE_NO_PAD)
-            .map_err(|_| JwtError::InvalidBase64)?;
+        let (payload, payload_enc) = match detached_payload {
+            Some(payload) => {
+                if !payload_str.is_empty() {
+                    return Err(JwtError::InvalidCompactFormat);
+                }
+                let payload_enc = if header.b64() {
+                    base64::encode_config(payload, base64::URL_SAFE_NO_PAD).into_bytes()
+                } else {
+                    payload.to_vec()
+                };
+                (payload.to_vec(), payload_enc)
+            }
+            None => {
+                let payload = if header.b64() {
+                    base64::decode_config(payload_str, base64::URL_SAFE_NO_PAD)
+                        .map_err(|e| JwtError::InvalidBase64(e.into()))?
+                } else {
+                    payload_str.as_bytes().to_vec()
+                };
+                (payload, payload_str.as_bytes().to_vec())
+            }
+        };
 
         let signature = base64::decode_config(sig_str, base64::URL_SAFE_NO_PAD)
-            .map_err(|_| JwtError::InvalidBase64)?;
-
-        let (data_input, _) = s.rsplit_once(".").ok_or(JwtError::InvalidCompactFormat)?;
-        let sign_input = data_input.as_bytes().to_vec();
+            .map_err(|e| JwtError::InvalidBase64(e.into()))?;
 
-        debug_assert!(data_input == &format!("{}.{}", hdr_str, payload_str));
+        let mut sign_input = hdr_str.as_bytes().to_vec();
+        sign_input.push(b'.');
+        sign_input.extend_from_slice(&payload_enc);
 
         Ok(JwsCompact {
             header,
             payload,
             sign_input,
             signature,
+            detached: detached_payload.is_some(),
         })
     }
 }
 
+impl FromStr for JwsCompact {
+    type Err = JwtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        JwsCompact::from_str_with_critical(s, &CriticalExtensions::default())
+    }
+}
+
 impl fmt::Display for JwsCompact {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let hdr = serde_json::to_vec(&self.header)
-            .map_err(|_| fmt::Error)
-            .map(|bytes| base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD))?;
-        let payload = base64::encode_config(&self.payload, base64::URL_SAFE_NO_PAD);
+        // The header and payload are written exactly as they were signed, since
+        // re-serialising the header may not reproduce the same bytes.
         let sig = base64::encode_config(&self.signature, base64::URL_SAFE_NO_PAD);
-        write!(f, "{}.{}.{}", hdr, payload, sig)
+        if self.detached {
+            let hdr = self
+                .sign_input
+                .split(|b| *b == b'.')
+                .next()
+                .and_then(|hdr| std::str::from_utf8(hdr).ok())
+                .ok_or(fmt::Error)?;
+            write!(f, "{}..{}", hdr, sig)
+        } else {
+            let sign_input = std::str::from_utf8(&self.sign_input).map_err(|_| fmt::Error)?;
+            write!(f, "{}.{}", sign_input, sig)
+        }
     }
 }
 
-impl TryFrom<&Jwk> for JwsValidator {
-    type Error = JwtError;
+/// How the signatures of a jws in JSON serialization are validated, when it has more
+/// than one signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JwsSignaturePolicy {
+    /// At least one signature must be valid.
+    Any,
+    /// Every signature must be valid.
+    All,
+}
 
-    fn try_from(value: &Jwk) -> Result<Self, Self::Error> {
-        match value {
-            Jwk::EC {
-                crv,
-                x,
-                y,
-                alg: _,
-                use_: _,
-                kid: _,
-            } => {
-                let (curve, digest) = match crv {
-                    EcCurve::P256 => (nid::Nid::X9_62_PRIME256V1, hash::MessageDigest::sha256()),
-                };
-                let ec_group =
-                    ec::EcGroup::from_curve_name(curve).map_err(|_| JwtError::OpenSSLError)?;
+#[derive(Debug, Clone)]
+pub(crate) struct JwsJsonSignature {
+    // The union of the protected and unprotected header parameters.
+    header: ProtectedHeader,
+    // The protected header exactly as it was signed.
+    protected: String,
+    unprotected: Option<serde_json::Map<String, serde_json::Value>>,
+    signature: Vec<u8>,
+}
 
-                let xbn = bn::BigNum::from_slice(&x.0).map_err(|_| JwtError::OpenSSLError)?;
-                let ybn = bn::BigNum::from_slice(&y.0).map_err(|_| JwtError::OpenSSLError)?;
+#[derive(Debug, Clone)]
+pub(crate) struct JwsJson {
+    payload: Vec<u8>,
+    // The payload member exactly as it was signed.
+    payload_enc: String,
+    signatures: Vec<JwsJsonSignature>,
+    flattened: bool,
+}
 
-                let pkey = ec::EcKey::from_public_key_affine_coordinates(&ec_group, &xbn, &ybn)
-                    .map_err(|_| JwtError::OpenSSLError)?;
+#[derive(Serialize, Deserialize)]
+struct JwsJsonSignatureRepr {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    protected: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    header: Option<serde_json::Map<String, serde_json::Value>>,
+    signature: String,
+}
 
-                pkey.check_key().map_err(|_| JwtError::OpenSSLError)?;
+#[derive(Serialize)]
+struct JwsJsonGeneralRepr<'a> {
+    payload: &'a str,
+    signatures: Vec<JwsJsonSignatureRepr>,
+}
 
-                Ok(match crv {
-                    EcCurve::P256 => JwsValidator::ES256 { pkey, digest },
-                })
+#[derive(Serialize)]
+struct JwsJsonFlattenedRepr<'a> {
+    payload: &'a str,
+    #[serde(flatten)]
+    signature: JwsJsonSignatureRepr,
+}
+
+impl JwsJsonSignature {
+    fn from_repr(
+        repr: JwsJsonSignatureRepr,
+        critical: &CriticalExtensions,
+    ) -> Result<Self, JwtError> {
+        let mut params = if repr.protected.is_empty() {
+            serde_json::Map::new()
+        } else {
+            base64::decode_config(&repr.protected, base64::URL_SAFE_NO_PAD)
+                .map_err(|e| JwtError::InvalidBase64(e.into()))
+                .and_then(|bytes| {
+                    serde_json::from_slice(&bytes)
+                        .map_err(|e| JwtError::InvalidHeaderFormat(e.into()))
+                })?
+        };
+
+        // The header parameters may be split between the protected and unprotected
+        // headers, but must not be in both.
+        // https://datatracker.ietf.org/doc/html/rfc7515#section-7.2.1
+        if let Some(unprotected) = &repr.header {
+            for (name, value) in unprotected {
+                // These change how the jws is processed, so they must be protected.
+                if name == "crit" || name == "b64" || params.contains_key(name) {
+                    return Err(JwtError::InvalidHeaderParameter { name: name.clone() });
+                }
+                params.insert(name.clone(), value.clone());
             }
-            Jwk::RSA {
-                n,
-                e,
-                alg: _,
-                use_: _,
-                kid: _,
-            } => {
-                let digest = hash::MessageDigest::sha256();
+        }
 
-                let nbn = bn::BigNum::from_slice(&n.0).map_err(|_| JwtError::OpenSSLError)?;
-                let ebn = bn::BigNum::from_slice(&e.0).map_err(|_| JwtError::OpenSSLError)?;
+        let header: ProtectedHeader = serde_json::from_value(serde_json::Value::Object(params))
+            .map_err(|e| JwtError::InvalidHeaderFormat(e.into()))?;
 
-                let pkey = rsa::Rsa::from_public_components(nbn, ebn)
-                    .map_err(|_| JwtError::OpenSSLError)?;
+        critical.check(&header)?;
 
-                Ok(JwsValidator::RS256 { pkey, digest })
-            }
+        let signature = base64::decode_config(&repr.signature, base64::URL_SAFE_NO_PAD)
+            .map_err(|e| JwtError::InvalidBase64(e.into()))?;
+
+        Ok(JwsJsonSignature {
+            header,
+            protected: repr.protected,
+            unprotected: repr.header,
+            signature,
+        })
+    }
+
+    fn to_repr(&self) -> JwsJsonSignatureRepr {
+        JwsJsonSignatureRepr {
+            protected: self.protected.clone(),
+            header: self.unprotected.clone(),
+            signature: base64::encode_config(&self.signature, base64::URL_SAFE_NO_PAD),
         }
     }
 }
 
-impl JwsSigner {
-    #[cfg(test)]
-    pub fn from_es256_jwk_components(x: &str, y: &str, d: &str) -> Result<Self, JwtError> {
-        let x = base64::decode_config(x, base64::URL_SAFE_NO_PAD)
-            .map_err(|_| JwtError::InvalidBase64)?;
-        let y = base64::decode_config(y, base64::URL_SAFE_NO_PAD)
-            .map_err(|_| JwtError::InvalidBase64)?;
-
-        let d = base64::decode_config(&d, base64::URL_SAFE_NO_PAD)
-            .map_err(|_| JwtError::InvalidBase64)?;
+impl JwsJson {
+    pub(crate) fn from_str_with_critical(
+        s: &str,
+        critical: &CriticalExtensions,
+    ) -> Result<Self, JwtError> {
+        let mut members: serde_json::Map<String, serde_json::Value> = serde_json::from_str(s)
+            .map_err(|e| {
+                debug!(?e, "jws is not a json object");
+                JwtError::InvalidJsonFormat
+            })?;
 
-        let xbn = bn::BigNum::from_slice(&x).map_err(|_| JwtError::OpenSSLError)?;
-        let ybn = bn::BigNum::from_slice(&y).map_err(|_| JwtError::OpenSSLError)?;
-        let dbn = bn::BigNum::from_slice(&d).map_err(|_| JwtError::OpenSSLError)?;
+        let payload_enc = match members.remove("payload") {
+            Some(serde_json::Value::String(p)) => p,
+            _ => return Err(JwtError::InvalidJsonFormat),
+        };
 
-        let ec_group = ec::EcGroup::from_curve_name(nid::Nid::X9_62_PRIME256V1)
-            .map_err(|_| JwtError::OpenSSLError)?;
+        let (reprs, flattened): (Vec<JwsJsonSignatureRepr>, bool) = match members
+            .remove("signatures")
+        {
+            Some(signatures) => {
+                if ["protected", "header", "signature"]
+                    .iter()
+                    .any(|name| members.contains_key(*name))
+                {
+                    return Err(JwtError::InvalidJsonFormat);
+                }
+                let reprs = serde_json::from_value(signatures).map_err(|e| {
+                    debug!(?e, "invalid signatures");
+                    JwtError::InvalidJsonFormat
+                })?;
+                (reprs, false)
+            }
+            None => {
+                let repr =
+                    serde_json::from_value(serde_json::Value::Object(members)).map_err(|e| {
+                        debug!(?e, "invalid signature");
+                        JwtError::InvalidJsonFormat
+                    })?;
+                (vec![repr], true)
+            }
+        };
 
-        let pkey = ec::EcKey::from_public_key_affine_coordinates(&ec_group, &xbn, &ybn)
-            .map_err(|_| JwtError::OpenSSLError)?;
+        if reprs.is_empty() {
+            return Err(JwtError::InvalidJsonFormat);
+        }
 
-        let skey = ec::EcKey::from_private_components(&ec_group, &dbn, pkey.public_key())
-            .map_err(|_| JwtError::OpenSSLError)?;
+        let signatures = reprs
+            .into_iter()
+            .map(|repr| JwsJsonSignature::from_repr(repr, critical))
+            .collect::<Result<Vec<_>, _>>()?;
 
-        skey.check_key().map_err(|_| JwtError::OpenSSLError)?;
-        Ok(JwsSigner::ES256 {
-            skey,
-            digest: hash::MessageDigest::sha256(),
-        })
+        JwsJson::new(payload_enc, signatures, flattened)
     }
 
-    #[cfg(test)]
-    pub fn from_hs256_raw(buf: &[u8]) -> Result<Self, JwtError> {
-        if buf.len() < 32 {
-            return Err(JwtError::OpenSSLError);
+    fn new(
+        payload_enc: String,
+        signatures: Vec<JwsJsonSignature>,
+        flattened: bool,
+    ) -> Result<Self, JwtError> {
+        // Every signature must agree on how the payload is encoded.
+        // https://datatracker.ietf.org/doc/html/rfc7797#section-7
+        let b64 = signatures
+            .first()
+            .map(|sig| sig.header.b64())
+            .ok_or(JwtError::InvalidJsonFormat)?;
+        if signatures.iter().any(|sig| sig.header.b64() != b64) {
+            return Err(JwtError::InvalidHeaderParameter {
+                name: "b64".to_string(),
+            });
+        }
+
+        let payload = if b64 {
+            base64::decode_config(&payload_enc, base64::URL_SAFE_NO_PAD)
+                .map_err(|e| JwtError::InvalidBase64(e.into()))?
+        } else {
+            payload_enc.as_bytes().to_vec()
+        };
+
+        Ok(JwsJson {
+            payload,
+            payload_enc,
+            signatures,
+            flattened,
+        })
+    }
+
+    /// Sign this payload with each of these signers, in the general form.
+    pub(crate) fn sign(
+        payload: &[u8],
+        signers: &[(&JwsSigner, &JwsHeader)],
+    ) -> Result<Self, JwtError> {
+        let mut payload_enc = None;
+        let mut signatures = Vec::with_capacity(signers.len());
+
+        for (signer, header) in signers {
+            // The payload member is not part of the signature, so each signature is
+            // created detached and shares the one payload member.
+            let jwsc = JwsInner::new(payload.to_vec())
+                .set_header((*header).clone())
+                .sign_compact(signer, None, true)?;
+            let mut jwsj = JwsJson::from_compact(&jwsc)?;
+            payload_enc.get_or_insert(jwsj.payload_enc);
+            signatures.append(&mut jwsj.signatures);
+        }
+
+        let payload_enc = payload_enc.ok_or(JwtError::InvalidJsonFormat)?;
+        JwsJson::new(payload_enc, signatures, false)
+    }
+
+    pub(crate) fn headers(&self) -> impl Iterator<Item = &ProtectedHeader> {
+        self.signatures.iter().map(|sig| &sig.header)
+    }
+
+    fn compact(&self, sig: &JwsJsonSignature) -> JwsCompact {
+        let mut sign_input = sig.protected.as_bytes().to_vec();
+        sign_input.push(b'.');
+        sign_input.extend_from_slice(self.payload_enc.as_bytes());
+
+        JwsCompact {
+            header: sig.header.clone(),
+            payload: self.payload.clone(),
+            sign_input,
+            signature: sig.signature.clone(),
+            detached: false,
+        }
+    }
+
+    pub(crate) fn validate<S>(
+        &self,
+        validator: &S,
+        policy: JwsSignaturePolicy,
+    ) -> Result<&[u8], JwtError>
+    where
+        S: JwsValidatorSelector + ?Sized,
+    {
+        let mut last_err = JwtError::InvalidSignature;
+        for sig in self.signatures.iter() {
+            let res = validator
+                .select_validator(sig.header.kid.as_deref(), &sig.header.alg)
+                .and_then(|jwsv| self.compact(sig).validate(&jwsv));
+
+            match (res, policy) {
+                (Ok(_), JwsSignaturePolicy::Any) => return Ok(&self.payload),
+                (Ok(_), JwsSignaturePolicy::All) => {}
+                (Err(e), JwsSignaturePolicy::All) => return Err(e),
+                (Err(e), JwsSignaturePolicy::Any) => {
+                    debug!(?e, kid = ?sig.header.kid, "signature is not valid");
+                    last_err = e;
+                }
+            }
+        }
+
+        match policy {
+            JwsSignaturePolicy::All => Ok(&self.payload),
+            JwsSignaturePolicy::Any => Err(last_err),
+        }
+    }
+
+    /// The compact form of this jws. This requires exactly one signature, with all of
+    /// its header parameters protected.
+    pub(crate) fn to_compact(&self) -> Result<JwsCompact, JwtError> {
+        match self.signatures.as_slice() {
+            [sig] if sig.unprotected.is_none() && !sig.protected.is_empty() => {
+                // An unencoded payload can not contain a period in compact form.
+                if self.payload_enc.contains('.') {
+                    return Err(JwtError::CompactFormUnavailable);
+                }
+                Ok(self.compact(sig))
+            }
+            _ => Err(JwtError::CompactFormUnavailable),
+        }
+    }
+
+    /// The flattened form of this compact jws.
+    pub(crate) fn from_compact(jwsc: &JwsCompact) -> Result<Self, JwtError> {
+        let sign_input = std::str::from_utf8(&jwsc.sign_input).map_err(|_| {
+            // An unencoded detached payload may not be a string.
+            JwtError::InvalidJsonFormat
+        })?;
+        let (protected, payload_enc) = sign_input
+            .split_once('.')
+            .ok_or(JwtError::InvalidCompactFormat)?;
+
+        Ok(JwsJson {
+            payload: jwsc.payload.clone(),
+            payload_enc: payload_enc.to_string(),
+            signatures: vec![JwsJsonSignature {
+                header: jwsc.header.clone(),
+                protected: protected.to_string(),
+                unprotected: None,
+                signature: jwsc.signature.clone(),
+            }],
+            flattened: true,
+        })
+    }
+}
+
+impl fmt::Display for JwsJson {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let out = match self.signatures.as_slice() {
+            [sig] if self.flattened => serde_json::to_string(&JwsJsonFlattenedRepr {
+                payload: &self.payload_enc,
+                signature: sig.to_repr(),
+            }),
+            sigs => serde_json::to_string(&JwsJsonGeneralRepr {
+                payload: &self.payload_enc,
+                signatures: sigs.iter().map(|sig| sig.to_repr()).collect(),
+            }),
+        }
+        .map_err(|_| fmt::Error)?;
+        f.write_str(&out)
+    }
+}
+
+impl TryFrom<&Jwk> for JwsValidator {
+    type Error = JwtError;
+
+    fn try_from(value: &Jwk) -> Result<Self, Self::Error> {
+        let alg = match value {
+            Jwk::EC { alg, .. }
+            | Jwk::OKP { alg, .. }
+            | Jwk::RSA { alg, .. }
+            | Jwk::oct { alg, .. } => alg.as_ref(),
+        };
+        match alg {
+            None => JwsValidator::from_jwk_with_alg(value, None),
+            Some(JwkAlg::Jws(alg)) => JwsValidator::from_jwk_with_alg(value, Some(alg)),
+            // This key is for encryption.
+            Some(JwkAlg::Jwe(_)) => Err(JwtError::ValidatorAlgMismatch),
+        }
+    }
+}
+
+/// A source of validators, which is able to select the correct key to validate a token
+/// from the key id (kid) and algorithm (alg) in the token's header.
+pub trait JwsValidatorSelector {
+    /// Select the validator for a token with this kid and alg.
+    fn select_validator(
+        &self,
+        kid: Option<&str>,
+        alg: &JwaAlg,
+    ) -> Result<Cow<'_, JwsValidator>, JwtError>;
+}
+
+impl JwsValidatorSelector for JwsValidator {
+    fn select_validator(
+        &self,
+        _kid: Option<&str>,
+        _alg: &JwaAlg,
+    ) -> Result<Cow<'_, JwsValidator>, JwtError> {
+        // The alg is asserted during validation.
+        Ok(Cow::Borrowed(self))
+    }
+}
+
+impl JwsValidatorSelector for JwkKeySet {
+    /// Select the key from this set that matches the token. Keys that are for encryption,
+    /// that specify a different alg, or are unable to be used with the alg are ignored.
+    ///
+    /// If the token has a kid, the key with the same kid is used. If the token does not
+    /// have a kid, then there must be exactly one key in the set that is able to validate
+    /// this alg, else an error is returned.
+    fn select_validator(
+        &self,
+        kid: Option<&str>,
+        alg: &JwaAlg,
+    ) -> Result<Cow<'_, JwsValidator>, JwtError> {
+        let mut candidates = self.keys.iter().filter_map(|jwk| {
+            let (k_alg, k_use, k_kid) = match jwk {
+                Jwk::EC { alg, use_, kid, .. }
+                | Jwk::OKP { alg, use_, kid, .. }
+                | Jwk::RSA { alg, use_, kid, .. }
+                | Jwk::oct { alg, use_, kid, .. } => (alg, use_, kid),
+            };
+
+            if *k_use == Some(JwkUse::Enc) {
+                return None;
+            }
+
+            match k_alg {
+                None => {}
+                Some(JwkAlg::Jws(k_alg)) if k_alg == alg => {}
+                Some(_) => return None,
+            }
+
+            if kid.is_some() && k_kid.as_deref() != kid {
+                return None;
+            }
+
+            JwsValidator::from_jwk_with_alg(jwk, Some(alg))
+                .map_err(|e| {
+                    debug!(
+                        ?e,
+                        ?k_kid,
+                        "Ignoring jwk that can not be used for validation"
+                    );
+                })
+                .ok()
+        });
+
+        let validator = candidates.next().ok_or(JwtError::JwkKeyNotFound)?;
+
+        if kid.is_none() && candidates.next().is_some() {
+            // Without a kid we can't know which of these keys was intended.
+            return Err(JwtError::JwkKeyAmbiguous);
+        }
+
+        Ok(Cow::Owned(validator))
+    }
+}
+
+impl JwsValidator {
+    /// Build a validator from this public key for use with the requested alg. If no alg is
+    /// requested, the default alg for the key type is used.
+    fn from_jwk_with_alg(value: &Jwk, alg: Option<&JwaAlg>) -> Result<Self, JwtError> {
+        match value {
+            Jwk::EC {
+                crv,
+                x,
+                y,
+                alg: _,
+                use_: _,
+                kid: _,
+            } => {
+                match (crv, alg) {
+                    (_, None)
+                    | (EcCurve::P256, Some(JwaAlg::ES256))
+                    | (EcCurve::P384, Some(JwaAlg::ES384))
+                    | (EcCurve::P521, Some(JwaAlg::ES512)) => {}
+                    _ => return Err(JwtError::ValidatorAlgMismatch),
+                };
+
+                let digest = match crv {
+                    EcCurve::P256 => hash::MessageDigest::sha256(),
+                    EcCurve::P384 => hash::MessageDigest::sha384(),
+                    EcCurve::P521 => hash::MessageDigest::sha512(),
+                };
+                let ec_group = ec::EcGroup::from_curve_name(crv.to_nid())
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+                let xbn =
+                    bn::BigNum::from_slice(&x.0).map_err(|e| JwtError::OpenSSLError(e.into()))?;
+                let ybn =
+                    bn::BigNum::from_slice(&y.0).map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+                let pkey = ec::EcKey::from_public_key_affine_coordinates(&ec_group, &xbn, &ybn)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+                pkey.check_key()
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+                Ok(match crv {
+                    EcCurve::P256 => JwsValidator::ES256 { pkey, digest },
+                    EcCurve::P384 => JwsValidator::ES384 { pkey, digest },
+                    EcCurve::P521 => JwsValidator::ES512 { pkey, digest },
+                })
+            }
+            Jwk::OKP {
+                crv,
+                x,
+                alg: _,
+                use_: _,
+                kid: _,
+            } => {
+                if !crv.is_signing() {
+                    return Err(JwtError::InvalidKey);
+                }
+
+                if !matches!(alg, None | Some(JwaAlg::EdDSA)) {
+                    return Err(JwtError::ValidatorAlgMismatch);
+                }
+
+                pkey::PKey::public_key_from_raw_bytes(&x.0, crv.to_id())
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))
+                    .map(|pkey| JwsValidator::EdDSA { pkey })
+            }
+            Jwk::RSA {
+                n,
+                e,
+                alg: _,
+                use_: _,
+                kid: _,
+            } => {
+                let nbn =
+                    bn::BigNum::from_slice(&n.0).map_err(|e| JwtError::OpenSSLError(e.into()))?;
+                let ebn =
+                    bn::BigNum::from_slice(&e.0).map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+                let pkey = rsa::Rsa::from_public_components(nbn, ebn)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+                // RSA keys can be used with multiple padding schemes, so we rely on the
+                // requested alg to determine which one is in use, defaulting to RS256.
+                match alg {
+                    None | Some(JwaAlg::RS256) => Ok(JwsValidator::RS256 {
+                        pkey,
+                        digest: hash::MessageDigest::sha256(),
+                    }),
+                    Some(JwaAlg::RS384) => Ok(JwsValidator::RS384 {
+                        pkey,
+                        digest: hash::MessageDigest::sha384(),
+                    }),
+                    Some(JwaAlg::RS512) => Ok(JwsValidator::RS512 {
+                        pkey,
+                        digest: hash::MessageDigest::sha512(),
+                    }),
+                    Some(JwaAlg::PS256) => Ok(JwsValidator::PS256 {
+                        pkey,
+                        digest: hash::MessageDigest::sha256(),
+                    }),
+                    Some(JwaAlg::PS384) => Ok(JwsValidator::PS384 {
+                        pkey,
+                        digest: hash::MessageDigest::sha384(),
+                    }),
+                    Some(JwaAlg::PS512) => Ok(JwsValidator::PS512 {
+                        pkey,
+                        digest: hash::MessageDigest::sha512(),
+                    }),
+                    Some(_) => Err(JwtError::ValidatorAlgMismatch),
+                }
+            }
+            // Symmetric keys are only shared with the parties that hold the secret, so
+            // they are never a source of validators.
+            Jwk::oct { .. } => Err(JwtError::InvalidKey),
+        }
+    }
+}
+
+impl JwsSigner {
+    fn alg(&self) -> Result<JwaAlg, JwtError> {
+        match self {
+            JwsSigner::ES256 { skey, digest }
+            | JwsSigner::ES384 { skey, digest }
+            | JwsSigner::ES512 { skey, digest } => ecdsa_alg(skey.group(), digest),
+            JwsSigner::RS256 { skey: _, digest }
+            | JwsSigner::RS384 { skey: _, digest }
+            | JwsSigner::RS512 { skey: _, digest } => rsa_pkcs1_alg(digest),
+            JwsSigner::PS256 { skey: _, digest }
+            | JwsSigner::PS384 { skey: _, digest }
+            | JwsSigner::PS512 { skey: _, digest } => rsa_pss_alg(digest),
+            JwsSigner::HS256 { skey: _, digest }
+            | JwsSigner::HS384 { skey: _, digest }
+            | JwsSigner::HS512 { skey: _, digest } => hmac_alg(digest),
+            JwsSigner::EdDSA { skey: _ } => Ok(JwaAlg::EdDSA),
+        }
+    }
+
+    #[cfg(test)]
+    /// Restore an ES256 signer from the base64 components of a private JWK.
+    pub fn from_es256_jwk_components(x: &str, y: &str, d: &str) -> Result<Self, JwtError> {
+        Self::from_ec_jwk_components(EcCurve::P256, x, y, d)
+    }
+
+    #[cfg(test)]
+    /// Restore an EC signer from the base64 components of a private JWK.
+    pub fn from_ec_jwk_components(
+        crv: EcCurve,
+        x: &str,
+        y: &str,
+        d: &str,
+    ) -> Result<Self, JwtError> {
+        let x = base64::decode_config(x, base64::URL_SAFE_NO_PAD)
+            .map_err(|e| JwtError::InvalidBase64(e.into()))?;
+        let y = base64::decode_config(y, base64::URL_SAFE_NO_PAD)
+            .map_err(|e| JwtError::InvalidBase64(e.into()))?;
+
+        let d = base64::decode_config(d, base64::URL_SAFE_NO_PAD)
+            .map_err(|e| JwtError::InvalidBase64(e.into()))?;
+
+        let xbn = bn::BigNum::from_slice(&x).map_err(|e| JwtError::OpenSSLError(e.into()))?;
+        let ybn = bn::BigNum::from_slice(&y).map_err(|e| JwtError::OpenSSLError(e.into()))?;
+        let dbn = bn::BigNum::from_slice(&d).map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+        let ec_group = ec::EcGroup::from_curve_name(crv.to_nid())
+            .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+        let pkey = ec::EcKey::from_public_key_affine_coordinates(&ec_group, &xbn, &ybn)
+            .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+        let skey = ec::EcKey::from_private_components(&ec_group, &dbn, pkey.public_key())
+            .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+        skey.check_key()
+            .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+        Ok(match crv {
+            EcCurve::P256 => JwsSigner::ES256 {
+                skey,
+                digest: hash::MessageDigest::sha256(),
+            },
+            EcCurve::P384 => JwsSigner::ES384 {
+                skey,
+                digest: hash::MessageDigest::sha384(),
+            },
+            EcCurve::P521 => JwsSigner::ES512 {
+                skey,
+                digest: hash::MessageDigest::sha512(),
+            },
+        })
+    }
+
+    #[cfg(test)]
+    /// Restore an EdDSA signer from the raw private key component of an OKP JWK.
+    pub fn from_okp_jwk_components(crv: OkpCurve, d: &str) -> Result<Self, JwtError> {
+        if !crv.is_signing() {
+            return Err(JwtError::InvalidKey);
+        }
+
+        let d = base64::decode_config(d, base64::URL_SAFE_NO_PAD)
+            .map_err(|e| JwtError::InvalidBase64(e.into()))?;
+
+        pkey::PKey::private_key_from_raw_bytes(&d, crv.to_id())
+            .map_err(|e| JwtError::OpenSSLError(e.into()))
+            .map(|skey| JwsSigner::EdDSA { skey })
+    }
+
+    #[cfg(test)]
+    /// Restore an HS256 signer from raw key bytes.
+    pub fn from_hs256_raw(buf: &[u8]) -> Result<Self, JwtError> {
+        if buf.len() < 32 {
+            return Err(JwtError::InvalidKey);
         }
 
         let skey = pkey::PKey::hmac(buf).map_err(|e| {
             error!("{:?}", e);
-            JwtError::OpenSSLError
+            JwtError::OpenSSLError(e.into())
         })?;
 
         Ok(JwsSigner::HS256 {
@@ -630,32 +2130,90 @@ impl JwsSigner {
         match self {
             JwsSigner::ES256 { skey, digest } => {
                 ec::EcKey::from_public_key(skey.group(), skey.public_key())
-                    .map_err(|_| JwtError::OpenSSLError)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))
                     .map(|pkey| JwsValidator::ES256 {
                         pkey,
                         digest: *digest,
                     })
             }
-            JwsSigner::RS256 { skey, digest } => {
-                let n = skey.n().to_owned().map_err(|_| JwtError::OpenSSLError)?;
-                let e = skey.e().to_owned().map_err(|_| JwtError::OpenSSLError)?;
-                rsa::Rsa::from_public_components(n, e)
-                    .map_err(|_| JwtError::OpenSSLError)
-                    .map(|pkey| JwsValidator::RS256 {
+            JwsSigner::ES384 { skey, digest } => {
+                ec::EcKey::from_public_key(skey.group(), skey.public_key())
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))
+                    .map(|pkey| JwsValidator::ES384 {
+                        pkey,
+                        digest: *digest,
+                    })
+            }
+            JwsSigner::ES512 { skey, digest } => {
+                ec::EcKey::from_public_key(skey.group(), skey.public_key())
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))
+                    .map(|pkey| JwsValidator::ES512 {
                         pkey,
                         digest: *digest,
                     })
             }
+            JwsSigner::RS256 { skey, digest } => {
+                rsa_public_key(skey).map(|pkey| JwsValidator::RS256 {
+                    pkey,
+                    digest: *digest,
+                })
+            }
+            JwsSigner::RS384 { skey, digest } => {
+                rsa_public_key(skey).map(|pkey| JwsValidator::RS384 {
+                    pkey,
+                    digest: *digest,
+                })
+            }
+            JwsSigner::RS512 { skey, digest } => {
+                rsa_public_key(skey).map(|pkey| JwsValidator::RS512 {
+                    pkey,
+                    digest: *digest,
+                })
+            }
+            JwsSigner::PS256 { skey, digest } => {
+                rsa_public_key(skey).map(|pkey| JwsValidator::PS256 {
+                    pkey,
+                    digest: *digest,
+                })
+            }
+            JwsSigner::PS384 { skey, digest } => {
+                rsa_public_key(skey).map(|pkey| JwsValidator::PS384 {
+                    pkey,
+                    digest: *digest,
+                })
+            }
+            JwsSigner::PS512 { skey, digest } => {
+                rsa_public_key(skey).map(|pkey| JwsValidator::PS512 {
+                    pkey,
+                    digest: *digest,
+                })
+            }
             JwsSigner::HS256 { skey, digest } => Ok(JwsValidator::HS256 {
                 skey: skey.clone(),
                 digest: *digest,
             }),
+            JwsSigner::HS384 { skey, digest } => Ok(JwsValidator::HS384 {
+                skey: skey.clone(),
+                digest: *digest,
+            }),
+            JwsSigner::HS512 { skey, digest } => Ok(JwsValidator::HS512 {
+                skey: skey.clone(),
+                digest: *digest,
+            }),
+            JwsSigner::EdDSA { skey } => {
+                let curve = OkpCurve::from_id(skey.id()).ok_or(JwtError::InvalidKey)?;
+                skey.raw_public_key()
+                    .and_then(|x| pkey::PKey::public_key_from_raw_bytes(&x, curve.to_id()))
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))
+                    .map(|pkey| JwsValidator::EdDSA { pkey })
+            }
         }
     }
 
-    /// Restore this JwsSigner from a DER private key.
+    /// Restore this JwsSigner from a DER private key. The key must be on the P-256
+    /// curve, and keys on any other curve are rejected with InvalidKey.
     pub fn from_es256_der(der: &[u8]) -> Result<Self, JwtError> {
-        let skey = ec::EcKey::private_key_from_der(der).map_err(|_| JwtError::OpenSSLError)?;
+        let skey = ec_key_from_der(der, EcCurve::P256)?;
 
         Ok(JwsSigner::ES256 {
             skey,
@@ -663,9 +2221,32 @@ impl JwsSigner {
         })
     }
 
+    /// Restore this JwsSigner from a DER private key. The key must be on the P-384
+    /// curve, and keys on any other curve are rejected with InvalidKey.
+    pub fn from_es384_der(der: &[u8]) -> Result<Self, JwtError> {
+        let skey = ec_key_from_der(der, EcCurve::P384)?;
+
+        Ok(JwsSigner::ES384 {
+            skey,
+            digest: hash::MessageDigest::sha384(),
+        })
+    }
+
+    /// Restore this JwsSigner from a DER private key. The key must be on the P-521
+    /// curve, and keys on any other curve are rejected with InvalidKey.
+    pub fn from_es512_der(der: &[u8]) -> Result<Self, JwtError> {
+        let skey = ec_key_from_der(der, EcCurve::P521)?;
+
+        Ok(JwsSigner::ES512 {
+            skey,
+            digest: hash::MessageDigest::sha512(),
+        })
+    }
+
     /// Restore this JwsSigner from a DER private key.
     pub fn from_rs256_der(der: &[u8]) -> Result<Self, JwtError> {
-        let skey = rsa::Rsa::private_key_from_der(der).map_err(|_| JwtError::OpenSSLError)?;
+        let skey =
+            rsa::Rsa::private_key_from_der(der).map_err(|e| JwtError::OpenSSLError(e.into()))?;
 
         Ok(JwsSigner::RS256 {
             skey,
@@ -673,6 +2254,66 @@ impl JwsSigner {
         })
     }
 
+    /// Restore this JwsSigner from a DER private key.
+    pub fn from_rs384_der(der: &[u8]) -> Result<Self, JwtError> {
+        let skey = rsa_key_from_der(der)?;
+
+        Ok(JwsSigner::RS384 {
+            skey,
+            digest: hash::MessageDigest::sha384(),
+        })
+    }
+
+    /// Restore this JwsSigner from a DER private key.
+    pub fn from_rs512_der(der: &[u8]) -> Result<Self, JwtError> {
+        let skey = rsa_key_from_der(der)?;
+
+        Ok(JwsSigner::RS512 {
+            skey,
+            digest: hash::MessageDigest::sha512(),
+        })
+    }
+
+    /// Restore this JwsSigner from a DER private key.
+    pub fn from_ps256_der(der: &[u8]) -> Result<Self, JwtError> {
+        let skey = rsa_key_from_der(der)?;
+
+        Ok(JwsSigner::PS256 {
+            skey,
+            digest: hash::MessageDigest::sha256(),
+        })
+    }
+
+    /// Restore this JwsSigner from a DER private key.
+    pub fn from_ps384_der(der: &[u8]) -> Result<Self, JwtError> {
+        let skey = rsa_key_from_der(der)?;
+
+        Ok(JwsSigner::PS384 {
+            skey,
+            digest: hash::MessageDigest::sha384(),
+        })
+    }
+
+    /// Restore this JwsSigner from a DER private key.
+    pub fn from_ps512_der(der: &[u8]) -> Result<Self, JwtError> {
+        let skey = rsa_key_from_der(der)?;
+
+        Ok(JwsSigner::PS512 {
+            skey,
+            digest: hash::MessageDigest::sha512(),
+        })
+    }
+
+    /// Restore this JwsSigner from a DER private key.
+    pub fn from_ed25519_der(der: &[u8]) -> Result<Self, JwtError> {
+        okp_key_from_der(der, OkpCurve::Ed25519).map(|skey| JwsSigner::EdDSA { skey })
+    }
+
+    /// Restore this JwsSigner from a DER private key.
+    pub fn from_ed448_der(der: &[u8]) -> Result<Self, JwtError> {
+        okp_key_from_der(der, OkpCurve::Ed448).map(|skey| JwsSigner::EdDSA { skey })
+    }
+
     /*
     pub fn public_key_to_der(&self) -> Result<Vec<u8>, JwtError> {
         unimplemented!();
@@ -682,24 +2323,32 @@ impl JwsSigner {
     /// Export this JwsSigner to a DER private key.
     pub fn private_key_to_der(&self) -> Result<Vec<u8>, JwtError> {
         match self {
-            JwsSigner::ES256 { skey, digest: _ } => skey
+            JwsSigner::ES256 { skey, digest: _ }
+            | JwsSigner::ES384 { skey, digest: _ }
+            | JwsSigner::ES512 { skey, digest: _ } => skey
+                .private_key_to_der()
+                .map_err(|e| JwtError::OpenSSLError(e.into())),
+            JwsSigner::RS256 { skey, digest: _ }
+            | JwsSigner::RS384 { skey, digest: _ }
+            | JwsSigner::RS512 { skey, digest: _ }
+            | JwsSigner::PS256 { skey, digest: _ }
+            | JwsSigner::PS384 { skey, digest: _ }
+            | JwsSigner::PS512 { skey, digest: _ } => skey
                 .private_key_to_der()
-                .map_err(|_| JwtError::OpenSSLError),
-            JwsSigner::RS256 { skey, digest: _ } => skey
+                .map_err(|e| JwtError::OpenSSLError(e.into())),
+            JwsSigner::HS256 { skey: _, digest: _ }
+            | JwsSigner::HS384 { skey: _, digest: _ }
+            | JwsSigner::HS512 { skey: _, digest: _ } => Err(JwtError::PrivateKeyDenied),
+            JwsSigner::EdDSA { skey } => skey
                 .private_key_to_der()
-                .map_err(|_| JwtError::OpenSSLError),
-            JwsSigner::HS256 { skey: _, digest: _ } => Err(JwtError::PrivateKeyDenied),
+                .map_err(|e| JwtError::OpenSSLError(e.into())),
         }
     }
 
     /// Create a new secure private key for signing
     pub fn generate_es256() -> Result<Self, JwtError> {
-        let ec_group = ec::EcGroup::from_curve_name(nid::Nid::X9_62_PRIME256V1)
-            .map_err(|_| JwtError::OpenSSLError)?;
-
-        let skey = ec::EcKey::generate(&ec_group).map_err(|_| JwtError::OpenSSLError)?;
+        let skey = ec_key_generate(EcCurve::P256)?;
 
-        skey.check_key().map_err(|_| JwtError::OpenSSLError)?;
         Ok(JwsSigner::ES256 {
             skey,
             digest: hash::MessageDigest::sha256(),
@@ -707,18 +2356,42 @@ impl JwsSigner {
     }
 
     /// Create a new secure private key for signing
-    pub fn generate_hs256() -> Result<Self, JwtError> {
-        let mut buf = [0; 32];
-        rand::rand_bytes(&mut buf).map_err(|e| {
-            error!("{:?}", e);
-            JwtError::OpenSSLError
-        })?;
+    pub fn generate_es384() -> Result<Self, JwtError> {
+        let skey = ec_key_generate(EcCurve::P384)?;
 
-        // Can it become a pkey?
-        let skey = pkey::PKey::hmac(&buf).map_err(|e| {
-            error!("{:?}", e);
-            JwtError::OpenSSLError
-        })?;
+        Ok(JwsSigner::ES384 {
+            skey,
+            digest: hash::MessageDigest::sha384(),
+        })
+    }
+
+    /// Create a new secure private key for signing
+    pub fn generate_es512() -> Result<Self, JwtError> {
+        let skey = ec_key_generate(EcCurve::P521)?;
+
+        Ok(JwsSigner::ES512 {
+            skey,
+            digest: hash::MessageDigest::sha512(),
+        })
+    }
+
+    /// Create a new secure private key for signing
+    pub fn generate_ed25519() -> Result<Self, JwtError> {
+        pkey::PKey::generate_ed25519()
+            .map_err(|e| JwtError::OpenSSLError(e.into()))
+            .map(|skey| JwsSigner::EdDSA { skey })
+    }
+
+    /// Create a new secure private key for signing
+    pub fn generate_ed448() -> Result<Self, JwtError> {
+        pkey::PKey::generate_ed448()
+            .map_err(|e| JwtError::OpenSSLError(e.into()))
+            .map(|skey| JwsSigner::EdDSA { skey })
+    }
+
+    /// Create a new secure private key for signing
+    pub fn generate_hs256() -> Result<Self, JwtError> {
+        let skey = hmac_key_generate(32)?;
 
         Ok(JwsSigner::HS256 {
             skey,
@@ -726,84 +2399,210 @@ impl JwsSigner {
         })
     }
 
+    /// Create a new secure private key for signing
+    pub fn generate_hs384() -> Result<Self, JwtError> {
+        let skey = hmac_key_generate(48)?;
+
+        Ok(JwsSigner::HS384 {
+            skey,
+            digest: hash::MessageDigest::sha384(),
+        })
+    }
+
+    /// Create a new secure private key for signing
+    pub fn generate_hs512() -> Result<Self, JwtError> {
+        let skey = hmac_key_generate(64)?;
+
+        Ok(JwsSigner::HS512 {
+            skey,
+            digest: hash::MessageDigest::sha512(),
+        })
+    }
+
     /// Create a new legacy (RSA) private key for signing
     pub fn generate_legacy_rs256() -> Result<Self, JwtError> {
-        let skey = rsa::Rsa::generate(RSA_MIN_SIZE).map_err(|_| JwtError::OpenSSLError)?;
+        let skey = rsa_key_generate()?;
 
-        skey.check_key().map_err(|_| JwtError::OpenSSLError)?;
         Ok(JwsSigner::RS256 {
             skey,
             digest: hash::MessageDigest::sha256(),
         })
     }
 
+    /// Create a new legacy (RSA) private key for signing
+    pub fn generate_legacy_rs384() -> Result<Self, JwtError> {
+        let skey = rsa_key_generate()?;
+
+        Ok(JwsSigner::RS384 {
+            skey,
+            digest: hash::MessageDigest::sha384(),
+        })
+    }
+
+    /// Create a new legacy (RSA) private key for signing
+    pub fn generate_legacy_rs512() -> Result<Self, JwtError> {
+        let skey = rsa_key_generate()?;
+
+        Ok(JwsSigner::RS512 {
+            skey,
+            digest: hash::MessageDigest::sha512(),
+        })
+    }
+
+    /// Create a new RSASSA-PSS private key for signing
+    pub fn generate_ps256() -> Result<Self, JwtError> {
+        let skey = rsa_key_generate()?;
+
+        Ok(JwsSigner::PS256 {
+            skey,
+            digest: hash::MessageDigest::sha256(),
+        })
+    }
+
+    /// Create a new RSASSA-PSS private key for signing
+    pub fn generate_ps384() -> Result<Self, JwtError> {
+        let skey = rsa_key_generate()?;
+
+        Ok(JwsSigner::PS384 {
+            skey,
+            digest: hash::MessageDigest::sha384(),
+        })
+    }
+
+    /// Create a new RSASSA-PSS private key for signing
+    pub fn generate_ps512() -> Result<Self, JwtError> {
+        let skey = rsa_key_generate()?;
+
+        Ok(JwsSigner::PS512 {
+            skey,
+            digest: hash::MessageDigest::sha512(),
+        })
+    }
+
+    /// Export the public key of this signer as a Jwk, using the RFC 7638 SHA-256 thumbprint
+    /// of the key as the key id.
+    pub fn public_key_as_jwk_thumbprint_kid(&self) -> Result<Jwk, JwtError> {
+        let mut jwk = self.public_key_as_jwk(None)?;
+        let thumbprint = jwk.thumbprint(hash::MessageDigest::sha256())?.to_string();
+
+        match &mut jwk {
+            Jwk::EC { kid, .. }
+            | Jwk::OKP { kid, .. }
+            | Jwk::RSA { kid, .. }
+            | Jwk::oct { kid, .. } => *kid = Some(thumbprint),
+        }
+
+        Ok(jwk)
+    }
+
     /// Export the public key of this signer as a Jwk
     pub fn public_key_as_jwk(&self, kid: Option<&str>) -> Result<Jwk, JwtError> {
         match self {
-            JwsSigner::ES256 { skey, digest: _ } => {
+            JwsSigner::ES256 { skey, digest: _ }
+            | JwsSigner::ES384 { skey, digest: _ }
+            | JwsSigner::ES512 { skey, digest: _ } => {
                 let pkey = skey.public_key();
                 let ec_group = skey.group();
 
-                let mut bnctx = bn::BigNumContext::new().map_err(|_| JwtError::OpenSSLError)?;
+                let crv = ec_group
+                    .curve_name()
+                    .and_then(EcCurve::from_nid)
+                    .ok_or(JwtError::InvalidKey)?;
 
-                let mut xbn = bn::BigNum::new().map_err(|_| JwtError::OpenSSLError)?;
+                let mut bnctx =
+                    bn::BigNumContext::new().map_err(|e| JwtError::OpenSSLError(e.into()))?;
 
-                let mut ybn = bn::BigNum::new().map_err(|_| JwtError::OpenSSLError)?;
+                let mut xbn = bn::BigNum::new().map_err(|e| JwtError::OpenSSLError(e.into()))?;
 
-                pkey.affine_coordinates_gfp(ec_group, &mut xbn, &mut ybn, &mut bnctx)
-                    .map_err(|_| JwtError::OpenSSLError)?;
-
-                let mut public_key_x = Vec::with_capacity(32);
-                let mut public_key_y = Vec::with_capacity(32);
-
-                public_key_x.resize(32, 0);
-                public_key_y.resize(32, 0);
-
-                let xbnv = xbn.to_vec();
-                let ybnv = ybn.to_vec();
+                let mut ybn = bn::BigNum::new().map_err(|e| JwtError::OpenSSLError(e.into()))?;
 
-                let (_pad, x_fill) = public_key_x.split_at_mut(32 - xbnv.len());
-                x_fill.copy_from_slice(&xbnv);
+                pkey.affine_coordinates_gfp(ec_group, &mut xbn, &mut ybn, &mut bnctx)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
 
-                let (_pad, y_fill) = public_key_y.split_at_mut(32 - ybnv.len());
-                y_fill.copy_from_slice(&ybnv);
+                let field_size = ec_field_size(ec_group);
+                let public_key_x = bn_to_fixed_width(&xbn, field_size)?;
+                let public_key_y = bn_to_fixed_width(&ybn, field_size)?;
 
                 Ok(Jwk::EC {
-                    crv: EcCurve::P256,
+                    crv,
                     x: Base64UrlSafeData(public_key_x),
                     y: Base64UrlSafeData(public_key_y),
-                    alg: Some(JwaAlg::ES256),
+                    alg: Some(self.alg()?.into()),
                     use_: Some(JwkUse::Sig),
                     kid: kid.map(str::to_string),
                 })
             }
-            JwsSigner::RS256 { skey, digest: _ } => {
-                let public_key_n = skey
-                    .n()
-                    .to_vec_padded(RSA_SIG_SIZE)
-                    .map_err(|_| JwtError::OpenSSLError)?;
-
-                let public_key_e = skey
-                    .e()
-                    .to_vec_padded(3)
-                    .map_err(|_| JwtError::OpenSSLError)?;
+            JwsSigner::RS256 { skey, digest: _ }
+            | JwsSigner::RS384 { skey, digest: _ }
+            | JwsSigner::RS512 { skey, digest: _ }
+            | JwsSigner::PS256 { skey, digest: _ }
+            | JwsSigner::PS384 { skey, digest: _ }
+            | JwsSigner::PS512 { skey, digest: _ } => {
+                // These must be the minimal big endian representation without leading
+                // zeros. https://datatracker.ietf.org/doc/html/rfc7518#section-6.3.1.1
+                let public_key_n = skey.n().to_vec();
+                let public_key_e = skey.e().to_vec();
 
                 Ok(Jwk::RSA {
                     n: Base64UrlSafeData(public_key_n),
                     e: Base64UrlSafeData(public_key_e),
-                    alg: Some(JwaAlg::RS256),
+                    alg: Some(self.alg()?.into()),
+                    use_: Some(JwkUse::Sig),
+                    kid: kid.map(str::to_string),
+                })
+            }
+            JwsSigner::HS256 { skey: _, digest: _ }
+            | JwsSigner::HS384 { skey: _, digest: _ }
+            | JwsSigner::HS512 { skey: _, digest: _ } => Err(JwtError::JwkPublicKeyDenied),
+            JwsSigner::EdDSA { skey } => {
+                let crv = OkpCurve::from_id(skey.id()).ok_or(JwtError::InvalidKey)?;
+
+                let public_key_x = skey
+                    .raw_public_key()
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+                Ok(Jwk::OKP {
+                    crv,
+                    x: Base64UrlSafeData(public_key_x),
+                    alg: Some(JwaAlg::EdDSA.into()),
                     use_: Some(JwkUse::Sig),
                     kid: kid.map(str::to_string),
                 })
             }
-            JwsSigner::HS256 { skey: _, digest: _ } => Err(JwtError::JwkPublicKeyDenied),
         }
     }
+
+    /// Compute the OpenID Connect at_hash or c_hash of this access token or authorization
+    /// code, using the hash function of this signer's algorithm.
+    pub fn oidc_token_hash(&self, value: &str) -> Result<String, JwtError> {
+        let digest = match self {
+            JwsSigner::ES256 { digest, .. }
+            | JwsSigner::ES384 { digest, .. }
+            | JwsSigner::ES512 { digest, .. }
+            | JwsSigner::RS256 { digest, .. }
+            | JwsSigner::RS384 { digest, .. }
+            | JwsSigner::RS512 { digest, .. }
+            | JwsSigner::PS256 { digest, .. }
+            | JwsSigner::PS384 { digest, .. }
+            | JwsSigner::PS512 { digest, .. }
+            | JwsSigner::HS256 { digest, .. }
+            | JwsSigner::HS384 { digest, .. }
+            | JwsSigner::HS512 { digest, .. } => hash_value(*digest, value.as_bytes())?,
+            JwsSigner::EdDSA { skey } => eddsa_hash_value(skey.id(), value.as_bytes())?,
+        };
+        Ok(oidc_left_half(digest))
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Jwk, JwsCompact, JwsInner, JwsSigner, JwsValidator};
+    use super::{
+        CriticalExtensions, EcCurve, JwaAlg, Jwk, JwkAlg, JwkKeySet, JwsCompact, JwsHeader,
+        JwsInner, JwsJson, JwsSignaturePolicy, JwsSigner, JwsValidator, JwsValidatorSelector,
+        OkpCurve,
+    };
+    use crate::error::JwtError;
+    use openssl::{hash, pkey, sign};
     use std::convert::TryFrom;
     use std::str::FromStr;
 
@@ -895,15 +2694,149 @@ mod tests {
 
         let jwss = JwsSigner::from_es256_der(&der).expect("Failed to restore signer");
 
-        // This time we'll add the jwk pubkey and show it being used with the validator.
-        let jws = JwsInner::new(vec![0, 1, 2, 3, 4])
-            .set_kid("abcd".to_string())
-            .set_typ("abcd".to_string())
-            .set_cty("abcd".to_string());
+        // This time we'll add the jwk pubkey and show it being used with the validator.
+        let jws = JwsInner::new(vec![0, 1, 2, 3, 4])
+            .set_kid("abcd".to_string())
+            .set_typ("abcd".to_string())
+            .set_cty("abcd".to_string());
+
+        let jwsc = jws.sign_embed_public_jwk(&jwss).expect("Failed to sign");
+
+        assert!(jwsc.get_jwk_pubkey_url().is_none());
+        let pub_jwk = jwsc.get_jwk_pubkey().expect("No embeded public jwk!");
+        assert!(*pub_jwk == jwss.public_key_as_jwk(None).unwrap());
+
+        let jws_validator = JwsValidator::try_from(pub_jwk).expect("Unable to create validator");
+
+        let released = jwsc
+            .validate(&jws_validator)
+            .expect("Unable to validate jws");
+        assert!(released.payload() == [0, 1, 2, 3, 4]);
+    }
+
+    // https://datatracker.ietf.org/doc/html/rfc7520#section-4.3
+    #[test]
+    fn rfc7520_es512_validation_example() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let test_jws = "eyJhbGciOiJFUzUxMiIsImtpZCI6ImJpbGJvLmJhZ2dpbnNAaG9iYml0b24uZXhhbXBsZSJ9.SXTigJlzIGEgZGFuZ2Vyb3VzIGJ1c2luZXNzLCBGcm9kbywgZ29pbmcgb3V0IHlvdXIgZG9vci4gWW91IHN0ZXAgb250byB0aGUgcm9hZCwgYW5kIGlmIHlvdSBkb24ndCBrZWVwIHlvdXIgZmVldCwgdGhlcmXigJlzIG5vIGtub3dpbmcgd2hlcmUgeW91IG1pZ2h0IGJlIHN3ZXB0IG9mZiB0by4.AE_R_YZCChjn4791jSQCrdPZCNYqHXCTZH0-JZGYNlaAjP2kqaluUIIUnC9qvbu9Plon7KRTzoNEuT4Va2cmL1eJAQy3mtPBu_u_sDDyYjnAMDxXPn7XrT0lw-kvAD890jl8e2puQens_IEKBpHABlsbEPX6sFY8OcGDqoRuBomu9xQ2";
+
+        let jwsc = JwsCompact::from_str(test_jws).unwrap();
+
+        assert!(jwsc.to_string() == test_jws);
+        assert!(jwsc.get_jwk_kid() == Some("bilbo.baggins@hobbiton.example"));
+
+        let pkey = r#"{
+            "kty": "EC",
+            "kid": "bilbo.baggins@hobbiton.example",
+            "use": "sig",
+            "crv": "P-521",
+            "x": "AHKZLLOsCOzz5cY97ewNUajB957y-C-U88c3v13nmGZx6sYl_oJXu9A5RkTKqjqvjyekWF-7ytDyRXYgCF5cj0Kt",
+            "y": "AdymlHvOiLxXkEhayXQnNCvDX4h9htZaCJN34kfmC6pV5OhQHiraVySsUdaQkAgDPrwQrJmbnX9cwlGfP-HqHZR1"
+        }"#;
+
+        let pkey: Jwk = serde_json::from_str(pkey).expect("Invalid JWK");
+        trace!("jwk -> {:?}", pkey);
+
+        let jws_validator = JwsValidator::try_from(&pkey).expect("Unable to create validator");
+
+        let released = jwsc
+            .validate(&jws_validator)
+            .expect("Unable to validate jws");
+        assert!(released.payload() == "It\u{2019}s a dangerous business, Frodo, going out your door. You step onto the road, and if you don't keep your feet, there\u{2019}s no knowing where you might be swept off to.".as_bytes());
+    }
+
+    #[test]
+    fn rfc7520_es512_signature_example() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let jwss = JwsSigner::from_ec_jwk_components(
+            EcCurve::P521,
+            "AHKZLLOsCOzz5cY97ewNUajB957y-C-U88c3v13nmGZx6sYl_oJXu9A5RkTKqjqvjyekWF-7ytDyRXYgCF5cj0Kt",
+            "AdymlHvOiLxXkEhayXQnNCvDX4h9htZaCJN34kfmC6pV5OhQHiraVySsUdaQkAgDPrwQrJmbnX9cwlGfP-HqHZR1",
+            "AAhRON2r9cqXX1hg-RoI6R1tX5p2rUAYdmpHZoC1XNM56KtscrX6zbKipQrCW9CGZH3T4ubpnoTKLDYJ_fF3_rJt",
+        )
+        .expect("failed to construct signer");
+
+        let jws = JwsInner::new(b"It\xe2\x80\x99s a dangerous business, Frodo".to_vec())
+            .set_kid("bilbo.baggins@hobbiton.example".to_string());
+
+        let jwsc = jws.sign(&jwss).expect("Failed to sign");
+
+        // The signature is always the fixed width r || s for P-521.
+        assert!(jwsc.signature.len() == 132);
+
+        let pub_jwk = jwss.public_key_as_jwk(None).unwrap();
+        assert!(matches!(
+            pub_jwk,
+            Jwk::EC {
+                crv: EcCurve::P521,
+                alg: Some(JwkAlg::Jws(JwaAlg::ES512)),
+                ..
+            }
+        ));
+
+        let jws_validator = JwsValidator::try_from(&pub_jwk).expect("Unable to create validator");
+
+        let released = jwsc
+            .validate(&jws_validator)
+            .expect("Unable to validate jws");
+        trace!("rel -> {:?}", released);
+    }
+
+    #[test]
+    fn es384_key_generate_cycle() {
+        let jwss = JwsSigner::generate_es384().expect("failed to construct signer.");
+
+        let der = jwss.private_key_to_der().expect("Failed to extract DER");
+
+        // A P-384 key must not be usable as any other curve.
+        assert!(matches!(
+            JwsSigner::from_es256_der(&der),
+            Err(JwtError::InvalidKey)
+        ));
+        assert!(matches!(
+            JwsSigner::from_es512_der(&der),
+            Err(JwtError::InvalidKey)
+        ));
+
+        let jwss = JwsSigner::from_es384_der(&der).expect("Failed to restore signer");
+
+        let jws = JwsInner::new(vec![0, 1, 2, 3, 4]).set_kid("abcd".to_string());
+
+        let jwsc = jws.sign_embed_public_jwk(&jwss).expect("Failed to sign");
+        assert!(jwsc.signature.len() == 96);
+
+        let pub_jwk = jwsc.get_jwk_pubkey().expect("No embeded public jwk!");
+        assert!(*pub_jwk == jwss.public_key_as_jwk(None).unwrap());
+
+        let jws_validator = JwsValidator::try_from(pub_jwk).expect("Unable to create validator");
+
+        let released = jwsc
+            .validate(&jws_validator)
+            .expect("Unable to validate jws");
+        assert!(released.payload() == [0, 1, 2, 3, 4]);
+
+        // An ES256 validator must not accept this token.
+        let es256_validator = JwsSigner::generate_es256()
+            .and_then(|s| s.get_validator())
+            .expect("Unable to create validator");
+        assert!(matches!(
+            jwsc.validate(&es256_validator),
+            Err(JwtError::ValidatorAlgMismatch)
+        ));
+    }
+
+    #[test]
+    fn es512_key_generate_cycle() {
+        let jwss = JwsSigner::generate_es512().expect("failed to construct signer.");
+
+        let der = jwss.private_key_to_der().expect("Failed to extract DER");
+
+        let jwss = JwsSigner::from_es512_der(&der).expect("Failed to restore signer");
+
+        let jws = JwsInner::new(vec![0, 1, 2, 3, 4]).set_kid("abcd".to_string());
 
         let jwsc = jws.sign_embed_public_jwk(&jwss).expect("Failed to sign");
 
-        assert!(jwsc.get_jwk_pubkey_url().is_none());
         let pub_jwk = jwsc.get_jwk_pubkey().expect("No embeded public jwk!");
         assert!(*pub_jwk == jwss.public_key_as_jwk(None).unwrap());
 
@@ -912,7 +2845,7 @@ mod tests {
         let released = jwsc
             .validate(&jws_validator)
             .expect("Unable to validate jws");
-        assert!(released.payload() == &[0, 1, 2, 3, 4]);
+        assert!(released.payload() == [0, 1, 2, 3, 4]);
     }
 
     // RSA3072
@@ -976,6 +2909,61 @@ mod tests {
         trace!("rel -> {:?}", released);
     }
 
+    // https://datatracker.ietf.org/doc/html/rfc7638#section-3.1
+    #[test]
+    fn rfc7638_rsa_thumbprint_example() {
+        let pkey = r#"{
+            "kty": "RSA",
+            "n": "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw",
+            "e": "AQAB",
+            "alg": "RS256",
+            "kid": "2011-04-29"
+        }"#;
+        let pkey: Jwk = serde_json::from_str(pkey).expect("Invalid JWK");
+
+        let thumbprint = pkey
+            .thumbprint(hash::MessageDigest::sha256())
+            .expect("Unable to compute thumbprint");
+        assert!(thumbprint.to_string() == "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs");
+    }
+
+    #[test]
+    fn ec_thumbprint_kid() {
+        let jwss = JwsSigner::generate_es256().expect("failed to construct signer.");
+
+        let pub_jwk = jwss
+            .public_key_as_jwk_thumbprint_kid()
+            .expect("Unable to export jwk");
+
+        let thumbprint = pub_jwk
+            .thumbprint(hash::MessageDigest::sha256())
+            .expect("Unable to compute thumbprint");
+
+        // The thumbprint ignores the kid, alg and use, so it is stable.
+        let plain_jwk = jwss.public_key_as_jwk(Some("other")).unwrap();
+        assert!(
+            plain_jwk
+                .thumbprint(hash::MessageDigest::sha256())
+                .expect("Unable to compute thumbprint")
+                == thumbprint
+        );
+
+        match pub_jwk {
+            Jwk::EC { kid, .. } => assert!(kid == Some(thumbprint.to_string())),
+            _ => panic!("Invalid key type"),
+        }
+
+        // Different digests give different thumbprints.
+        assert!(
+            plain_jwk
+                .thumbprint(hash::MessageDigest::sha512())
+                .expect("Unable to compute thumbprint")
+                .0
+                .len()
+                == 64
+        );
+    }
+
     #[test]
     fn rs256_key_generate_cycle() {
         let _ = tracing_subscriber::fmt().try_init();
@@ -1002,12 +2990,380 @@ mod tests {
         let released = jwsc
             .validate(&jws_validator)
             .expect("Unable to validate jws");
-        assert!(released.payload() == &[0, 1, 2, 3, 4]);
+        assert!(released.payload() == [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn rs384_rs512_key_generate_cycle() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let jwss = JwsSigner::generate_legacy_rs384().expect("failed to construct signer.");
+        let der = jwss.private_key_to_der().expect("Failed to extract DER");
+
+        let rs384 = JwsSigner::from_rs384_der(&der).expect("Failed to restore signer");
+        let rs512 = JwsSigner::from_rs512_der(&der).expect("Failed to restore signer");
+
+        let jwsc_384 = JwsInner::new(vec![0, 1, 2, 3, 4])
+            .sign_embed_public_jwk(&rs384)
+            .expect("Failed to sign");
+        let jwsc_512 = JwsInner::new(vec![0, 1, 2, 3, 4])
+            .sign_embed_public_jwk(&rs512)
+            .expect("Failed to sign");
+
+        assert!(jwsc_384.header.alg == JwaAlg::RS384);
+        assert!(jwsc_512.header.alg == JwaAlg::RS512);
+
+        let validator_384 = JwsValidator::try_from(jwsc_384.get_jwk_pubkey().unwrap())
+            .expect("Unable to create validator");
+        let validator_512 = JwsValidator::try_from(jwsc_512.get_jwk_pubkey().unwrap())
+            .expect("Unable to create validator");
+
+        assert!(jwsc_384.validate(&validator_384).is_ok());
+        assert!(jwsc_512.validate(&validator_512).is_ok());
+
+        // The same key with a different digest must be rejected.
+        assert!(matches!(
+            jwsc_384.validate(&validator_512),
+            Err(JwtError::ValidatorAlgMismatch)
+        ));
+        assert!(matches!(
+            jwsc_512.validate(&validator_384),
+            Err(JwtError::ValidatorAlgMismatch)
+        ));
+    }
+
+    #[test]
+    fn ps256_key_generate_cycle() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let jwss = JwsSigner::generate_ps256().expect("failed to construct signer.");
+
+        let der = jwss.private_key_to_der().expect("Failed to extract DER");
+
+        let jwss = JwsSigner::from_ps256_der(&der).expect("Failed to restore signer");
+
+        let jws = JwsInner::new(vec![0, 1, 2, 3, 4])
+            .set_kid("abcd".to_string())
+            .set_typ("abcd".to_string())
+            .set_cty("abcd".to_string());
+
+        let jwsc = jws.sign_embed_public_jwk(&jwss).expect("Failed to sign");
+
+        assert!(jwsc.header.alg == JwaAlg::PS256);
+        let pub_jwk = jwsc.get_jwk_pubkey().expect("No embeded public jwk!");
+        assert!(*pub_jwk == jwss.public_key_as_jwk(None).unwrap());
+        assert!(matches!(
+            pub_jwk,
+            Jwk::RSA {
+                alg: Some(JwkAlg::Jws(JwaAlg::PS256)),
+                ..
+            }
+        ));
+
+        let jws_validator = JwsValidator::try_from(pub_jwk).expect("Unable to create validator");
+
+        let released = jwsc
+            .validate(&jws_validator)
+            .expect("Unable to validate jws");
+        assert!(released.payload() == [0, 1, 2, 3, 4]);
+
+        // The same key with PKCS1 padding must not accept this token.
+        let rs256_validator = JwsSigner::from_rs256_der(&der)
+            .and_then(|s| s.get_validator())
+            .expect("Unable to create validator");
+        assert!(matches!(
+            jwsc.validate(&rs256_validator),
+            Err(JwtError::ValidatorAlgMismatch)
+        ));
+    }
+
+    #[test]
+    fn ps384_ps512_sign_and_validate() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let jwss = JwsSigner::generate_ps384().expect("failed to construct signer.");
+        let der = jwss.private_key_to_der().expect("Failed to extract DER");
+
+        for (jwss, alg) in [
+            (
+                JwsSigner::from_ps384_der(&der).expect("Failed to restore signer"),
+                JwaAlg::PS384,
+            ),
+            (
+                JwsSigner::from_ps512_der(&der).expect("Failed to restore signer"),
+                JwaAlg::PS512,
+            ),
+        ] {
+            let jwsc = JwsInner::new(vec![0, 1, 2, 3, 4])
+                .sign(&jwss)
+                .expect("Failed to sign");
+            assert!(jwsc.header.alg == alg);
+
+            let pub_jwk = jwss.public_key_as_jwk(None).unwrap();
+            let jws_validator =
+                JwsValidator::try_from(&pub_jwk).expect("Unable to create validator");
+
+            let released = jwsc
+                .validate(&jws_validator)
+                .expect("Unable to validate jws");
+            assert!(released.payload() == [0, 1, 2, 3, 4]);
+        }
+    }
+
+    // https://datatracker.ietf.org/doc/html/rfc8037#appendix-A.4
+    #[test]
+    fn rfc8037_ed25519_signature_example() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let jwss = JwsSigner::from_okp_jwk_components(
+            OkpCurve::Ed25519,
+            "nWGxne_9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A",
+        )
+        .expect("failed to construct signer");
+
+        // A.2 - the public key is derived from the private key.
+        let pub_jwk = jwss.public_key_as_jwk(None).unwrap();
+        let pkey =
+            r#"{"kty":"OKP","crv":"Ed25519","x":"11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"}"#;
+        let pkey: Jwk = serde_json::from_str(pkey).expect("Invalid JWK");
+        match (&pub_jwk, &pkey) {
+            (Jwk::OKP { crv: a, x: ax, .. }, Jwk::OKP { crv: b, x: bx, .. }) => {
+                assert!(a == b);
+                assert!(ax == bx);
+            }
+            _ => panic!("Invalid key type"),
+        }
+
+        // Ed25519 is deterministic, so this must exactly match the example.
+        let jwsc = JwsInner::new(b"Example of Ed25519 signing".to_vec())
+            .sign(&jwss)
+            .expect("Failed to sign");
+
+        assert!(jwsc.to_string() == "eyJhbGciOiJFZERTQSJ9.RXhhbXBsZSBvZiBFZDI1NTE5IHNpZ25pbmc.hgyY0il_MGCjP0JzlnLWG1PPOt7-09PGcvMg3AIbQR6dWbhijcNR4ki4iylGjg5BhVsPt9g7sVvpAr_MuM0KAg");
+
+        // A.5 - verification.
+        let jws_validator = JwsValidator::try_from(&pkey).expect("Unable to create validator");
+        let released = jwsc
+            .validate(&jws_validator)
+            .expect("Unable to validate jws");
+        assert!(released.payload() == b"Example of Ed25519 signing");
+
+        // A.6 - a key set with an X25519 key alongside the Ed25519 key still parses, and
+        // the X25519 key is never used to validate.
+        let jwks: JwkKeySet = serde_json::from_str(
+            r#"{"keys":[
+                {"kty":"OKP","crv":"Ed25519","x":"11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"},
+                {"kty":"OKP","crv":"X25519","kid":"Bob","x":"3p7bfXt9wbTTW2HC7OQ1Nz-DQ8hbeGdNrfx-FG-IK08"}
+            ]}"#,
+        )
+        .expect("Invalid JWKS");
+        assert!(matches!(
+            JwsValidator::try_from(&jwks.keys[1]),
+            Err(JwtError::InvalidKey)
+        ));
+        assert!(jwks
+            .select_validator(None, &JwaAlg::EdDSA)
+            .and_then(|jws_validator| jwsc.validate(&jws_validator))
+            .is_ok());
+    }
+
+    #[test]
+    fn rfc8037_ed25519_validation_example() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let test_jws = "eyJhbGciOiJFZERTQSJ9.RXhhbXBsZSBvZiBFZDI1NTE5IHNpZ25pbmc.hgyY0il_MGCjP0JzlnLWG1PPOt7-09PGcvMg3AIbQR6dWbhijcNR4ki4iylGjg5BhVsPt9g7sVvpAr_MuM0KAg";
+
+        let jwsc = JwsCompact::from_str(test_jws).unwrap();
+        assert!(jwsc.to_string() == test_jws);
+
+        let pkey =
+            r#"{"kty":"OKP","crv":"Ed25519","x":"11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"}"#;
+        let pkey: Jwk = serde_json::from_str(pkey).expect("Invalid JWK");
+        let jws_validator = JwsValidator::try_from(&pkey).expect("Unable to create validator");
+
+        jwsc.validate(&jws_validator)
+            .expect("Unable to validate jws");
+
+        // A tampered payload must be rejected.
+        let tampered = "eyJhbGciOiJFZERTQSJ9.SXhhbXBsZSBvZiBFZDI1NTE5IHNpZ25pbmc.hgyY0il_MGCjP0JzlnLWG1PPOt7-09PGcvMg3AIbQR6dWbhijcNR4ki4iylGjg5BhVsPt9g7sVvpAr_MuM0KAg";
+        let jwsc = JwsCompact::from_str(tampered).unwrap();
+        assert!(matches!(
+            jwsc.validate(&jws_validator),
+            Err(JwtError::InvalidSignature)
+        ));
+    }
+
+    // https://datatracker.ietf.org/doc/html/rfc8037#appendix-A.3
+    #[test]
+    fn rfc8037_ed25519_thumbprint_example() {
+        let pkey =
+            r#"{"kty":"OKP","crv":"Ed25519","x":"11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"}"#;
+        let pkey: Jwk = serde_json::from_str(pkey).expect("Invalid JWK");
+
+        let thumbprint = pkey
+            .thumbprint(hash::MessageDigest::sha256())
+            .expect("Unable to compute thumbprint");
+        assert!(thumbprint.to_string() == "kPrK_qmxVWaYVA9wwBF6Iuo3vVzz7TxHCTwXBygrS4k");
+    }
+
+    #[test]
+    fn ed25519_key_generate_cycle() {
+        let jwss = JwsSigner::generate_ed25519().expect("failed to construct signer.");
+
+        let der = jwss.private_key_to_der().expect("Failed to extract DER");
+
+        assert!(JwsSigner::from_ed448_der(&der).is_err());
+        let jwss = JwsSigner::from_ed25519_der(&der).expect("Failed to restore signer");
+
+        let jws = JwsInner::new(vec![0, 1, 2, 3, 4]).set_kid("abcd".to_string());
+
+        let jwsc = jws.sign_embed_public_jwk(&jwss).expect("Failed to sign");
+
+        let pub_jwk = jwsc.get_jwk_pubkey().expect("No embeded public jwk!");
+        assert!(*pub_jwk == jwss.public_key_as_jwk(None).unwrap());
+
+        let jws_validator = JwsValidator::try_from(pub_jwk).expect("Unable to create validator");
+
+        let released = jwsc
+            .validate(&jws_validator)
+            .expect("Unable to validate jws");
+        assert!(released.payload() == [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn ed448_key_generate_cycle() {
+        let jwss = JwsSigner::generate_ed448().expect("failed to construct signer.");
+
+        let der = jwss.private_key_to_der().expect("Failed to extract DER");
+
+        let jwss = JwsSigner::from_ed448_der(&der).expect("Failed to restore signer");
+
+        let jwsc = JwsInner::new(vec![0, 1, 2, 3, 4])
+            .sign(&jwss)
+            .expect("Failed to sign");
+        assert!(jwsc.signature.len() == 114);
+
+        let pub_jwk = jwss.public_key_as_jwk(None).unwrap();
+        assert!(matches!(
+            pub_jwk,
+            Jwk::OKP {
+                crv: OkpCurve::Ed448,
+                ..
+            }
+        ));
+
+        // Check the JWK round trips through serialisation.
+        let pub_jwk_str = serde_json::to_string(&pub_jwk).unwrap();
+        assert!(pub_jwk_str.contains(r#""kty":"OKP""#));
+        let pub_jwk: Jwk = serde_json::from_str(&pub_jwk_str).unwrap();
+
+        let jws_validator = JwsValidator::try_from(&pub_jwk).expect("Unable to create validator");
+        jwsc.validate(&jws_validator)
+            .expect("Unable to validate jws");
+
+        let jws_validator = jwss.get_validator().expect("Unable to create validator");
+        jwsc.validate(&jws_validator)
+            .expect("Unable to validate jws");
     }
 
     // A test for the signer to/from der.
     // directly get the validator from the signer.
 
+    #[test]
+    fn hs256_invalid_signature_rejected() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let jwss = JwsSigner::generate_hs256().expect("failed to construct signer.");
+        let jws_validator = jwss.get_validator().expect("Unable to create validator");
+
+        let jwsc = JwsInner::new(vec![0, 1, 2, 3, 4])
+            .sign(&jwss)
+            .expect("Failed to sign");
+        assert!(jwsc.validate(&jws_validator).is_ok());
+
+        // Truncated, extended and empty signatures.
+        for len in [0, 1, 31, 33, 64] {
+            let mut bad = jwsc.clone();
+            bad.signature.resize(len, 0);
+            assert!(matches!(
+                bad.validate(&jws_validator),
+                Err(JwtError::InvalidSignature)
+            ));
+        }
+
+        // A single bit flipped in the first, middle and last byte.
+        for idx in [0, 16, 31] {
+            let mut bad = jwsc.clone();
+            bad.signature[idx] ^= 0x01;
+            assert!(matches!(
+                bad.validate(&jws_validator),
+                Err(JwtError::InvalidSignature)
+            ));
+        }
+    }
+
+    #[test]
+    fn hs384_hs512_sign_and_validate() {
+        let _ = tracing_subscriber::fmt().try_init();
+        for (jwss, alg) in [
+            (JwsSigner::generate_hs384().unwrap(), JwaAlg::HS384),
+            (JwsSigner::generate_hs512().unwrap(), JwaAlg::HS512),
+        ] {
+            let jwsc = JwsInner::new(vec![0, 1, 2, 3, 4])
+                .sign(&jwss)
+                .expect("Failed to sign");
+            assert!(jwsc.header.alg == alg);
+
+            let jws_validator = jwss.get_validator().expect("Unable to create validator");
+            let released = jwsc
+                .validate(&jws_validator)
+                .expect("Unable to validate jws");
+            assert!(released.payload() == [0, 1, 2, 3, 4]);
+        }
+    }
+
+    #[test]
+    fn hmac_alg_derived_from_digest() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let skey = match JwsSigner::generate_hs512().unwrap() {
+            JwsSigner::HS512 { skey, digest: _ } => skey,
+            _ => unreachable!(),
+        };
+
+        // The header must reflect the digest actually in use, not only the variant.
+        let jwss = JwsSigner::HS256 {
+            skey: skey.clone(),
+            digest: hash::MessageDigest::sha512(),
+        };
+        let jwsc = JwsInner::new(vec![0, 1, 2, 3, 4])
+            .sign(&jwss)
+            .expect("Failed to sign");
+        assert!(jwsc.header.alg == JwaAlg::HS512);
+
+        // A validator for SHA-256 with the same key must reject it.
+        let jws_validator = JwsValidator::HS256 {
+            skey: skey.clone(),
+            digest: hash::MessageDigest::sha256(),
+        };
+        assert!(matches!(
+            jwsc.validate(&jws_validator),
+            Err(JwtError::ValidatorAlgMismatch)
+        ));
+
+        let jws_validator = JwsValidator::HS512 {
+            skey,
+            digest: hash::MessageDigest::sha512(),
+        };
+        assert!(jwsc.validate(&jws_validator).is_ok());
+
+        // Unsupported digests are refused outright.
+        let jwss = JwsSigner::HS256 {
+            skey: match JwsSigner::generate_hs256().unwrap() {
+                JwsSigner::HS256 { skey, digest: _ } => skey,
+                _ => unreachable!(),
+            },
+            digest: hash::MessageDigest::sha1(),
+        };
+        assert!(matches!(
+            JwsInner::new(vec![0]).sign(&jwss),
+            Err(JwtError::ValidatorAlgMismatch)
+        ));
+    }
+
     #[test]
     fn rfc7519_hs256_validation_example() {
         let _ = tracing_subscriber::fmt().try_init();
@@ -1053,4 +3409,329 @@ mod tests {
             .expect("Unable to validate jws");
         trace!("rel -> {:?}", released);
     }
+
+    #[test]
+    fn oidc_token_hash() {
+        let _ = tracing_subscriber::fmt().try_init();
+        // https://openid.net/specs/openid-connect-core-1_0.html#code-id_tokenExample
+        let code = "Qcb0Orv1zh30vL1MPRsbm-diHiMwcLyZvn1arpZv-Jxf_11jnpEX3Tgfvk";
+
+        let jwss = JwsSigner::generate_legacy_rs256().expect("failed to construct signer.");
+        assert!(jwss.oidc_token_hash(code).expect("Unable to hash") == "LDktKdoQak3Pk0cnXxCltA");
+
+        let jwsv = jwss.get_validator().expect("Unable to create validator");
+        assert!(jwsv.oidc_token_hash(code).expect("Unable to hash") == "LDktKdoQak3Pk0cnXxCltA");
+
+        // The hash length follows the digest of the algorithm.
+        let jwss = JwsSigner::generate_es512().expect("failed to construct signer.");
+        assert!(jwss.oidc_token_hash(code).expect("Unable to hash").len() == 43);
+
+        let jwss = JwsSigner::generate_hs384().expect("failed to construct signer.");
+        assert!(jwss.oidc_token_hash(code).expect("Unable to hash").len() == 32);
+
+        // Ed25519 uses SHA-512, and Ed448 uses SHAKE256 with a 114 byte output.
+        let jwss = JwsSigner::generate_ed25519().expect("failed to construct signer.");
+        assert!(jwss.oidc_token_hash(code).expect("Unable to hash").len() == 43);
+
+        let jwss = JwsSigner::generate_ed448().expect("failed to construct signer.");
+        let jwsv = jwss.get_validator().expect("Unable to create validator");
+        let hash = jwss.oidc_token_hash(code).expect("Unable to hash");
+        assert!(hash.len() == 76);
+        assert!(jwsv.oidc_token_hash(code).expect("Unable to hash") == hash);
+    }
+
+    #[test]
+    fn protected_header_view() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let hdr = base64::encode_config(
+            r#"{"alg":"ES256","typ":"at+jwt","cty":"JWT","kid":"a","jku":"https://idm.example.com/jwks","crit":[],"x-tenant":"acme","x5c":["MIIB"]}"#,
+            base64::URL_SAFE_NO_PAD,
+        );
+        let input = format!("{}.e30.AAAA", hdr);
+
+        let jwsc = JwsCompact::from_str(&input).expect("Invalid compact jws");
+        let header = jwsc.header();
+        assert!(header.alg() == &JwaAlg::ES256);
+        assert!(header.typ() == Some("at+jwt"));
+        assert!(header.cty() == Some("JWT"));
+        assert!(header.kid() == Some("a"));
+        assert!(header.jku().map(|u| u.as_str()) == Some("https://idm.example.com/jwks"));
+        assert!(header.jwk().is_none());
+        assert!(header.crit().is_empty());
+        assert!(header.extra().get("x-tenant") == Some(&serde_json::json!("acme")));
+        assert!(header.extra().get("x5c") == Some(&serde_json::json!(["MIIB"])));
+        assert!(!header.extra().contains_key("kid"));
+
+        // The token is written back exactly as it was parsed.
+        assert!(jwsc.to_string() == input);
+    }
+
+    #[test]
+    fn x5c_header_resign() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let skey = [7u8; 32];
+        let jws_signer = JwsSigner::from_hs256_raw(&skey).expect("Unable to create signer");
+        let jws_validator = jws_signer
+            .get_validator()
+            .expect("Unable to create validator");
+
+        // Our header doesn't allow x5c to be set, so sign this token by hand.
+        let hdr = base64::encode_config(
+            r#"{"alg":"HS256","kid":"a","x-tenant":"acme","x5c":["MIIB"],"x5t#S256":"AAAA"}"#,
+            base64::URL_SAFE_NO_PAD,
+        );
+        let sign_input = format!("{}.e30", hdr);
+        let key = pkey::PKey::hmac(&skey).expect("Invalid key");
+        let mut signer =
+            sign::Signer::new(hash::MessageDigest::sha256(), &key).expect("Unable to sign");
+        let sig = signer
+            .sign_oneshot_to_vec(sign_input.as_bytes())
+            .expect("Unable to sign");
+        let input = format!(
+            "{}.{}",
+            sign_input,
+            base64::encode_config(sig, base64::URL_SAFE_NO_PAD)
+        );
+
+        let jwsc = JwsCompact::from_str(&input).expect("Invalid compact jws");
+        let released = jwsc.validate(&jws_validator).expect("Unable to validate");
+
+        // The released header can be signed again, keeping the extension but not x5c.
+        let resigned = released.sign(&jws_signer).expect("Unable to resign");
+        let header = resigned.header();
+        assert!(header.kid() == Some("a"));
+        assert!(header.extra().get("x-tenant") == Some(&serde_json::json!("acme")));
+        assert!(!header.extra().contains_key("x5c"));
+        assert!(!header.extra().contains_key("x5t#S256"));
+        assert!(resigned.validate(&jws_validator).is_ok());
+    }
+
+    #[test]
+    fn critical_extension_checks() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let critical = CriticalExtensions::new().register("x-tenant", |_| Ok(()));
+
+        let parse = |hdr: &str| {
+            let hdr = base64::encode_config(hdr, base64::URL_SAFE_NO_PAD);
+            JwsCompact::from_str_with_critical(&format!("{}.e30.AAAA", hdr), &critical)
+        };
+
+        assert!(parse(r#"{"alg":"ES256","crit":["x-tenant"],"x-tenant":"acme"}"#).is_ok());
+
+        // Understood, but not present.
+        assert!(matches!(
+            parse(r#"{"alg":"ES256","crit":["x-tenant"]}"#),
+            Err(JwtError::CriticalExtensionMissing { name }) if name == "x-tenant"
+        ));
+
+        // Present, but not understood.
+        assert!(matches!(
+            parse(r#"{"alg":"ES256","crit":["x-tenant","x-other"],"x-tenant":"acme","x-other":1}"#),
+            Err(JwtError::CriticalExtension { name }) if name == "x-other"
+        ));
+
+        // Registered header parameters can never be critical.
+        assert!(matches!(
+            parse(r#"{"alg":"ES256","crit":["kid"],"kid":"a"}"#),
+            Err(JwtError::CriticalExtension { name }) if name == "kid"
+        ));
+    }
+
+    #[test]
+    fn rfc7797_unencoded_payload() {
+        let _ = tracing_subscriber::fmt().try_init();
+        // https://datatracker.ietf.org/doc/html/rfc7797#section-4
+        let skey = base64::decode_config(
+            "AyM1SysPpbyDfgZld3umj1qzKObwVMkoqQ-EstJQLr_T-1qS0gZH75aKtMN3Yj0iPS4hcgUuTwjAzZr1Z9CAow",
+            base64::URL_SAFE_NO_PAD,
+        )
+        .expect("Invalid key");
+        let jws_signer = JwsSigner::from_hs256_raw(&skey).expect("Unable to create signer");
+        let jws_validator = jws_signer
+            .get_validator()
+            .expect("Unable to create validator");
+        let critical = CriticalExtensions::default();
+
+        // Example 1, the payload is encoded.
+        let jwsc = JwsInner::new(b"$.02".to_vec())
+            .sign(&jws_signer)
+            .expect("Unable to sign");
+        assert!(
+            jwsc.to_string()
+                == "eyJhbGciOiJIUzI1NiJ9.JC4wMg.5mvfOroL-g7HyqJoozehmsaqmvTYGEq5jTI1gVvoEoQ"
+        );
+
+        // Example 2, the payload is unencoded and detached.
+        let example = "eyJhbGciOiJIUzI1NiIsImI2NCI6ZmFsc2UsImNyaXQiOlsiYjY0Il19..A5dxf2s96_n5FLueVuW1Z_vh161FwXZC4YLPff6dmDY";
+        let jwsc = JwsInner::new(b"$.02".to_vec())
+            .set_header(JwsHeader::new().set_unencoded_payload())
+            .sign_detached(&jws_signer)
+            .expect("Unable to sign");
+        assert!(jwsc.to_string() == example);
+
+        let jwsc = JwsCompact::from_str_detached(example, b"$.02", &critical)
+            .expect("Invalid compact jws");
+        assert!(!jwsc.header().b64());
+        assert!(jwsc.header().crit() == ["b64".to_string()]);
+        let released = jwsc
+            .validate(&jws_validator)
+            .expect("Unable to validate jws");
+        assert!(released.payload() == b"$.02");
+
+        let jwsc = JwsCompact::from_str_detached(example, b"$.03", &critical)
+            .expect("Invalid compact jws");
+        assert!(matches!(
+            jwsc.validate(&jws_validator),
+            Err(JwtError::InvalidSignature)
+        ));
+
+        // The same token with the payload attached.
+        let (hdr, sig) = example.split_once("..").unwrap();
+        let attached = format!("{}.$.02.{}", hdr, sig);
+        assert!(matches!(
+            JwsCompact::from_str(&attached),
+            Err(JwtError::InvalidCompactFormat)
+        ));
+
+        let jwsc = JwsInner::new(b"$02".to_vec())
+            .set_header(JwsHeader::new().set_unencoded_payload())
+            .sign(&jws_signer)
+            .expect("Unable to sign");
+        let attached = jwsc.to_string();
+        assert!(attached.split('.').nth(1) == Some("$02"));
+        let jwsc = JwsCompact::from_str(&attached).expect("Invalid compact jws");
+        let released = jwsc
+            .validate(&jws_validator)
+            .expect("Unable to validate jws");
+        assert!(released.payload() == b"$02");
+
+        // A period in an attached payload would change the structure of the token.
+        assert!(matches!(
+            JwsInner::new(b"$.02".to_vec())
+                .set_header(JwsHeader::new().set_unencoded_payload())
+                .sign(&jws_signer),
+            Err(JwtError::InvalidCompactFormat)
+        ));
+
+        // b64 must be listed as critical.
+        let hdr = base64::encode_config(r#"{"alg":"HS256","b64":false}"#, base64::URL_SAFE_NO_PAD);
+        assert!(matches!(
+            JwsCompact::from_str(&format!("{}.$02.AAAA", hdr)),
+            Err(JwtError::InvalidHeaderParameter { name }) if name == "b64"
+        ));
+        let hdr =
+            base64::encode_config(r#"{"alg":"HS256","crit":["b64"]}"#, base64::URL_SAFE_NO_PAD);
+        assert!(matches!(
+            JwsCompact::from_str(&format!("{}.e30.AAAA", hdr)),
+            Err(JwtError::CriticalExtensionMissing { name }) if name == "b64"
+        ));
+    }
+
+    // https://datatracker.ietf.org/doc/html/rfc7515#appendix-A.6
+    #[test]
+    fn rfc7515_json_serialization_example() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let payload = "eyJpc3MiOiJqb2UiLA0KICJleHAiOjEzMDA4MTkzODAsDQogImh0dHA6Ly9leGFtcGxlLmNvbS9pc19yb290Ijp0cnVlfQ";
+        let rs256_sig = r#"{"protected":"eyJhbGciOiJSUzI1NiJ9","header":{"kid":"2010-12-29"},"signature":"cC4hiUPoj9Eetdgtv3hF80EGrhuB__dzERat0XF9g2VtQgr9PJbu3XOiZj5RZmh7AAuHIm4Bh-0Qc_lF5YKt_O8W2Fp5jujGbds9uJdbF9CUAr7t1dnZcAcQjbKBYNX4BAynRFdiuB--f_nZLgrnbyTyWzO75vRK5h6xBArLIARNPvkSjtQBMHlb1L07Qe7K0GarZRmB_eSN9383LcOLn6_dO--xi12jzDwusC-eOkHWEsqtFZESc6BfI7noOPqvhJ1phCnvWh6IeYI2w9QOYEUipUTI8np6LbgGY9Fs98rqVt5AXLIhWkWywlVmtVrBp0igcN_IoypGlUPQGe77Rw"}"#;
+        let es256_sig = r#""protected":"eyJhbGciOiJFUzI1NiJ9","header":{"kid":"e9bc097a-ce51-4036-9562-d2ade882db0d"},"signature":"DtEhU3ljbEg8L38VWAfUAqOyKAM6-Xx-F4GawxaepmXFCgfTjDxw5djxLa8ISlSApmWQxfKTUJqPP3-Kg6NU1Q""#;
+        let general = format!(
+            r#"{{"payload":"{}","signatures":[{},{{{}}}]}}"#,
+            payload, rs256_sig, es256_sig
+        );
+
+        let rs256_key: Jwk = serde_json::from_str(r#"{
+            "kty":"RSA",
+            "kid":"2010-12-29",
+            "n":"ofgWCuLjybRlzo0tZWJjNiuSfb4p4fAkd_wWJcyQoTbji9k0l8W26mPddxHmfHQp-Vaw-4qPCJrcS2mJPMEzP1Pt0Bm4d4QlL-yRT-SFd2lZS-pCgNMsD1W_YpRPEwOWvG6b32690r2jZ47soMZo9wGzjb_7OMg0LOL-bSf63kpaSHSXndS5z5rexMdbBYUsLA9e-KXBdQOS-UTo7WTBEMa2R2CapHg665xsmtdVMTBQY4uDZlxvb3qCo5ZwKh9kG4LT6_I5IhlJH7aGhyxXFvUK-DWNmoudF8NAco9_h9iaGNj8q2ethFkMLs91kzk2PAcDTW9gb54h4FRWyuXpoQ",
+            "e":"AQAB"
+        }"#).expect("Invalid JWK");
+        let es256_key: Jwk = serde_json::from_str(r#"{"kty":"EC","kid":"e9bc097a-ce51-4036-9562-d2ade882db0d","crv":"P-256","x":"f83OJ3D2xF1Bg8vub9tLe1gHMzV76e8Tus9uPHvRVEU","y":"x_FEzRu9m36HLN_tue659LNpXW6pCyStikYjKIWI5a0"}"#).expect("Invalid JWK");
+        let both = JwkKeySet {
+            keys: vec![rs256_key, es256_key.clone()],
+        };
+        let only_es256 = JwkKeySet {
+            keys: vec![es256_key],
+        };
+
+        let jwsj = JwsJson::from_str_with_critical(&general, &CriticalExtensions::default())
+            .expect("Invalid json jws");
+        assert!(jwsj.to_string() == general);
+        let algs: Vec<_> = jwsj.headers().map(|h| h.alg().clone()).collect();
+        assert!(algs == [JwaAlg::RS256, JwaAlg::ES256]);
+
+        let released = jwsj
+            .validate(&both, JwsSignaturePolicy::All)
+            .expect("Unable to validate jws");
+        assert!(released.starts_with(b"{\"iss\":\"joe\""));
+        assert!(jwsj.validate(&only_es256, JwsSignaturePolicy::Any).is_ok());
+        assert!(matches!(
+            jwsj.validate(&only_es256, JwsSignaturePolicy::All),
+            Err(JwtError::JwkKeyNotFound)
+        ));
+
+        // More than one signature can not be written in compact form.
+        assert!(matches!(
+            jwsj.to_compact(),
+            Err(JwtError::CompactFormUnavailable)
+        ));
+
+        // https://datatracker.ietf.org/doc/html/rfc7515#appendix-A.7
+        let flattened = format!(r#"{{"payload":"{}",{}}}"#, payload, es256_sig);
+        let jwsj = JwsJson::from_str_with_critical(&flattened, &CriticalExtensions::default())
+            .expect("Invalid json jws");
+        assert!(jwsj.to_string() == flattened);
+        assert!(
+            jwsj.headers().next().and_then(|h| h.kid())
+                == Some("e9bc097a-ce51-4036-9562-d2ade882db0d")
+        );
+        assert!(jwsj.validate(&only_es256, JwsSignaturePolicy::All).is_ok());
+
+        // The kid is in the unprotected header, which compact form can not carry.
+        assert!(matches!(
+            jwsj.to_compact(),
+            Err(JwtError::CompactFormUnavailable)
+        ));
+
+        // https://datatracker.ietf.org/doc/html/rfc7515#appendix-A.3
+        let compact = format!("eyJhbGciOiJFUzI1NiJ9.{}.DtEhU3ljbEg8L38VWAfUAqOyKAM6-Xx-F4GawxaepmXFCgfTjDxw5djxLa8ISlSApmWQxfKTUJqPP3-Kg6NU1Q", payload);
+        let jwsc = JwsCompact::from_str(&compact).expect("Invalid compact jws");
+        let jwsj = JwsJson::from_compact(&jwsc).expect("Unable to convert jws");
+        let jwsj =
+            JwsJson::from_str_with_critical(&jwsj.to_string(), &CriticalExtensions::default())
+                .expect("Invalid json jws");
+        assert!(
+            jwsj.to_compact()
+                .expect("Unable to convert jws")
+                .to_string()
+                == compact
+        );
+
+        let parse = |s: &str| JwsJson::from_str_with_critical(s, &CriticalExtensions::default());
+
+        // Header parameters must not be in both headers.
+        assert!(matches!(
+            parse(&format!(r#"{{"payload":"{}","protected":"eyJhbGciOiJFUzI1NiJ9","header":{{"alg":"ES256"}},"signature":""}}"#, payload)),
+            Err(JwtError::InvalidHeaderParameter { name }) if name == "alg"
+        ));
+
+        // crit must be protected.
+        assert!(matches!(
+            parse(&format!(r#"{{"payload":"{}","protected":"eyJhbGciOiJFUzI1NiJ9","header":{{"crit":[]}},"signature":""}}"#, payload)),
+            Err(JwtError::InvalidHeaderParameter { name }) if name == "crit"
+        ));
+
+        // The flattened and general forms can not be mixed.
+        assert!(matches!(
+            parse(&format!(
+                r#"{{"payload":"{}",{},"signatures":[]}}"#,
+                payload, es256_sig
+            )),
+            Err(JwtError::InvalidJsonFormat)
+        ));
+        assert!(matches!(
+            parse(&format!(r#"{{"payload":"{}","signatures":[]}}"#, payload)),
+            Err(JwtError::InvalidJsonFormat)
+        ));
+        assert!(matches!(parse("[]"), Err(JwtError::InvalidJsonFormat)));
+    }
 }
@@ -1,34 +1,402 @@
 //! Error types.
 
-use serde::{Deserialize, Serialize};
+use crate::audience::Audience;
+use crate::numeric_date::NumericDate;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Debug, Serialize, Clone, Deserialize, PartialEq)]
+#[non_exhaustive]
 /// An error in the JWT library
 pub enum JwtError {
     /// Invalid Token - May not be in correct compact form
     InvalidCompactFormat,
+    /// Invalid Token - May not be in correct json serialization form
+    InvalidJsonFormat,
+    /// The jws has more than one signature or an unprotected header, so it can not be
+    /// written in compact form
+    CompactFormUnavailable,
     /// Invalid Base64 encodidng of the token content
-    InvalidBase64,
+    InvalidBase64(ErrorSource),
     /// Invalid token header
-    InvalidHeaderFormat,
+    InvalidHeaderFormat(ErrorSource),
     /// Invalid signature over the header and payload
     InvalidSignature,
     /// Invalid JWT content
-    InvalidJwt,
+    InvalidJwt(ErrorSource),
     /// Invalid Critical Extension present
-    CriticalExtension,
+    CriticalExtension {
+        /// The name of the critical header parameter
+        name: String,
+    },
+    /// A critical header parameter is listed but not present
+    CriticalExtensionMissing {
+        /// The name of the critical header parameter
+        name: String,
+    },
+    /// A header parameter can not be set as an extra parameter
+    InvalidHeaderParameter {
+        /// The name of the header parameter
+        name: String,
+    },
+    /// The token could not be decrypted, or its content is not authentic
+    DecryptionFailed,
     /// OpenSSL failure
-    OpenSSLError,
+    OpenSSLError(ErrorSource),
+    /// The key is not of a supported type or curve
+    InvalidKey,
     /// Incorrect Algorithm for verification
     ValidatorAlgMismatch,
     /// Invalid JWT Key ID
     InvalidJwtKid,
     /// The Token has expired
-    OidcTokenExpired,
+    #[deprecated(note = "expired tokens are reported as TokenExpired")]
+    OidcTokenExpired {
+        /// The expiry of the token
+        exp: NumericDate,
+        /// The time the token was validated at
+        now: NumericDate,
+    },
     /// No embeded JWK is available
     EmbededJwkNotAvailable,
     /// Jwk public key export denied
     JwkPublicKeyDenied,
     /// Private key export denied
     PrivateKeyDenied,
+    /// No key in the key set is able to validate this token
+    JwkKeyNotFound,
+    /// The token has no key id, and more than one key in the key set could validate it
+    JwkKeyAmbiguous,
+    /// The token was not issued by an expected issuer
+    InvalidIssuer {
+        /// The issuers that are accepted
+        expected: Vec<String>,
+        /// The issuer of the token
+        actual: Option<String>,
+    },
+    /// The token is not intended for an expected audience
+    InvalidAudience {
+        /// The audiences that are accepted
+        expected: Vec<String>,
+        /// The audience of the token
+        actual: Option<Audience>,
+    },
+    /// A required claim is not present in the token
+    MissingClaim {
+        /// The name of the claim
+        claim: String,
+    },
+    /// The Token has expired
+    TokenExpired {
+        /// The expiry of the token
+        exp: NumericDate,
+        /// The time the token was validated at
+        now: NumericDate,
+    },
+    /// The Token is not yet valid
+    NotYetValid {
+        /// The time the token is valid from
+        nbf: NumericDate,
+        /// The time the token was validated at
+        now: NumericDate,
+    },
+    /// The Token was issued in the future
+    IssuedInFuture {
+        /// The time the token was issued at
+        iat: NumericDate,
+        /// The time the token was validated at
+        now: NumericDate,
+    },
+    /// The Token was issued too long ago
+    TokenTooOld {
+        /// The time the token was issued at
+        iat: NumericDate,
+        /// The maximum age of the token that is accepted
+        max_age: Duration,
+        /// The time the token was validated at
+        now: NumericDate,
+    },
+    /// The authorized party (azp) of the token is not the expected client
+    InvalidAuthorizedParty {
+        /// The client_id that is accepted
+        expected: String,
+        /// The authorized party of the token
+        actual: Option<String>,
+    },
+    /// The nonce of the token does not match the nonce of the request
+    InvalidNonce {
+        /// The nonce of the request
+        expected: String,
+        /// The nonce of the token
+        actual: Option<String>,
+    },
+    /// The authentication context class (acr) of the token was not requested
+    InvalidAcr {
+        /// The acr values of the request
+        expected: Vec<String>,
+        /// The acr of the token
+        actual: Option<String>,
+    },
+    /// The user authenticated too long ago
+    AuthTimeTooOld {
+        /// The time the user authenticated
+        auth_time: NumericDate,
+        /// The max_age of the request
+        max_age: Duration,
+        /// The time the token was validated at
+        now: NumericDate,
+    },
+    /// The at_hash of the token does not match the access token
+    InvalidAtHash,
+    /// The c_hash of the token does not match the authorization code
+    InvalidCHash,
+}
+
+impl fmt::Display for JwtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JwtError::InvalidCompactFormat => write!(f, "token is not in compact form"),
+            JwtError::InvalidJsonFormat => write!(f, "token is not in json serialization form"),
+            JwtError::CompactFormUnavailable => {
+                write!(f, "token can not be written in compact form")
+            }
+            JwtError::InvalidBase64(e) => write!(f, "token contains invalid base64: {}", e),
+            JwtError::InvalidHeaderFormat(e) => write!(f, "invalid token header: {}", e),
+            JwtError::InvalidSignature => write!(f, "invalid token signature"),
+            JwtError::InvalidJwt(e) => write!(f, "invalid token content: {}", e),
+            JwtError::CriticalExtension { name } => {
+                write!(f, "critical header parameter {} is not understood", name)
+            }
+            JwtError::CriticalExtensionMissing { name } => {
+                write!(f, "critical header parameter {} is not present", name)
+            }
+            JwtError::InvalidHeaderParameter { name } => {
+                write!(
+                    f,
+                    "header parameter {} can not be set as an extra parameter",
+                    name
+                )
+            }
+            JwtError::DecryptionFailed => write!(f, "unable to decrypt the token"),
+            JwtError::OpenSSLError(e) => write!(f, "openssl failure: {}", e),
+            JwtError::InvalidKey => write!(f, "key is not of a supported type or curve"),
+            JwtError::ValidatorAlgMismatch => {
+                write!(f, "key can not be used with the token algorithm")
+            }
+            JwtError::InvalidJwtKid => write!(f, "invalid token key id"),
+            #[allow(deprecated)]
+            JwtError::OidcTokenExpired { exp, now } => {
+                write!(f, "token expired at {}, current time is {}", exp, now)
+            }
+            JwtError::EmbededJwkNotAvailable => write!(f, "token has no embedded jwk"),
+            JwtError::JwkPublicKeyDenied => write!(f, "key has no public key to export"),
+            JwtError::PrivateKeyDenied => write!(f, "private key export denied"),
+            JwtError::JwkKeyNotFound => write!(f, "no key in the key set can validate the token"),
+            JwtError::JwkKeyAmbiguous => {
+                write!(f, "more than one key in the key set can validate the token")
+            }
+            JwtError::InvalidIssuer { expected, actual } => write!(
+                f,
+                "invalid issuer {:?}, expected one of {:?}",
+                actual, expected
+            ),
+            JwtError::InvalidAudience { expected, actual } => write!(
+                f,
+                "invalid audience {:?}, expected one of {:?}",
+                actual.as_ref().map(Audience::as_slice),
+                expected
+            ),
+            JwtError::MissingClaim { claim } => write!(f, "required claim {} is missing", claim),
+            JwtError::TokenExpired { exp, now } => {
+                write!(f, "token expired at {}, current time is {}", exp, now)
+            }
+            JwtError::NotYetValid { nbf, now } => {
+                write!(
+                    f,
+                    "token is not valid until {}, current time is {}",
+                    nbf, now
+                )
+            }
+            JwtError::IssuedInFuture { iat, now } => {
+                write!(f, "token is issued at {}, current time is {}", iat, now)
+            }
+            JwtError::TokenTooOld { iat, max_age, now } => write!(
+                f,
+                "token issued at {} is older than {}s, current time is {}",
+                iat,
+                max_age.as_secs(),
+                now
+            ),
+            JwtError::InvalidAuthorizedParty { expected, actual } => write!(
+                f,
+                "invalid authorized party {:?}, expected {:?}",
+                actual, expected
+            ),
+            JwtError::InvalidNonce { expected, actual } => {
+                write!(f, "invalid nonce {:?}, expected {:?}", actual, expected)
+            }
+            JwtError::InvalidAcr { expected, actual } => {
+                write!(
+                    f,
+                    "invalid acr {:?}, expected one of {:?}",
+                    actual, expected
+                )
+            }
+            JwtError::AuthTimeTooOld {
+                auth_time,
+                max_age,
+                now,
+            } => write!(
+                f,
+                "authentication at {} is older than {}s, current time is {}",
+                auth_time,
+                max_age.as_secs(),
+                now
+            ),
+            JwtError::InvalidAtHash => write!(f, "at_hash does not match the access token"),
+            JwtError::InvalidCHash => write!(f, "c_hash does not match the authorization code"),
+        }
+    }
+}
+
+impl Error for JwtError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            JwtError::InvalidBase64(e)
+            | JwtError::InvalidHeaderFormat(e)
+            | JwtError::InvalidJwt(e)
+            | JwtError::OpenSSLError(e) => Some(e.0.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The underlying error that caused a JwtError. Sources are equal when their messages
+/// are equal, and are serialised as their message.
+pub struct ErrorSource(Arc<dyn Error + Send + Sync>);
+
+/// An error that has been deserialised, of which only the message remains.
+#[derive(Debug)]
+struct ErrorMessage(String);
+
+impl fmt::Display for ErrorMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for ErrorMessage {}
+
+impl Serialize for ErrorSource {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for ErrorSource {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)
+            .map(|message| ErrorSource(Arc::new(ErrorMessage(message))))
+    }
+}
+
+impl ErrorSource {
+    /// The underlying error.
+    pub fn get_ref(&self) -> &(dyn Error + Send + Sync + 'static) {
+        self.0.as_ref()
+    }
+}
+
+impl fmt::Display for ErrorSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl PartialEq for ErrorSource {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_string() == other.0.to_string()
+    }
+}
+
+impl From<serde_json::Error> for ErrorSource {
+    fn from(e: serde_json::Error) -> Self {
+        ErrorSource(Arc::new(e))
+    }
+}
+
+impl From<base64::DecodeError> for ErrorSource {
+    fn from(e: base64::DecodeError) -> Self {
+        ErrorSource(Arc::new(e))
+    }
+}
+
+impl From<openssl::error::ErrorStack> for ErrorSource {
+    fn from(e: openssl::error::ErrorStack) -> Self {
+        ErrorSource(Arc::new(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::JwtError;
+    use crate::numeric_date::NumericDate;
+    use std::error::Error;
+
+    #[test]
+    fn test_error_context() {
+        let err = JwtError::TokenExpired {
+            exp: NumericDate::from_secs(1000),
+            now: NumericDate::from_secs(1001),
+        };
+        assert!(err.to_string() == "token expired at 1000, current time is 1001");
+        assert!(err.source().is_none());
+
+        let source = serde_json::from_str::<u32>("\"a\"").unwrap_err();
+        let message = source.to_string();
+        let err = JwtError::InvalidJwt(source.into());
+        assert!(err.to_string() == format!("invalid token content: {}", message));
+        assert!(err.source().map(|e| e.to_string()) == Some(message));
+
+        let source = base64::decode_config("a", base64::URL_SAFE_NO_PAD).unwrap_err();
+        let message = source.to_string();
+        let err = JwtError::InvalidBase64(source.into());
+        assert!(err.to_string() == format!("token contains invalid base64: {}", message));
+        assert!(err.source().map(|e| e.to_string()) == Some(message));
+    }
+
+    #[test]
+    fn test_error_serde() {
+        let err = JwtError::TokenExpired {
+            exp: NumericDate::from_secs(1000),
+            now: NumericDate::from_secs(1001),
+        };
+        let encoded = serde_json::to_string(&err).expect("Unable to serialise error");
+        let decoded: JwtError = serde_json::from_str(&encoded).expect("Unable to parse error");
+        assert!(decoded == err);
+
+        // Sources are carried as their message.
+        let source = serde_json::from_str::<u32>("\"a\"").unwrap_err();
+        let message = source.to_string();
+        let err = JwtError::InvalidJwt(source.into());
+        let encoded = serde_json::to_string(&err).expect("Unable to serialise error");
+        assert!(encoded == format!(r#"{{"InvalidJwt":{:?}}}"#, message));
+        let decoded: JwtError = serde_json::from_str(&encoded).expect("Unable to parse error");
+        assert!(decoded == err);
+        assert!(decoded.source().map(|e| e.to_string()) == Some(message));
+
+        let decoded: JwtError =
+            serde_json::from_str(r#""InvalidSignature""#).expect("Unable to parse error");
+        assert!(decoded == JwtError::InvalidSignature);
+    }
 }
@@ -0,0 +1,2059 @@
+//! Jwe Implementation
+
+use crate::base64_data::Base64UrlSafeData;
+use crate::crypto::{
+    bn_to_fixed_width, ec_field_size, ec_key_from_der, ec_key_generate, rsa_key_from_der,
+    rsa_key_generate, rsa_public_key, EcCurve, Jwk, JwkAlg, JwkUse,
+};
+use crate::error::JwtError;
+use openssl::{aes, bn, derive, ec, encrypt, hash, pkcs5, pkey, rand, rsa, symm};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+// https://datatracker.ietf.org/doc/html/rfc7518#section-5.3
+const GCM_IV_LEN: usize = 12;
+const GCM_TAG_LEN: usize = 16;
+
+// https://datatracker.ietf.org/doc/html/rfc7518#section-4.8.1.1
+const PBES2_SALT_LEN: usize = 16;
+const PBES2_MIN_SALT_LEN: usize = 8;
+/// The smallest iteration count that PBES2 can be configured with.
+pub const PBES2_MIN_ITERATIONS: u32 = 1000;
+/// The largest iteration count that PBES2 can be configured with, or that is accepted in
+/// a token. This bounds the work that a token can demand of the recipient.
+pub const PBES2_MAX_ITERATIONS: u32 = 1_000_000;
+
+#[derive(Debug, Serialize, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[allow(non_camel_case_types)]
+/// Key management algorithm, which protects the content encryption key of a jwe
+pub enum JweAlg {
+    /// RSAES OAEP using SHA-1 and MGF1 with SHA-1. Prefer RSA-OAEP-256.
+    #[serde(rename = "RSA-OAEP")]
+    RSA_OAEP,
+    /// RSAES OAEP using SHA-256 and MGF1 with SHA-256
+    #[serde(rename = "RSA-OAEP-256")]
+    RSA_OAEP_256,
+    /// Elliptic Curve Diffie-Hellman Ephemeral Static key agreement, where the agreed key
+    /// is used directly as the content encryption key
+    #[serde(rename = "ECDH-ES")]
+    ECDH_ES,
+    /// ECDH-ES key agreement, where the agreed key wraps the content encryption key with
+    /// A128KW
+    #[serde(rename = "ECDH-ES+A128KW")]
+    ECDH_ES_A128KW,
+    /// ECDH-ES key agreement, where the agreed key wraps the content encryption key with
+    /// A256KW
+    #[serde(rename = "ECDH-ES+A256KW")]
+    ECDH_ES_A256KW,
+    /// A shared symmetric key is used directly as the content encryption key
+    dir,
+    /// AES Key Wrap with a 128 bit shared key
+    A128KW,
+    /// AES Key Wrap with a 256 bit shared key
+    A256KW,
+    /// AES GCM key encryption with a 256 bit shared key
+    A256GCMKW,
+    /// AES Key Wrap with a 128 bit key derived from a password with PBKDF2 HMAC SHA256
+    #[serde(rename = "PBES2-HS256+A128KW")]
+    PBES2_HS256_A128KW,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, Deserialize, PartialEq, Eq)]
+/// Content encryption algorithm, which encrypts the payload of a jwe
+pub enum JweEnc {
+    /// AES GCM using a 128 bit key
+    A128GCM,
+    /// AES GCM using a 256 bit key
+    A256GCM,
+}
+
+impl JweEnc {
+    fn cipher(self) -> symm::Cipher {
+        match self {
+            JweEnc::A128GCM => symm::Cipher::aes_128_gcm(),
+            JweEnc::A256GCM => symm::Cipher::aes_256_gcm(),
+        }
+    }
+
+    fn key_len(self) -> usize {
+        self.cipher().key_len()
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            JweEnc::A128GCM => "A128GCM",
+            JweEnc::A256GCM => "A256GCM",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Deserialize)]
+/// The protected header of a jwe. This is authenticated by the content encryption, but
+/// is not itself encrypted.
+pub struct JweProtectedHeader {
+    alg: JweAlg,
+    enc: JweEnc,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    typ: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cty: Option<String>,
+    // https://datatracker.ietf.org/doc/html/rfc7518#section-4.6.1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    epk: Option<Jwk>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    apu: Option<Base64UrlSafeData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    apv: Option<Base64UrlSafeData>,
+    // https://datatracker.ietf.org/doc/html/rfc7518#section-4.7.1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    iv: Option<Base64UrlSafeData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tag: Option<Base64UrlSafeData>,
+    // https://datatracker.ietf.org/doc/html/rfc7518#section-4.8.1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    p2s: Option<Base64UrlSafeData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    p2c: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crit: Option<Vec<String>>,
+    #[serde(flatten)]
+    extra: BTreeMap<String, serde_json::Value>,
+}
+
+impl JweProtectedHeader {
+    /// The key management algorithm (alg) of this jwe.
+    pub fn alg(&self) -> JweAlg {
+        self.alg
+    }
+
+    /// The content encryption algorithm (enc) of this jwe.
+    pub fn enc(&self) -> JweEnc {
+        self.enc
+    }
+
+    /// The key id (kid) of the key used to protect the content encryption key.
+    pub fn kid(&self) -> Option<&str> {
+        self.kid.as_deref()
+    }
+
+    /// The media type (typ) of this jwe.
+    pub fn typ(&self) -> Option<&str> {
+        self.typ.as_deref()
+    }
+
+    /// The content type (cty) of the payload. This is "JWT" for a nested token.
+    pub fn cty(&self) -> Option<&str> {
+        self.cty.as_deref()
+    }
+
+    /// The ephemeral public key (epk) of the sender, for ECDH-ES key agreement.
+    pub fn epk(&self) -> Option<&Jwk> {
+        self.epk.as_ref()
+    }
+
+    /// The agreement PartyUInfo (apu), for ECDH-ES key agreement.
+    pub fn apu(&self) -> Option<&[u8]> {
+        self.apu.as_ref().map(|apu| apu.0.as_slice())
+    }
+
+    /// The agreement PartyVInfo (apv), for ECDH-ES key agreement.
+    pub fn apv(&self) -> Option<&[u8]> {
+        self.apv.as_ref().map(|apv| apv.0.as_slice())
+    }
+
+    /// The salt input (p2s), for PBES2 key derivation.
+    pub fn p2s(&self) -> Option<&[u8]> {
+        self.p2s.as_ref().map(|p2s| p2s.0.as_slice())
+    }
+
+    /// The iteration count (p2c), for PBES2 key derivation.
+    pub fn p2c(&self) -> Option<u32> {
+        self.p2c
+    }
+
+    /// Header parameters that are not registered by RFC 7516.
+    pub fn extra(&self) -> &BTreeMap<String, serde_json::Value> {
+        &self.extra
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// The header parameters of a jwe that is being created. The alg and enc are determined
+/// by the encrypter.
+pub struct JweHeader {
+    kid: Option<String>,
+    typ: Option<String>,
+    cty: Option<String>,
+    apu: Option<Base64UrlSafeData>,
+    apv: Option<Base64UrlSafeData>,
+}
+
+impl JweHeader {
+    /// Create an empty set of header parameters.
+    pub fn new() -> Self {
+        JweHeader::default()
+    }
+
+    /// Set the key id (kid) of the recipient's key.
+    pub fn set_kid(mut self, kid: &str) -> Self {
+        self.kid = Some(kid.to_string());
+        self
+    }
+
+    /// Set the media type (typ) of this jwe.
+    pub fn set_typ(mut self, typ: &str) -> Self {
+        self.typ = Some(typ.to_string());
+        self
+    }
+
+    /// Set the content type (cty) of the payload. Use "JWT" when the payload is a signed
+    /// token.
+    pub fn set_cty(mut self, cty: &str) -> Self {
+        self.cty = Some(cty.to_string());
+        self
+    }
+
+    /// Set the agreement PartyUInfo (apu), which is information about the sender that is
+    /// bound to the key agreed with ECDH-ES.
+    pub fn set_apu(mut self, apu: &[u8]) -> Self {
+        self.apu = Some(Base64UrlSafeData(apu.to_vec()));
+        self
+    }
+
+    /// Set the agreement PartyVInfo (apv), which is information about the recipient that
+    /// is bound to the key agreed with ECDH-ES.
+    pub fn set_apv(mut self, apv: &[u8]) -> Self {
+        self.apv = Some(Base64UrlSafeData(apv.to_vec()));
+        self
+    }
+}
+
+impl From<&JweProtectedHeader> for JweHeader {
+    fn from(phdr: &JweProtectedHeader) -> Self {
+        JweHeader {
+            kid: phdr.kid.clone(),
+            typ: phdr.typ.clone(),
+            cty: phdr.cty.clone(),
+            apu: phdr.apu.clone(),
+            apv: phdr.apv.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+#[allow(non_camel_case_types)]
+/// A public key of a recipient, or a key shared with them, that can encrypt the content
+/// encryption key of a jwe.
+pub enum JweEncrypter {
+    /// RSAES OAEP using SHA-256
+    RSA_OAEP_256 {
+        /// Public Key
+        pkey: rsa::Rsa<pkey::Public>,
+    },
+    /// ECDH-ES with P-256
+    ECDH_ES {
+        /// Public Key
+        pkey: ec::EcKey<pkey::Public>,
+    },
+    /// ECDH-ES with P-256 and A128KW
+    ECDH_ES_A128KW {
+        /// Public Key
+        pkey: ec::EcKey<pkey::Public>,
+    },
+    /// ECDH-ES with P-256 and A256KW
+    ECDH_ES_A256KW {
+        /// Public Key
+        pkey: ec::EcKey<pkey::Public>,
+    },
+    /// Direct use of a shared key
+    dir {
+        /// Shared Key
+        key: Vec<u8>,
+    },
+    /// AES Key Wrap with a 128 bit key
+    A128KW {
+        /// Shared Key
+        key: Vec<u8>,
+    },
+    /// AES Key Wrap with a 256 bit key
+    A256KW {
+        /// Shared Key
+        key: Vec<u8>,
+    },
+    /// AES GCM key encryption with a 256 bit key
+    A256GCMKW {
+        /// Shared Key
+        key: Vec<u8>,
+    },
+    /// PBES2 with HMAC SHA256 and A128KW
+    PBES2_HS256_A128KW {
+        /// Shared Password
+        password: Vec<u8>,
+        /// The iteration count of the key derivation.
+        iterations: u32,
+    },
+}
+
+#[derive(Clone)]
+#[allow(non_camel_case_types)]
+/// A private key, or a shared key, that can decrypt the content encryption key of a jwe.
+pub enum JweDecrypter {
+    /// RSAES OAEP using SHA-1
+    RSA_OAEP {
+        /// Private Key
+        skey: rsa::Rsa<pkey::Private>,
+    },
+    /// RSAES OAEP using SHA-256
+    RSA_OAEP_256 {
+        /// Private Key
+        skey: rsa::Rsa<pkey::Private>,
+    },
+    /// ECDH-ES with P-256
+    ECDH_ES {
+        /// Private Key
+        skey: ec::EcKey<pkey::Private>,
+    },
+    /// ECDH-ES with P-256 and A128KW
+    ECDH_ES_A128KW {
+        /// Private Key
+        skey: ec::EcKey<pkey::Private>,
+    },
+    /// ECDH-ES with P-256 and A256KW
+    ECDH_ES_A256KW {
+        /// Private Key
+        skey: ec::EcKey<pkey::Private>,
+    },
+    /// Direct use of a shared key
+    dir {
+        /// Shared Key
+        key: Vec<u8>,
+    },
+    /// AES Key Wrap with a 128 bit key
+    A128KW {
+        /// Shared Key
+        key: Vec<u8>,
+    },
+    /// AES Key Wrap with a 256 bit key
+    A256KW {
+        /// Shared Key
+        key: Vec<u8>,
+    },
+    /// AES GCM key encryption with a 256 bit key
+    A256GCMKW {
+        /// Shared Key
+        key: Vec<u8>,
+    },
+    /// PBES2 with HMAC SHA256 and A128KW
+    PBES2_HS256_A128KW {
+        /// Shared Password
+        password: Vec<u8>,
+        /// The smallest iteration count of the key derivation that is accepted.
+        min_iterations: u32,
+    },
+}
+
+fn random_bytes(len: usize) -> Result<Vec<u8>, JwtError> {
+    let mut buf = vec![0; len];
+    rand::rand_bytes(&mut buf).map_err(|e| {
+        error!("{:?}", e);
+        JwtError::OpenSSLError(e.into())
+    })?;
+    Ok(buf)
+}
+
+/// RSA-OAEP-256 encryption, the only form of RSA-OAEP used to create new tokens.
+fn rsa_oaep_256_encrypt(pkey: &rsa::Rsa<pkey::Public>, input: &[u8]) -> Result<Vec<u8>, JwtError> {
+    let digest = hash::MessageDigest::sha256();
+    let pkey = pkey::PKey::from_rsa(pkey.clone()).map_err(|e| JwtError::OpenSSLError(e.into()))?;
+    let mut encrypter =
+        encrypt::Encrypter::new(&pkey).map_err(|e| JwtError::OpenSSLError(e.into()))?;
+    encrypter
+        .set_rsa_padding(rsa::Padding::PKCS1_OAEP)
+        .and_then(|_| encrypter.set_rsa_oaep_md(digest))
+        .and_then(|_| encrypter.set_rsa_mgf1_md(digest))
+        .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+    let len = encrypter
+        .encrypt_len(input)
+        .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+    let mut output = vec![0; len];
+    let len = encrypter
+        .encrypt(input, &mut output)
+        .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+    output.truncate(len);
+    Ok(output)
+}
+
+fn rsa_oaep_decrypt(
+    skey: &rsa::Rsa<pkey::Private>,
+    digest: hash::MessageDigest,
+    input: &[u8],
+) -> Result<Vec<u8>, JwtError> {
+    let skey = pkey::PKey::from_rsa(skey.clone()).map_err(|e| JwtError::OpenSSLError(e.into()))?;
+    let mut decrypter =
+        encrypt::Decrypter::new(&skey).map_err(|e| JwtError::OpenSSLError(e.into()))?;
+    decrypter
+        .set_rsa_padding(rsa::Padding::PKCS1_OAEP)
+        .and_then(|_| decrypter.set_rsa_oaep_md(digest))
+        .and_then(|_| decrypter.set_rsa_mgf1_md(digest))
+        .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+    let len = decrypter
+        .decrypt_len(input)
+        .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+    let mut output = vec![0; len];
+    let len = decrypter
+        .decrypt(input, &mut output)
+        .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+    output.truncate(len);
+    Ok(output)
+}
+
+/// A key may be limited to a single algorithm, which must be the one it is used with.
+fn check_jwk_alg(jwk_alg: &Option<JwkAlg>, alg: JweAlg) -> Result<(), JwtError> {
+    match jwk_alg {
+        None => Ok(()),
+        Some(JwkAlg::Jwe(jwk_alg)) if *jwk_alg == alg => Ok(()),
+        Some(_) => Err(JwtError::ValidatorAlgMismatch),
+    }
+}
+
+fn ec_public_key_from_jwk(jwk: &Jwk, alg: JweAlg) -> Result<ec::EcKey<pkey::Public>, JwtError> {
+    match jwk {
+        Jwk::EC {
+            crv: EcCurve::P256,
+            x,
+            y,
+            alg: jwk_alg,
+            use_: None | Some(JwkUse::Enc),
+            kid: _,
+        } => {
+            check_jwk_alg(jwk_alg, alg)?;
+
+            let ec_group = ec::EcGroup::from_curve_name(EcCurve::P256.to_nid())
+                .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+            // The coordinates are always the full size of the field.
+            // https://datatracker.ietf.org/doc/html/rfc7518#section-6.2.1.2
+            let field_size = ec_field_size(&ec_group);
+            if x.0.len() != field_size || y.0.len() != field_size {
+                return Err(JwtError::InvalidKey);
+            }
+
+            let xbn = bn::BigNum::from_slice(&x.0).map_err(|e| JwtError::OpenSSLError(e.into()))?;
+            let ybn = bn::BigNum::from_slice(&y.0).map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+            // A point that is not on the curve can disclose the private key of the
+            // recipient through the agreed key, so it must be rejected.
+            // https://datatracker.ietf.org/doc/html/rfc7518#section-7.1
+            let pkey = ec::EcKey::from_public_key_affine_coordinates(&ec_group, &xbn, &ybn)
+                .and_then(|pkey| pkey.check_key().map(|_| pkey))
+                .map_err(|e| {
+                    debug!(?e, "invalid ec public key");
+                    JwtError::InvalidKey
+                })?;
+
+            Ok(pkey)
+        }
+        _ => Err(JwtError::InvalidKey),
+    }
+}
+
+fn ec_public_key_as_jwk<T: pkey::HasPublic>(
+    key: &ec::EcKeyRef<T>,
+    alg: Option<JweAlg>,
+    kid: Option<&str>,
+) -> Result<Jwk, JwtError> {
+    let ec_group = key.group();
+    let mut bnctx = bn::BigNumContext::new().map_err(|e| JwtError::OpenSSLError(e.into()))?;
+    let mut xbn = bn::BigNum::new().map_err(|e| JwtError::OpenSSLError(e.into()))?;
+    let mut ybn = bn::BigNum::new().map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+    key.public_key()
+        .affine_coordinates(ec_group, &mut xbn, &mut ybn, &mut bnctx)
+        .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+    let field_size = ec_field_size(ec_group);
+    Ok(Jwk::EC {
+        crv: EcCurve::P256,
+        x: Base64UrlSafeData(bn_to_fixed_width(&xbn, field_size)?),
+        y: Base64UrlSafeData(bn_to_fixed_width(&ybn, field_size)?),
+        alg: alg.map(JwkAlg::Jwe),
+        use_: Some(JwkUse::Enc),
+        kid: kid.map(str::to_string),
+    })
+}
+
+fn ecdh_shared_secret(
+    skey: &ec::EcKey<pkey::Private>,
+    pkey: &ec::EcKey<pkey::Public>,
+) -> Result<Vec<u8>, JwtError> {
+    let skey =
+        pkey::PKey::from_ec_key(skey.clone()).map_err(|e| JwtError::OpenSSLError(e.into()))?;
+    let pkey =
+        pkey::PKey::from_ec_key(pkey.clone()).map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+    let mut deriver = derive::Deriver::new(&skey).map_err(|e| JwtError::OpenSSLError(e.into()))?;
+    deriver
+        .set_peer(&pkey)
+        .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+    deriver
+        .derive_to_vec()
+        .map_err(|e| JwtError::OpenSSLError(e.into()))
+}
+
+/// Derive a key of key_len bytes from the shared secret of a key agreement, with the
+/// Concat KDF using SHA-256.
+/// `https://datatracker.ietf.org/doc/html/rfc7518#section-4.6.2`
+fn concat_kdf(
+    z: &[u8],
+    alg_id: &str,
+    apu: &[u8],
+    apv: &[u8],
+    key_len: usize,
+) -> Result<Vec<u8>, JwtError> {
+    let mut other_info = Vec::new();
+    for field in [alg_id.as_bytes(), apu, apv] {
+        other_info.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        other_info.extend_from_slice(field);
+    }
+    other_info.extend_from_slice(&((key_len * 8) as u32).to_be_bytes());
+
+    let mut key = Vec::with_capacity(key_len);
+    let mut counter: u32 = 1;
+    while key.len() < key_len {
+        let mut hasher = hash::Hasher::new(hash::MessageDigest::sha256())
+            .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+        hasher
+            .update(&counter.to_be_bytes())
+            .and_then(|_| hasher.update(z))
+            .and_then(|_| hasher.update(&other_info))
+            .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+        let round = hasher
+            .finish()
+            .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+        key.extend_from_slice(&round);
+        counter += 1;
+    }
+    key.truncate(key_len);
+    Ok(key)
+}
+
+/// The key agreed by ECDH-ES for this header. For direct key agreement this is the
+/// content encryption key, else it is the key that wraps the content encryption key.
+fn ecdh_es_agreed_key(z: &[u8], header: &JweProtectedHeader) -> Result<Vec<u8>, JwtError> {
+    let (alg_id, key_len) = match header.alg {
+        JweAlg::ECDH_ES_A128KW => ("ECDH-ES+A128KW", 16),
+        JweAlg::ECDH_ES_A256KW => ("ECDH-ES+A256KW", 32),
+        _ => (header.enc.as_str(), header.enc.key_len()),
+    };
+
+    concat_kdf(
+        z,
+        alg_id,
+        header.apu().unwrap_or_default(),
+        header.apv().unwrap_or_default(),
+        key_len,
+    )
+}
+
+fn aes_key_wrap(kek: &[u8], cek: &[u8]) -> Result<Vec<u8>, JwtError> {
+    let key = aes::AesKey::new_encrypt(kek).map_err(|_| JwtError::InvalidKey)?;
+    let mut wrapped = vec![0; cek.len() + 8];
+    aes::wrap_key(&key, None, &mut wrapped, cek).map_err(|_| JwtError::InvalidKey)?;
+    Ok(wrapped)
+}
+
+/// Unwrap the content encryption key, which must be the correct size for enc.
+fn aes_key_unwrap(kek: &[u8], wrapped: &[u8], enc: JweEnc) -> Result<Vec<u8>, JwtError> {
+    // https://datatracker.ietf.org/doc/html/rfc3394#section-2
+    if wrapped.len() < 24 || !wrapped.len().is_multiple_of(8) {
+        return Err(JwtError::DecryptionFailed);
+    }
+    let key = aes::AesKey::new_decrypt(kek).map_err(|_| JwtError::InvalidKey)?;
+    let mut cek = vec![0; wrapped.len() - 8];
+    aes::unwrap_key(&key, None, &mut cek, wrapped).map_err(|_| JwtError::DecryptionFailed)?;
+    if cek.len() != enc.key_len() {
+        return Err(JwtError::DecryptionFailed);
+    }
+    Ok(cek)
+}
+
+fn oct_key_from_jwk(jwk: &Jwk, alg: JweAlg, key_lens: &[usize]) -> Result<Vec<u8>, JwtError> {
+    match jwk {
+        Jwk::oct {
+            k,
+            alg: jwk_alg,
+            use_: None | Some(JwkUse::Enc),
+            kid: _,
+        } if key_lens.contains(&k.0.len()) => {
+            check_jwk_alg(jwk_alg, alg)?;
+            Ok(k.0.clone())
+        }
+        _ => Err(JwtError::InvalidKey),
+    }
+}
+
+fn pbes2_check_iterations(iterations: u32) -> Result<u32, JwtError> {
+    if (PBES2_MIN_ITERATIONS..=PBES2_MAX_ITERATIONS).contains(&iterations) {
+        Ok(iterations)
+    } else {
+        Err(JwtError::InvalidKey)
+    }
+}
+
+/// Derive the key that wraps the content encryption key from the password.
+/// `https://datatracker.ietf.org/doc/html/rfc7518#section-4.8.1.1`
+fn pbes2_hs256_a128kw_key(password: &[u8], p2s: &[u8], p2c: u32) -> Result<Vec<u8>, JwtError> {
+    // The salt is bound to the alg.
+    let mut salt = b"PBES2-HS256+A128KW\0".to_vec();
+    salt.extend_from_slice(p2s);
+
+    let mut kek = vec![0; 16];
+    pkcs5::pbkdf2_hmac(
+        password,
+        &salt,
+        p2c as usize,
+        hash::MessageDigest::sha256(),
+        &mut kek,
+    )
+    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+    Ok(kek)
+}
+
+impl JweEncrypter {
+    /// The key management algorithm of this encrypter.
+    pub fn alg(&self) -> JweAlg {
+        match self {
+            JweEncrypter::RSA_OAEP_256 { .. } => JweAlg::RSA_OAEP_256,
+            JweEncrypter::ECDH_ES { .. } => JweAlg::ECDH_ES,
+            JweEncrypter::ECDH_ES_A128KW { .. } => JweAlg::ECDH_ES_A128KW,
+            JweEncrypter::ECDH_ES_A256KW { .. } => JweAlg::ECDH_ES_A256KW,
+            JweEncrypter::dir { .. } => JweAlg::dir,
+            JweEncrypter::A128KW { .. } => JweAlg::A128KW,
+            JweEncrypter::A256KW { .. } => JweAlg::A256KW,
+            JweEncrypter::A256GCMKW { .. } => JweAlg::A256GCMKW,
+            JweEncrypter::PBES2_HS256_A128KW { .. } => JweAlg::PBES2_HS256_A128KW,
+        }
+    }
+
+    /// Create an RSA-OAEP-256 encrypter from the recipient's public key. The key must not
+    /// be limited to signing, or to another algorithm.
+    pub fn from_rsa_oaep_256_jwk(jwk: &Jwk) -> Result<Self, JwtError> {
+        match jwk {
+            Jwk::RSA {
+                n,
+                e,
+                alg,
+                use_: None | Some(JwkUse::Enc),
+                kid: _,
+            } => {
+                check_jwk_alg(alg, JweAlg::RSA_OAEP_256)?;
+
+                let nbn =
+                    bn::BigNum::from_slice(&n.0).map_err(|e| JwtError::OpenSSLError(e.into()))?;
+                let ebn =
+                    bn::BigNum::from_slice(&e.0).map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+                let pkey = rsa::Rsa::from_public_components(nbn, ebn)
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+                Ok(JweEncrypter::RSA_OAEP_256 { pkey })
+            }
+            _ => Err(JwtError::InvalidKey),
+        }
+    }
+
+    /// Create an ECDH-ES encrypter from the recipient's P-256 public key.
+    pub fn from_ecdh_es_jwk(jwk: &Jwk) -> Result<Self, JwtError> {
+        ec_public_key_from_jwk(jwk, JweAlg::ECDH_ES).map(|pkey| JweEncrypter::ECDH_ES { pkey })
+    }
+
+    /// Create an ECDH-ES+A128KW encrypter from the recipient's P-256 public key.
+    pub fn from_ecdh_es_a128kw_jwk(jwk: &Jwk) -> Result<Self, JwtError> {
+        ec_public_key_from_jwk(jwk, JweAlg::ECDH_ES_A128KW)
+            .map(|pkey| JweEncrypter::ECDH_ES_A128KW { pkey })
+    }
+
+    /// Create an ECDH-ES+A256KW encrypter from the recipient's P-256 public key.
+    pub fn from_ecdh_es_a256kw_jwk(jwk: &Jwk) -> Result<Self, JwtError> {
+        ec_public_key_from_jwk(jwk, JweAlg::ECDH_ES_A256KW)
+            .map(|pkey| JweEncrypter::ECDH_ES_A256KW { pkey })
+    }
+
+    /// Create a dir encrypter from a shared 128 or 256 bit oct key. The key must not be
+    /// limited to signing.
+    pub fn from_dir_jwk(jwk: &Jwk) -> Result<Self, JwtError> {
+        oct_key_from_jwk(jwk, JweAlg::dir, &[16, 32]).map(|key| JweEncrypter::dir { key })
+    }
+
+    /// Create an A128KW encrypter from a shared 128 bit oct key. The key must not be
+    /// limited to signing.
+    pub fn from_a128kw_jwk(jwk: &Jwk) -> Result<Self, JwtError> {
+        oct_key_from_jwk(jwk, JweAlg::A128KW, &[16]).map(|key| JweEncrypter::A128KW { key })
+    }
+
+    /// Create an A256KW encrypter from a shared 256 bit oct key. The key must not be
+    /// limited to signing.
+    pub fn from_a256kw_jwk(jwk: &Jwk) -> Result<Self, JwtError> {
+        oct_key_from_jwk(jwk, JweAlg::A256KW, &[32]).map(|key| JweEncrypter::A256KW { key })
+    }
+
+    /// Create an A256GCMKW encrypter from a shared 256 bit oct key. The key must not be
+    /// limited to signing.
+    pub fn from_a256gcmkw_jwk(jwk: &Jwk) -> Result<Self, JwtError> {
+        oct_key_from_jwk(jwk, JweAlg::A256GCMKW, &[32]).map(|key| JweEncrypter::A256GCMKW { key })
+    }
+
+    /// Create a PBES2-HS256+A128KW encrypter from a password, deriving keys with this
+    /// iteration count. The count must be between PBES2_MIN_ITERATIONS and
+    /// PBES2_MAX_ITERATIONS, and at least the min_iterations of the recipient.
+    pub fn from_pbes2_hs256_a128kw_password(
+        password: &[u8],
+        iterations: u32,
+    ) -> Result<Self, JwtError> {
+        if password.is_empty() {
+            return Err(JwtError::InvalidKey);
+        }
+
+        Ok(JweEncrypter::PBES2_HS256_A128KW {
+            password: password.to_vec(),
+            iterations: pbes2_check_iterations(iterations)?,
+        })
+    }
+
+    /// Create a new content encryption key for the enc of this header, and the encrypted
+    /// key that is sent to the recipient. Any header parameters that the recipient needs to
+    /// recover the key are added to the header.
+    fn encrypt_cek(&self, header: &mut JweProtectedHeader) -> Result<(Vec<u8>, Vec<u8>), JwtError> {
+        match self {
+            JweEncrypter::RSA_OAEP_256 { pkey } => {
+                let cek = random_bytes(header.enc.key_len())?;
+                let encrypted_key = rsa_oaep_256_encrypt(pkey, &cek)?;
+                Ok((cek, encrypted_key))
+            }
+            JweEncrypter::ECDH_ES { pkey }
+            | JweEncrypter::ECDH_ES_A128KW { pkey }
+            | JweEncrypter::ECDH_ES_A256KW { pkey } => {
+                let ephemeral = ec_key_generate(EcCurve::P256)?;
+                header.epk = Some(ec_public_key_as_jwk(&ephemeral, None, None)?);
+
+                let z = ecdh_shared_secret(&ephemeral, pkey)?;
+                let agreed_key = ecdh_es_agreed_key(&z, header)?;
+
+                if matches!(self, JweEncrypter::ECDH_ES { .. }) {
+                    Ok((agreed_key, Vec::new()))
+                } else {
+                    let cek = random_bytes(header.enc.key_len())?;
+                    let encrypted_key = aes_key_wrap(&agreed_key, &cek)?;
+                    Ok((cek, encrypted_key))
+                }
+            }
+            JweEncrypter::dir { key } => {
+                if key.len() != header.enc.key_len() {
+                    return Err(JwtError::ValidatorAlgMismatch);
+                }
+                Ok((key.clone(), Vec::new()))
+            }
+            JweEncrypter::A128KW { key } | JweEncrypter::A256KW { key } => {
+                let cek = random_bytes(header.enc.key_len())?;
+                let encrypted_key = aes_key_wrap(key, &cek)?;
+                Ok((cek, encrypted_key))
+            }
+            JweEncrypter::A256GCMKW { key } => {
+                let cek = random_bytes(header.enc.key_len())?;
+                let iv = random_bytes(GCM_IV_LEN)?;
+                let mut tag = vec![0; GCM_TAG_LEN];
+                let encrypted_key = symm::encrypt_aead(
+                    symm::Cipher::aes_256_gcm(),
+                    key,
+                    Some(&iv),
+                    &[],
+                    &cek,
+                    &mut tag,
+                )
+                .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+                header.iv = Some(Base64UrlSafeData(iv));
+                header.tag = Some(Base64UrlSafeData(tag));
+                Ok((cek, encrypted_key))
+            }
+            JweEncrypter::PBES2_HS256_A128KW {
+                password,
+                iterations,
+            } => {
+                let p2s = random_bytes(PBES2_SALT_LEN)?;
+                let kek = pbes2_hs256_a128kw_key(password, &p2s, *iterations)?;
+
+                let cek = random_bytes(header.enc.key_len())?;
+                let encrypted_key = aes_key_wrap(&kek, &cek)?;
+
+                header.p2s = Some(Base64UrlSafeData(p2s));
+                header.p2c = Some(*iterations);
+                Ok((cek, encrypted_key))
+            }
+        }
+    }
+}
+
+impl JweDecrypter {
+    /// The key management algorithm of this decrypter.
+    pub fn alg(&self) -> JweAlg {
+        match self {
+            JweDecrypter::RSA_OAEP { .. } => JweAlg::RSA_OAEP,
+            JweDecrypter::RSA_OAEP_256 { .. } => JweAlg::RSA_OAEP_256,
+            JweDecrypter::ECDH_ES { .. } => JweAlg::ECDH_ES,
+            JweDecrypter::ECDH_ES_A128KW { .. } => JweAlg::ECDH_ES_A128KW,
+            JweDecrypter::ECDH_ES_A256KW { .. } => JweAlg::ECDH_ES_A256KW,
+            JweDecrypter::dir { .. } => JweAlg::dir,
+            JweDecrypter::A128KW { .. } => JweAlg::A128KW,
+            JweDecrypter::A256KW { .. } => JweAlg::A256KW,
+            JweDecrypter::A256GCMKW { .. } => JweAlg::A256GCMKW,
+            JweDecrypter::PBES2_HS256_A128KW { .. } => JweAlg::PBES2_HS256_A128KW,
+        }
+    }
+
+    /// Restore this JweDecrypter from a DER private key, for RSA-OAEP. This exists to
+    /// decrypt tokens from legacy senders, and RSA-OAEP-256 should be preferred.
+    pub fn from_rsa_oaep_der(der: &[u8]) -> Result<Self, JwtError> {
+        let skey = rsa_key_from_der(der)?;
+
+        Ok(JweDecrypter::RSA_OAEP { skey })
+    }
+
+    /// Restore this JweDecrypter from a DER private key, for RSA-OAEP-256.
+    pub fn from_rsa_oaep_256_der(der: &[u8]) -> Result<Self, JwtError> {
+        let skey = rsa_key_from_der(der)?;
+
+        Ok(JweDecrypter::RSA_OAEP_256 { skey })
+    }
+
+    /// Create a new RSA private key for RSA-OAEP-256
+    pub fn generate_rsa_oaep_256() -> Result<Self, JwtError> {
+        let skey = rsa_key_generate()?;
+
+        Ok(JweDecrypter::RSA_OAEP_256 { skey })
+    }
+
+    /// Restore this JweDecrypter from a DER P-256 private key, for ECDH-ES.
+    pub fn from_ecdh_es_der(der: &[u8]) -> Result<Self, JwtError> {
+        ec_key_from_der(der, EcCurve::P256).map(|skey| JweDecrypter::ECDH_ES { skey })
+    }
+
+    /// Restore this JweDecrypter from a DER P-256 private key, for ECDH-ES+A128KW.
+    pub fn from_ecdh_es_a128kw_der(der: &[u8]) -> Result<Self, JwtError> {
+        ec_key_from_der(der, EcCurve::P256).map(|skey| JweDecrypter::ECDH_ES_A128KW { skey })
+    }
+
+    /// Restore this JweDecrypter from a DER P-256 private key, for ECDH-ES+A256KW.
+    pub fn from_ecdh_es_a256kw_der(der: &[u8]) -> Result<Self, JwtError> {
+        ec_key_from_der(der, EcCurve::P256).map(|skey| JweDecrypter::ECDH_ES_A256KW { skey })
+    }
+
+    /// Create a new P-256 private key for ECDH-ES
+    pub fn generate_ecdh_es() -> Result<Self, JwtError> {
+        ec_key_generate(EcCurve::P256).map(|skey| JweDecrypter::ECDH_ES { skey })
+    }
+
+    /// Create a new P-256 private key for ECDH-ES+A128KW
+    pub fn generate_ecdh_es_a128kw() -> Result<Self, JwtError> {
+        ec_key_generate(EcCurve::P256).map(|skey| JweDecrypter::ECDH_ES_A128KW { skey })
+    }
+
+    /// Create a new P-256 private key for ECDH-ES+A256KW
+    pub fn generate_ecdh_es_a256kw() -> Result<Self, JwtError> {
+        ec_key_generate(EcCurve::P256).map(|skey| JweDecrypter::ECDH_ES_A256KW { skey })
+    }
+
+    /// Restore this JweDecrypter from a shared 128 or 256 bit oct key, for dir. The key
+    /// must not be limited to signing.
+    pub fn from_dir_jwk(jwk: &Jwk) -> Result<Self, JwtError> {
+        oct_key_from_jwk(jwk, JweAlg::dir, &[16, 32]).map(|key| JweDecrypter::dir { key })
+    }
+
+    /// Restore this JweDecrypter from a shared 128 bit oct key, for A128KW. The key must
+    /// not be limited to signing.
+    pub fn from_a128kw_jwk(jwk: &Jwk) -> Result<Self, JwtError> {
+        oct_key_from_jwk(jwk, JweAlg::A128KW, &[16]).map(|key| JweDecrypter::A128KW { key })
+    }
+
+    /// Restore this JweDecrypter from a shared 256 bit oct key, for A256KW. The key must
+    /// not be limited to signing.
+    pub fn from_a256kw_jwk(jwk: &Jwk) -> Result<Self, JwtError> {
+        oct_key_from_jwk(jwk, JweAlg::A256KW, &[32]).map(|key| JweDecrypter::A256KW { key })
+    }
+
+    /// Restore this JweDecrypter from a shared 256 bit oct key, for A256GCMKW. The key
+    /// must not be limited to signing.
+    pub fn from_a256gcmkw_jwk(jwk: &Jwk) -> Result<Self, JwtError> {
+        oct_key_from_jwk(jwk, JweAlg::A256GCMKW, &[32]).map(|key| JweDecrypter::A256GCMKW { key })
+    }
+
+    /// Create a PBES2-HS256+A128KW decrypter from a password. Tokens that derive their key
+    /// with fewer than min_iterations are rejected. This must be between
+    /// PBES2_MIN_ITERATIONS and PBES2_MAX_ITERATIONS.
+    pub fn from_pbes2_hs256_a128kw_password(
+        password: &[u8],
+        min_iterations: u32,
+    ) -> Result<Self, JwtError> {
+        if password.is_empty() {
+            return Err(JwtError::InvalidKey);
+        }
+
+        Ok(JweDecrypter::PBES2_HS256_A128KW {
+            password: password.to_vec(),
+            min_iterations: pbes2_check_iterations(min_iterations)?,
+        })
+    }
+
+    /// Create a new secure shared key for dir, with the key size of this enc
+    pub fn generate_dir(enc: JweEnc) -> Result<Self, JwtError> {
+        random_bytes(enc.key_len()).map(|key| JweDecrypter::dir { key })
+    }
+
+    /// Create a new secure shared key for A128KW
+    pub fn generate_a128kw() -> Result<Self, JwtError> {
+        random_bytes(16).map(|key| JweDecrypter::A128KW { key })
+    }
+
+    /// Create a new secure shared key for A256KW
+    pub fn generate_a256kw() -> Result<Self, JwtError> {
+        random_bytes(32).map(|key| JweDecrypter::A256KW { key })
+    }
+
+    /// Create a new secure shared key for A256GCMKW
+    pub fn generate_a256gcmkw() -> Result<Self, JwtError> {
+        random_bytes(32).map(|key| JweDecrypter::A256GCMKW { key })
+    }
+
+    /// Given this decrypter, retrieve the matching encrypter which senders can use to
+    /// encrypt tokens for this key. RSA-OAEP has no encrypter, as new tokens must use
+    /// RSA-OAEP-256.
+    pub fn get_encrypter(&self) -> Result<JweEncrypter, JwtError> {
+        match self {
+            // RSA-OAEP is only used to decrypt tokens from legacy senders.
+            JweDecrypter::RSA_OAEP { .. } => Err(JwtError::ValidatorAlgMismatch),
+            JweDecrypter::RSA_OAEP_256 { skey } => {
+                rsa_public_key(skey).map(|pkey| JweEncrypter::RSA_OAEP_256 { pkey })
+            }
+            JweDecrypter::ECDH_ES { skey }
+            | JweDecrypter::ECDH_ES_A128KW { skey }
+            | JweDecrypter::ECDH_ES_A256KW { skey } => {
+                let pkey = ec::EcKey::from_public_key(skey.group(), skey.public_key())
+                    .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+                Ok(match self {
+                    JweDecrypter::ECDH_ES_A128KW { .. } => JweEncrypter::ECDH_ES_A128KW { pkey },
+                    JweDecrypter::ECDH_ES_A256KW { .. } => JweEncrypter::ECDH_ES_A256KW { pkey },
+                    _ => JweEncrypter::ECDH_ES { pkey },
+                })
+            }
+            JweDecrypter::dir { key } => Ok(JweEncrypter::dir { key: key.clone() }),
+            JweDecrypter::A128KW { key } => Ok(JweEncrypter::A128KW { key: key.clone() }),
+            JweDecrypter::A256KW { key } => Ok(JweEncrypter::A256KW { key: key.clone() }),
+            JweDecrypter::A256GCMKW { key } => Ok(JweEncrypter::A256GCMKW { key: key.clone() }),
+            JweDecrypter::PBES2_HS256_A128KW {
+                password,
+                min_iterations,
+            } => Ok(JweEncrypter::PBES2_HS256_A128KW {
+                password: password.clone(),
+                iterations: *min_iterations,
+            }),
+        }
+    }
+
+    /// Export the public key of this decrypter as a Jwk, which senders can use to
+    /// encrypt tokens for this key.
+    pub fn public_key_as_jwk(&self, kid: Option<&str>) -> Result<Jwk, JwtError> {
+        match self {
+            JweDecrypter::RSA_OAEP { skey, .. } | JweDecrypter::RSA_OAEP_256 { skey, .. } => {
+                Ok(Jwk::RSA {
+                    n: Base64UrlSafeData(skey.n().to_vec()),
+                    e: Base64UrlSafeData(skey.e().to_vec()),
+                    alg: Some(self.alg().into()),
+                    use_: Some(JwkUse::Enc),
+                    kid: kid.map(str::to_string),
+                })
+            }
+            JweDecrypter::ECDH_ES { skey }
+            | JweDecrypter::ECDH_ES_A128KW { skey }
+            | JweDecrypter::ECDH_ES_A256KW { skey } => {
+                ec_public_key_as_jwk(skey, Some(self.alg()), kid)
+            }
+            JweDecrypter::dir { .. }
+            | JweDecrypter::A128KW { .. }
+            | JweDecrypter::A256KW { .. }
+            | JweDecrypter::A256GCMKW { .. }
+            | JweDecrypter::PBES2_HS256_A128KW { .. } => Err(JwtError::JwkPublicKeyDenied),
+        }
+    }
+
+    /// Export the shared key of this decrypter as an oct Jwk. This is secret, and must
+    /// only be given to parties that are trusted to encrypt and decrypt these tokens.
+    pub fn secret_key_as_jwk(&self, kid: Option<&str>) -> Result<Jwk, JwtError> {
+        match self {
+            JweDecrypter::dir { key }
+            | JweDecrypter::A128KW { key }
+            | JweDecrypter::A256KW { key }
+            | JweDecrypter::A256GCMKW { key } => Ok(Jwk::oct {
+                k: Base64UrlSafeData(key.clone()),
+                alg: Some(self.alg().into()),
+                use_: Some(JwkUse::Enc),
+                kid: kid.map(str::to_string),
+            }),
+            _ => Err(JwtError::PrivateKeyDenied),
+        }
+    }
+
+    /// Recover the content encryption key of this jwe.
+    fn decrypt_cek(
+        &self,
+        header: &JweProtectedHeader,
+        encrypted_key: &[u8],
+    ) -> Result<Vec<u8>, JwtError> {
+        match self {
+            JweDecrypter::RSA_OAEP { skey } | JweDecrypter::RSA_OAEP_256 { skey } => {
+                let digest = match self {
+                    JweDecrypter::RSA_OAEP { .. } => hash::MessageDigest::sha1(),
+                    _ => hash::MessageDigest::sha256(),
+                };
+                // If the key can not be decrypted, continue with a random key so that the
+                // failure is indistinguishable from an invalid tag.
+                // https://datatracker.ietf.org/doc/html/rfc7516#section-11.5
+                match rsa_oaep_decrypt(skey, digest, encrypted_key) {
+                    Ok(cek) if cek.len() == header.enc.key_len() => Ok(cek),
+                    _ => {
+                        debug!("unable to decrypt content encryption key");
+                        random_bytes(header.enc.key_len())
+                    }
+                }
+            }
+            JweDecrypter::ECDH_ES { skey }
+            | JweDecrypter::ECDH_ES_A128KW { skey }
+            | JweDecrypter::ECDH_ES_A256KW { skey } => {
+                // The ephemeral public key of the sender is required.
+                let epk = header
+                    .epk
+                    .as_ref()
+                    .ok_or(JwtError::InvalidKey)
+                    .and_then(|epk| ec_public_key_from_jwk(epk, header.alg))?;
+
+                let z = ecdh_shared_secret(skey, &epk)?;
+                let agreed_key = ecdh_es_agreed_key(&z, header)?;
+
+                if matches!(self, JweDecrypter::ECDH_ES { .. }) {
+                    // The agreed key is the content encryption key.
+                    if !encrypted_key.is_empty() {
+                        return Err(JwtError::DecryptionFailed);
+                    }
+                    Ok(agreed_key)
+                } else {
+                    aes_key_unwrap(&agreed_key, encrypted_key, header.enc)
+                }
+            }
+            JweDecrypter::dir { key } => {
+                if key.len() != header.enc.key_len() {
+                    return Err(JwtError::ValidatorAlgMismatch);
+                }
+                if !encrypted_key.is_empty() {
+                    return Err(JwtError::DecryptionFailed);
+                }
+                Ok(key.clone())
+            }
+            JweDecrypter::A128KW { key } | JweDecrypter::A256KW { key } => {
+                aes_key_unwrap(key, encrypted_key, header.enc)
+            }
+            JweDecrypter::A256GCMKW { key } => {
+                let (iv, tag) = match (&header.iv, &header.tag) {
+                    (Some(iv), Some(tag))
+                        if iv.0.len() == GCM_IV_LEN && tag.0.len() == GCM_TAG_LEN =>
+                    {
+                        (iv, tag)
+                    }
+                    _ => return Err(JwtError::DecryptionFailed),
+                };
+
+                let cek = symm::decrypt_aead(
+                    symm::Cipher::aes_256_gcm(),
+                    key,
+                    Some(&iv.0),
+                    &[],
+                    encrypted_key,
+                    &tag.0,
+                )
+                .map_err(|e| {
+                    debug!(?e, "unable to decrypt content encryption key");
+                    JwtError::DecryptionFailed
+                })?;
+
+                if cek.len() != header.enc.key_len() {
+                    return Err(JwtError::DecryptionFailed);
+                }
+                Ok(cek)
+            }
+            JweDecrypter::PBES2_HS256_A128KW {
+                password,
+                min_iterations,
+            } => {
+                let (p2s, p2c) = match (&header.p2s, header.p2c) {
+                    (Some(p2s), Some(p2c)) => (p2s, p2c),
+                    _ => return Err(JwtError::DecryptionFailed),
+                };
+
+                if p2s.0.len() < PBES2_MIN_SALT_LEN {
+                    debug!("pbes2 salt is too short");
+                    return Err(JwtError::DecryptionFailed);
+                }
+
+                if p2c < *min_iterations || p2c > PBES2_MAX_ITERATIONS {
+                    debug!(?p2c, "pbes2 iteration count is not accepted");
+                    return Err(JwtError::DecryptionFailed);
+                }
+
+                let kek = pbes2_hs256_a128kw_key(password, &p2s.0, p2c)?;
+                aes_key_unwrap(&kek, encrypted_key, header.enc)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The content of a jwe that is being encrypted, or has been decrypted.
+pub struct Jwe {
+    header: JweHeader,
+    payload: Vec<u8>,
+}
+
+impl Jwe {
+    /// Create a jwe that will encrypt this payload.
+    pub fn new(payload: Vec<u8>) -> Self {
+        Jwe {
+            header: JweHeader::default(),
+            payload,
+        }
+    }
+
+    /// Set the header parameters of this jwe.
+    pub fn set_header(mut self, header: JweHeader) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// The payload of this jwe.
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// Encrypt this payload for the recipient of this encrypter, using this content
+    /// encryption algorithm.
+    pub fn encrypt(&self, encrypter: &JweEncrypter, enc: JweEnc) -> Result<JweCompact, JwtError> {
+        let mut header = JweProtectedHeader {
+            alg: encrypter.alg(),
+            enc,
+            kid: self.header.kid.clone(),
+            typ: self.header.typ.clone(),
+            cty: self.header.cty.clone(),
+            epk: None,
+            apu: self.header.apu.clone(),
+            apv: self.header.apv.clone(),
+            iv: None,
+            tag: None,
+            p2s: None,
+            p2c: None,
+            crit: None,
+            extra: BTreeMap::new(),
+        };
+
+        let (cek, encrypted_key) = encrypter.encrypt_cek(&mut header)?;
+
+        let hdr_json =
+            serde_json::to_vec(&header).map_err(|e| JwtError::InvalidHeaderFormat(e.into()))?;
+        let aad = base64::encode_config(hdr_json, base64::URL_SAFE_NO_PAD);
+
+        let iv = random_bytes(GCM_IV_LEN)?;
+        let mut tag = vec![0; GCM_TAG_LEN];
+        let ciphertext = symm::encrypt_aead(
+            enc.cipher(),
+            &cek,
+            Some(&iv),
+            aad.as_bytes(),
+            &self.payload,
+            &mut tag,
+        )
+        .map_err(|e| JwtError::OpenSSLError(e.into()))?;
+
+        Ok(JweCompact {
+            header,
+            aad,
+            encrypted_key,
+            iv,
+            ciphertext,
+            tag,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A jwe in compact form, which can be decrypted or converted to a string.
+pub struct JweCompact {
+    header: JweProtectedHeader,
+    // The protected header exactly as it was encoded, which is the additional
+    // authenticated data of the content encryption.
+    aad: String,
+    encrypted_key: Vec<u8>,
+    iv: Vec<u8>,
+    ciphertext: Vec<u8>,
+    tag: Vec<u8>,
+}
+
+impl JweCompact {
+    /// The protected header of this jwe. These values are not trustworthy until the jwe is
+    /// decrypted.
+    pub fn header(&self) -> &JweProtectedHeader {
+        &self.header
+    }
+
+    /// Using this JweDecrypter, decrypt and authenticate the payload of this jwe.
+    pub fn decrypt(&self, decrypter: &JweDecrypter) -> Result<Jwe, JwtError> {
+        if decrypter.alg() != self.header.alg {
+            return Err(JwtError::ValidatorAlgMismatch);
+        }
+
+        if self.iv.len() != GCM_IV_LEN || self.tag.len() != GCM_TAG_LEN {
+            return Err(JwtError::DecryptionFailed);
+        }
+
+        let cek = decrypter.decrypt_cek(&self.header, &self.encrypted_key)?;
+
+        let payload = symm::decrypt_aead(
+            self.header.enc.cipher(),
+            &cek,
+            Some(&self.iv),
+            self.aad.as_bytes(),
+            &self.ciphertext,
+            &self.tag,
+        )
+        .map_err(|e| {
+            debug!(?e, "unable to decrypt content");
+            JwtError::DecryptionFailed
+        })?;
+
+        Ok(Jwe {
+            header: (&self.header).into(),
+            payload,
+        })
+    }
+}
+
+impl FromStr for JweCompact {
+    type Err = JwtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // header.encrypted_key.iv.ciphertext.tag
+        let parts: Vec<&str> = s.split('.').collect();
+        let (aad, encrypted_key, iv, ciphertext, tag) = match parts.as_slice() {
+            [aad, encrypted_key, iv, ciphertext, tag] => (*aad, encrypted_key, iv, ciphertext, tag),
+            _ => return Err(JwtError::InvalidCompactFormat),
+        };
+
+        let header: JweProtectedHeader = base64::decode_config(aad, base64::URL_SAFE_NO_PAD)
+            .map_err(|e| JwtError::InvalidBase64(e.into()))
+            .and_then(|bytes| {
+                serde_json::from_slice(&bytes).map_err(|e| JwtError::InvalidHeaderFormat(e.into()))
+            })?;
+
+        // No critical extensions are understood.
+        if let Some(name) = header.crit.as_ref().and_then(|crit| crit.first()) {
+            return Err(JwtError::CriticalExtension { name: name.clone() });
+        }
+
+        // Compressed payloads are not supported.
+        if header.extra.contains_key("zip") {
+            return Err(JwtError::InvalidHeaderParameter {
+                name: "zip".to_string(),
+            });
+        }
+
+        let decode = |part: &str| {
+            base64::decode_config(part, base64::URL_SAFE_NO_PAD)
+                .map_err(|e| JwtError::InvalidBase64(e.into()))
+        };
+
+        Ok(JweCompact {
+            header,
+            aad: aad.to_string(),
+            encrypted_key: decode(encrypted_key)?,
+            iv: decode(iv)?,
+            ciphertext: decode(ciphertext)?,
+            tag: decode(tag)?,
+        })
+    }
+}
+
+impl fmt::Display for JweCompact {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let encode = |data: &[u8]| base64::encode_config(data, base64::URL_SAFE_NO_PAD);
+        write!(
+            f,
+            "{}.{}.{}.{}.{}",
+            self.aad,
+            encode(&self.encrypted_key),
+            encode(&self.iv),
+            encode(&self.ciphertext),
+            encode(&self.tag)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        aes_key_unwrap, aes_key_wrap, concat_kdf, ec_public_key_from_jwk, ecdh_shared_secret, Jwe,
+        JweAlg, JweCompact, JweDecrypter, JweEnc, JweEncrypter, JweHeader, PBES2_MAX_ITERATIONS,
+    };
+    use crate::base64_data::Base64UrlSafeData;
+    use crate::crypto::{EcCurve, Jwk, JwkKeySet, JwkUse, JwsSigner, JwsValidator};
+    use crate::error::JwtError;
+    use crate::jws::{Jws, JwsUnverified};
+    use openssl::{bn, ec, nid, rsa};
+    use std::convert::TryFrom;
+    use std::str::FromStr;
+
+    // https://datatracker.ietf.org/doc/html/rfc7516#appendix-A.1
+    const RFC7516_A1_RSA_KEY: [&str; 8] = [
+            "oahUIoWw0K0usKNuOR6H4wkf4oBUXHTxRvgb48E-BVvxkeDNjbC4he8rUWcJoZmds2h7M70imEVhRU5djINXtqllXI4DFqcI1DgjT9LewND8MW2Krf3Spsk_ZkoFnilakGygTwpZ3uesH-PFABNIUYpOiN15dsQRkgr0vEhxN92i2asbOenSZeyaxziK72UwxrrKoExv6kc5twXTq4h-QChLOln0_mtUZwfsRaMStPs6mS6XrgxnxbWhojf663tuEQueGC-FCMfra36C9knDFGzKsNa7LZK2djYgyD3JR_MB_4NUJW_TqOQtwHYbxevoJArm-L5StowjzGy-_bq6Gw",
+            "AQAB",
+            "kLdtIj6GbDks_ApCSTYQtelcNttlKiOyPzMrXHeI-yk1F7-kpDxY4-WY5NWV5KntaEeXS1j82E375xxhWMHXyvjYecPT9fpwR_M9gV8n9Hrh2anTpTD93Dt62ypW3yDsJzBnTnrYu1iwWRgBKrEYY46qAZIrA2xAwnm2X7uGR1hghkqDp0Vqj3kbSCz1XyfCs6_LehBwtxHIyh8Ripy40p24moOAbgxVw3rxT_vlt3UVe4WO3JkJOzlpUf-KTVI2Ptgm-dARxTEtE-id-4OJr0h-K-VFs3VSndVTIznSxfyrj8ILL6MG_Uv8YAu7VILSB3lOW085-4qE3DzgrTjgyQ",
+            "1r52Xk46c-LsfB5P442p7atdPUrxQSy4mti_tZI3Mgf2EuFVbUoDBvaRQ-SWxkbkmoEzL7JXroSBjSrK3YIQgYdMgyAEPTPjXv_hI2_1eTSPVZfzL0lffNn03IXqWF5MDFuoUYE0hzb2vhrlN_rKrbfDIwUbTrjjgieRbwC6Cl0",
+            "wLb35x7hmQWZsWJmB_vle87ihgZ19S8lBEROLIsZG4ayZVe9Hi9gDVCOBmUDdaDYVTSNx_8Fyw1YYa9XGrGnDew00J28cRUoeBB_jKI1oma0Orv1T9aXIWxKwd4gvxFImOWr3QRL9KEBRzk2RatUBnmDZJTIAfwTs0g68UZHvtc",
+            "ZK-YwE7diUh0qR1tR7w8WHtolDx3MZ_OTowiFvgfeQ3SiresXjm9gZ5KLhMXvo-uz-KUJWDxS5pFQ_M0evdo1dKiRTjVw_x4NyqyXPM5nULPkcpU827rnpZzAJKpdhWAgqrXGKAECQH0Xt4taznjnd_zVpAmZZq60WPMBMfKcuE",
+            "Dq0gfgJ1DdFGXiLvQEZnuKEN0UUmsJBxkjydc3j4ZYdBiMRAy86x0vHCjywcMlYYg4yoC4YZa9hNVcsjqA3FeiL19rk8g6Qn29Tt0cj8qqyFpz9vNDBUfCAiJVeESOjJDZPYHdHY8v1b-o-Z2X5tvLx-TCekf7oxyeKDUqKWjis",
+            "VIMpMYbPf47dT1w_zDUXfPimsSegnMOA1zTaX7aGk_8urY6R8-ZW1FxU7AlWAyLWybqq6t16VFd7hQd0y6flUK4SlOydB61gwanOsXGOAOv82cHq0E3eL4HrtZkUuKvnPrMnsUUFlfUdybVzxyjz9JF_XyaY14ardLSjf4L_FNY",
+    ];
+
+    fn rsa_der_from_components(components: [&str; 8]) -> Vec<u8> {
+        let [n, e, d, p, q, dp, dq, qi] = components.map(|c| {
+            let bytes = base64::decode_config(c, base64::URL_SAFE_NO_PAD).expect("Invalid base64");
+            bn::BigNum::from_slice(&bytes).expect("Invalid bignum")
+        });
+        rsa::Rsa::from_private_components(n, e, d, p, q, dp, dq, qi)
+            .and_then(|skey| skey.private_key_to_der())
+            .expect("Invalid rsa key")
+    }
+
+    // https://datatracker.ietf.org/doc/html/rfc7516#appendix-A.1
+    #[test]
+    fn rfc7516_rsa_oaep_a256gcm_example() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let der = rsa_der_from_components(RFC7516_A1_RSA_KEY);
+        let test_jwe = "eyJhbGciOiJSU0EtT0FFUCIsImVuYyI6IkEyNTZHQ00ifQ.OKOawDo13gRp2ojaHV7LFpZcgV7T6DVZKTyKOMTYUmKoTCVJRgckCL9kiMT03JGeipsEdY3mx_etLbbWSrFr05kLzcSr4qKAq7YN7e9jwQRb23nfa6c9d-StnImGyFDbSv04uVuxIp5Zms1gNxKKK2Da14B8S4rzVRltdYwam_lDp5XnZAYpQdb76FdIKLaVmqgfwX7XWRxv2322i-vDxRfqNzo_tETKzpVLzfiwQyeyPGLBIO56YJ7eObdv0je81860ppamavo35UgoRdbYaBcoh9QcfylQr66oc6vFWXRcZ_ZT2LawVCWTIy3brGPi6UklfCpIMfIjf7iGdXKHzg.48V1_ALb6US04U3b.5eym8TW_c8SuK0ltJ3rpYIzOeDQz7TALvtu6UG9oMo4vpzs9tX_EFShS8iB7j6jiSdiwkIr3ajwQzaBtQD_A.XFBoMYUZodetZdvTiFvSkQ";
+
+        let jwec = JweCompact::from_str(test_jwe).expect("Invalid compact jwe");
+        assert!(jwec.to_string() == test_jwe);
+        assert!(jwec.header().alg() == JweAlg::RSA_OAEP);
+        assert!(jwec.header().enc() == JweEnc::A256GCM);
+
+        let jwe_decrypter = JweDecrypter::from_rsa_oaep_der(&der).expect("Invalid key");
+        assert!(matches!(
+            jwe_decrypter.get_encrypter(),
+            Err(JwtError::ValidatorAlgMismatch)
+        ));
+        let released = jwec.decrypt(&jwe_decrypter).expect("Unable to decrypt jwe");
+        assert!(
+            released.payload()
+                == b"The true sign of intelligence is not knowledge but imagination."
+        );
+
+        // The key is only valid for the algorithm it was created for.
+        let jwe_decrypter = JweDecrypter::from_rsa_oaep_256_der(&der).expect("Invalid key");
+        assert!(matches!(
+            jwec.decrypt(&jwe_decrypter),
+            Err(JwtError::ValidatorAlgMismatch)
+        ));
+
+        // Any change to the protected header, iv or ciphertext is detected by the tag.
+        let jwe_decrypter = JweDecrypter::from_rsa_oaep_der(&der).expect("Invalid key");
+        let tampered = test_jwe.replace(".48V1_ALb6US04U3b.", ".48V1_ALb6US04U3c.");
+        let jwec = JweCompact::from_str(&tampered).expect("Invalid compact jwe");
+        assert!(matches!(
+            jwec.decrypt(&jwe_decrypter),
+            Err(JwtError::DecryptionFailed)
+        ));
+
+        // An invalid encrypted key fails in the same way.
+        let tampered = test_jwe.replace(".OKOawDo13g", ".OKOawDo13h");
+        let jwec = JweCompact::from_str(&tampered).expect("Invalid compact jwe");
+        assert!(matches!(
+            jwec.decrypt(&jwe_decrypter),
+            Err(JwtError::DecryptionFailed)
+        ));
+
+        assert!(matches!(
+            JweCompact::from_str("eyJhbGciOiJSU0EtT0FFUCIsImVuYyI6IkEyNTZHQ00ifQ.AA.AA.AA"),
+            Err(JwtError::InvalidCompactFormat)
+        ));
+    }
+
+    #[test]
+    fn test_rsa_oaep_256_encrypt_and_decrypt() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let jwe_decrypter = JweDecrypter::generate_rsa_oaep_256().expect("Unable to generate key");
+        let pub_jwk = jwe_decrypter
+            .public_key_as_jwk(Some("enc-1"))
+            .expect("Unable to export key");
+        let jwe_encrypter = JweEncrypter::from_rsa_oaep_256_jwk(&pub_jwk).expect("Invalid key");
+
+        for enc in [JweEnc::A128GCM, JweEnc::A256GCM] {
+            let jwe_str = Jwe::new(b"Hello".to_vec())
+                .set_header(JweHeader::new().set_kid("enc-1"))
+                .encrypt(&jwe_encrypter, enc)
+                .expect("Unable to encrypt jwe")
+                .to_string();
+
+            let jwec = JweCompact::from_str(&jwe_str).expect("Invalid compact jwe");
+            assert!(jwec.header().alg() == JweAlg::RSA_OAEP_256);
+            assert!(jwec.header().enc() == enc);
+            assert!(jwec.header().kid() == Some("enc-1"));
+
+            let released = jwec.decrypt(&jwe_decrypter).expect("Unable to decrypt jwe");
+            assert!(released.payload() == b"Hello");
+        }
+
+        // Only the holder of the private key can decrypt.
+        let jwec = Jwe::new(b"Hello".to_vec())
+            .encrypt(&jwe_encrypter, JweEnc::A256GCM)
+            .expect("Unable to encrypt jwe");
+        let other = JweDecrypter::generate_rsa_oaep_256().expect("Unable to generate key");
+        assert!(matches!(
+            jwec.decrypt(&other),
+            Err(JwtError::DecryptionFailed)
+        ));
+
+        // A key that is limited to signing can not be used for encryption.
+        let mut sig_jwk = pub_jwk;
+        if let Jwk::RSA { use_, .. } = &mut sig_jwk {
+            *use_ = Some(JwkUse::Sig);
+        }
+        assert!(matches!(
+            JweEncrypter::from_rsa_oaep_256_jwk(&sig_jwk),
+            Err(JwtError::InvalidKey)
+        ));
+    }
+
+    #[test]
+    fn test_encrypt_to_published_rsa_jwk() {
+        let _ = tracing_subscriber::fmt().try_init();
+        // A relying party publishes its signing and encryption keys in one set.
+        let jwks_str = r#"{"keys":[
+            {"kty":"RSA","use":"sig","alg":"RS256","kid":"sig-1","e":"AQAB","n":"oahUIoWw0K0usKNuOR6H4wkf4oBUXHTxRvgb48E-BVvxkeDNjbC4he8rUWcJoZmds2h7M70imEVhRU5djINXtqllXI4DFqcI1DgjT9LewND8MW2Krf3Spsk_ZkoFnilakGygTwpZ3uesH-PFABNIUYpOiN15dsQRkgr0vEhxN92i2asbOenSZeyaxziK72UwxrrKoExv6kc5twXTq4h-QChLOln0_mtUZwfsRaMStPs6mS6XrgxnxbWhojf663tuEQueGC-FCMfra36C9knDFGzKsNa7LZK2djYgyD3JR_MB_4NUJW_TqOQtwHYbxevoJArm-L5StowjzGy-_bq6Gw"},
+            {"kty":"RSA","use":"enc","alg":"RSA-OAEP-256","kid":"enc-1","e":"AQAB","n":"oahUIoWw0K0usKNuOR6H4wkf4oBUXHTxRvgb48E-BVvxkeDNjbC4he8rUWcJoZmds2h7M70imEVhRU5djINXtqllXI4DFqcI1DgjT9LewND8MW2Krf3Spsk_ZkoFnilakGygTwpZ3uesH-PFABNIUYpOiN15dsQRkgr0vEhxN92i2asbOenSZeyaxziK72UwxrrKoExv6kc5twXTq4h-QChLOln0_mtUZwfsRaMStPs6mS6XrgxnxbWhojf663tuEQueGC-FCMfra36C9knDFGzKsNa7LZK2djYgyD3JR_MB_4NUJW_TqOQtwHYbxevoJArm-L5StowjzGy-_bq6Gw"}
+        ]}"#;
+        let jwks: JwkKeySet = serde_json::from_str(jwks_str).expect("Invalid jwks");
+        let enc_jwk = &jwks.keys[1];
+        assert!(serde_json::to_string(enc_jwk)
+            .expect("Unable to serialise jwk")
+            .contains(r#""alg":"RSA-OAEP-256""#));
+
+        let jwe_encrypter = JweEncrypter::from_rsa_oaep_256_jwk(enc_jwk).expect("Invalid key");
+        let jwe_str = Jwe::new(b"Hello".to_vec())
+            .set_header(JweHeader::new().set_kid("enc-1"))
+            .encrypt(&jwe_encrypter, JweEnc::A256GCM)
+            .expect("Unable to encrypt jwe")
+            .to_string();
+
+        let jwe_decrypter =
+            JweDecrypter::from_rsa_oaep_256_der(&rsa_der_from_components(RFC7516_A1_RSA_KEY))
+                .expect("Invalid key");
+        let released = JweCompact::from_str(&jwe_str)
+            .and_then(|jwec| jwec.decrypt(&jwe_decrypter))
+            .expect("Unable to decrypt jwe");
+        assert!(released.payload() == b"Hello");
+
+        // The exported key declares its algorithm.
+        let pub_jwk = jwe_decrypter
+            .public_key_as_jwk(Some("enc-1"))
+            .expect("Unable to export key");
+        assert!(pub_jwk == *enc_jwk);
+
+        // The signing key can not be used for encryption, and the encryption key can not
+        // be used for validation.
+        assert!(matches!(
+            JweEncrypter::from_rsa_oaep_256_jwk(&jwks.keys[0]),
+            Err(JwtError::InvalidKey)
+        ));
+        assert!(matches!(
+            JwsValidator::try_from(enc_jwk),
+            Err(JwtError::ValidatorAlgMismatch)
+        ));
+
+        // A key limited to another algorithm is rejected.
+        let mut other_alg = enc_jwk.clone();
+        if let Jwk::RSA { alg, .. } = &mut other_alg {
+            *alg = Some(JweAlg::RSA_OAEP.into());
+        }
+        assert!(matches!(
+            JweEncrypter::from_rsa_oaep_256_jwk(&other_alg),
+            Err(JwtError::ValidatorAlgMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_nested_signed_token() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let jwss = JwsSigner::generate_es256().expect("failed to construct signer.");
+        let jws_validator = jwss.get_validator().expect("Unable to create validator");
+        let jwe_decrypter = JweDecrypter::generate_rsa_oaep_256().expect("Unable to generate key");
+        let jwe_encrypter = jwe_decrypter
+            .get_encrypter()
+            .expect("Unable to create encrypter");
+
+        let jws = Jws {
+            inner: serde_json::json!({ "sub": "Hello" }),
+        };
+        let jws_str = jws.sign(&jwss).expect("failed to sign jws").to_string();
+
+        // Sign, then encrypt.
+        let jwe_str = Jwe::new(jws_str.into_bytes())
+            .set_header(JweHeader::new().set_cty("JWT"))
+            .encrypt(&jwe_encrypter, JweEnc::A256GCM)
+            .expect("Unable to encrypt jwe")
+            .to_string();
+
+        let jwec = JweCompact::from_str(&jwe_str).expect("Invalid compact jwe");
+        assert!(jwec.header().cty() == Some("JWT"));
+        let released = jwec.decrypt(&jwe_decrypter).expect("Unable to decrypt jwe");
+
+        let jws_str = std::str::from_utf8(released.payload()).expect("Invalid utf8");
+        let released: Jws<serde_json::Value> = JwsUnverified::from_str(jws_str)
+            .expect("Unable to parse jws")
+            .validate(&jws_validator)
+            .expect("Unable to validate jws");
+        assert!(released == jws);
+    }
+
+    fn b64(s: &str) -> Vec<u8> {
+        base64::decode_config(s, base64::URL_SAFE_NO_PAD).expect("Invalid base64")
+    }
+
+    fn p256_jwk(x: &str, y: &str) -> Jwk {
+        Jwk::EC {
+            crv: EcCurve::P256,
+            x: Base64UrlSafeData(b64(x)),
+            y: Base64UrlSafeData(b64(y)),
+            alg: None,
+            use_: None,
+            kid: None,
+        }
+    }
+
+    // The private key of Bob from RFC 7518 Appendix C.
+    fn rfc7518_bob_der() -> Vec<u8> {
+        let bob_pub = ec_public_key_from_jwk(
+            &p256_jwk(
+                "weNJy2HscCSM6AEDTDg04biOvhFhyyWvOHQfeF_PxMQ",
+                "e8lnCO-AlStT-NJVX-crhB7QRYhiix03illJOVAOyck",
+            ),
+            JweAlg::ECDH_ES,
+        )
+        .expect("Invalid key");
+        let bob_d = bn::BigNum::from_slice(&b64("VEmDZpDXXK8p8N0Cndsxs924q6nS1RXFASRl6BfUqdw"))
+            .expect("Invalid bignum");
+        ec::EcKey::from_private_components(bob_pub.group(), &bob_d, bob_pub.public_key())
+            .and_then(|skey| skey.private_key_to_der())
+            .expect("Invalid ec key")
+    }
+
+    // https://datatracker.ietf.org/doc/html/rfc7518#appendix-C
+    #[test]
+    fn rfc7518_ecdh_es_key_agreement_example() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let alice_epk = p256_jwk(
+            "gI0GAILBdu7T53akrFmMyGcsF3n5dO7MmwNBHKW5SV0",
+            "SLW_xSffzlPWrHEVI30DHM_4egVwt3NQqeUD7nMFpps",
+        );
+        let bob_der = rfc7518_bob_der();
+
+        let jwe_decrypter = JweDecrypter::from_ecdh_es_der(&bob_der).expect("Invalid key");
+        let skey = match &jwe_decrypter {
+            JweDecrypter::ECDH_ES { skey } => skey,
+            _ => unreachable!(),
+        };
+
+        let epk = ec_public_key_from_jwk(&alice_epk, JweAlg::ECDH_ES).expect("Invalid key");
+        let z = ecdh_shared_secret(skey, &epk).expect("Unable to agree key");
+        assert!(z[..4] == [158, 86, 217, 29]);
+
+        let cek = concat_kdf(&z, "A128GCM", b"Alice", b"Bob", 16).expect("Unable to derive key");
+        assert!(cek == b64("VqqN6vgjbSBcIijNcacQGg"));
+
+        // The recipient's public key round trips through the jwk.
+        let pub_jwk = jwe_decrypter
+            .public_key_as_jwk(None)
+            .expect("Unable to export key");
+        if let Jwk::EC { x, y, .. } = &pub_jwk {
+            assert!(x.0 == b64("weNJy2HscCSM6AEDTDg04biOvhFhyyWvOHQfeF_PxMQ"));
+            assert!(y.0 == b64("e8lnCO-AlStT-NJVX-crhB7QRYhiix03illJOVAOyck"));
+        } else {
+            panic!("Invalid jwk");
+        }
+    }
+
+    #[test]
+    fn test_ecdh_es_encrypt_and_decrypt() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let decrypters = [
+            (
+                JweAlg::ECDH_ES,
+                JweDecrypter::generate_ecdh_es().expect("Unable to generate key"),
+            ),
+            (
+                JweAlg::ECDH_ES_A128KW,
+                JweDecrypter::generate_ecdh_es_a128kw().expect("Unable to generate key"),
+            ),
+            (
+                JweAlg::ECDH_ES_A256KW,
+                JweDecrypter::generate_ecdh_es_a256kw().expect("Unable to generate key"),
+            ),
+        ];
+
+        for (alg, jwe_decrypter) in decrypters.iter() {
+            let pub_jwk = jwe_decrypter
+                .public_key_as_jwk(Some("enc-1"))
+                .expect("Unable to export key");
+            let jwe_encrypter = match alg {
+                JweAlg::ECDH_ES => JweEncrypter::from_ecdh_es_jwk(&pub_jwk),
+                JweAlg::ECDH_ES_A128KW => JweEncrypter::from_ecdh_es_a128kw_jwk(&pub_jwk),
+                _ => JweEncrypter::from_ecdh_es_a256kw_jwk(&pub_jwk),
+            }
+            .expect("Invalid key");
+            assert!(jwe_encrypter.alg() == *alg);
+
+            for enc in [JweEnc::A128GCM, JweEnc::A256GCM] {
+                let jwe_str = Jwe::new(b"Hello".to_vec())
+                    .set_header(JweHeader::new().set_apu(b"Alice").set_apv(b"Bob"))
+                    .encrypt(&jwe_encrypter, enc)
+                    .expect("Unable to encrypt jwe")
+                    .to_string();
+
+                let jwec = JweCompact::from_str(&jwe_str).expect("Invalid compact jwe");
+                assert!(jwec.header().alg() == *alg);
+                assert!(jwec.header().enc() == enc);
+                assert!(jwec.header().epk().is_some());
+                assert!(jwec.header().apu() == Some(b"Alice".as_slice()));
+                assert!(jwec.header().apv() == Some(b"Bob".as_slice()));
+                // Direct key agreement has no encrypted key.
+                assert!(
+                    jwe_str.split('.').nth(1).map(str::is_empty) == Some(*alg == JweAlg::ECDH_ES)
+                );
+
+                let released = jwec.decrypt(jwe_decrypter).expect("Unable to decrypt jwe");
+                assert!(released.payload() == b"Hello");
+            }
+        }
+
+        // Only the holder of the private key can decrypt.
+        let jwe_encrypter = decrypters[1]
+            .1
+            .get_encrypter()
+            .expect("Unable to create encrypter");
+        let jwec = Jwe::new(b"Hello".to_vec())
+            .encrypt(&jwe_encrypter, JweEnc::A128GCM)
+            .expect("Unable to encrypt jwe");
+        let other = JweDecrypter::generate_ecdh_es_a128kw().expect("Unable to generate key");
+        assert!(matches!(
+            jwec.decrypt(&other),
+            Err(JwtError::DecryptionFailed)
+        ));
+
+        // The key must match the algorithm of the token.
+        assert!(matches!(
+            jwec.decrypt(&decrypters[2].1),
+            Err(JwtError::ValidatorAlgMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_encrypt_to_published_ec_jwk() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let jwk_str = r#"{"kty":"EC","crv":"P-256","use":"enc","alg":"ECDH-ES+A128KW","kid":"enc-2","x":"weNJy2HscCSM6AEDTDg04biOvhFhyyWvOHQfeF_PxMQ","y":"e8lnCO-AlStT-NJVX-crhB7QRYhiix03illJOVAOyck"}"#;
+        let enc_jwk: Jwk = serde_json::from_str(jwk_str).expect("Invalid jwk");
+
+        let jwe_encrypter = JweEncrypter::from_ecdh_es_a128kw_jwk(&enc_jwk).expect("Invalid key");
+        let jwe_str = Jwe::new(b"Hello".to_vec())
+            .encrypt(&jwe_encrypter, JweEnc::A128GCM)
+            .expect("Unable to encrypt jwe")
+            .to_string();
+
+        let jwe_decrypter =
+            JweDecrypter::from_ecdh_es_a128kw_der(&rfc7518_bob_der()).expect("Invalid key");
+        let released = JweCompact::from_str(&jwe_str)
+            .and_then(|jwec| jwec.decrypt(&jwe_decrypter))
+            .expect("Unable to decrypt jwe");
+        assert!(released.payload() == b"Hello");
+
+        // The exported key declares its algorithm.
+        let pub_jwk = jwe_decrypter
+            .public_key_as_jwk(Some("enc-2"))
+            .expect("Unable to export key");
+        assert!(pub_jwk == enc_jwk);
+
+        // The key is limited to ECDH-ES+A128KW.
+        assert!(matches!(
+            JweEncrypter::from_ecdh_es_jwk(&enc_jwk),
+            Err(JwtError::ValidatorAlgMismatch)
+        ));
+        assert!(matches!(
+            JweEncrypter::from_ecdh_es_a256kw_jwk(&enc_jwk),
+            Err(JwtError::ValidatorAlgMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_ecdh_es_invalid_curve_point() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let jwe_decrypter = JweDecrypter::generate_ecdh_es().expect("Unable to generate key");
+        let pub_jwk = jwe_decrypter
+            .public_key_as_jwk(None)
+            .expect("Unable to export key");
+
+        // Move the point off the curve by changing its y coordinate.
+        let mut off_curve = pub_jwk.clone();
+        if let Jwk::EC { y, .. } = &mut off_curve {
+            y.0[31] ^= 0x01;
+        }
+        assert!(matches!(
+            JweEncrypter::from_ecdh_es_jwk(&off_curve),
+            Err(JwtError::InvalidKey)
+        ));
+
+        // Only P-256 is supported.
+        let mut other_curve = pub_jwk.clone();
+        if let Jwk::EC { crv, .. } = &mut other_curve {
+            *crv = EcCurve::P384;
+        }
+        assert!(matches!(
+            JweEncrypter::from_ecdh_es_jwk(&other_curve),
+            Err(JwtError::InvalidKey)
+        ));
+
+        // A point on another curve, presented as a P-256 point.
+        let p384 = ec::EcGroup::from_curve_name(nid::Nid::SECP384R1)
+            .and_then(|group| ec::EcKey::generate(&group))
+            .expect("Unable to generate key");
+        let mut bnctx = bn::BigNumContext::new().expect("Unable to create context");
+        let mut xbn = bn::BigNum::new().expect("Unable to create bignum");
+        let mut ybn = bn::BigNum::new().expect("Unable to create bignum");
+        p384.public_key()
+            .affine_coordinates(p384.group(), &mut xbn, &mut ybn, &mut bnctx)
+            .expect("Unable to get coordinates");
+        let p384_point = Jwk::EC {
+            crv: EcCurve::P256,
+            x: Base64UrlSafeData(xbn.to_vec()),
+            y: Base64UrlSafeData(ybn.to_vec()),
+            alg: None,
+            use_: None,
+            kid: None,
+        };
+        assert!(matches!(
+            JweEncrypter::from_ecdh_es_jwk(&p384_point),
+            Err(JwtError::InvalidKey)
+        ));
+
+        // An attacker substitutes an invalid point as the ephemeral key of a token.
+        let jwe_encrypter = jwe_decrypter
+            .get_encrypter()
+            .expect("Unable to create encrypter");
+        let jwe_str = Jwe::new(b"Hello".to_vec())
+            .encrypt(&jwe_encrypter, JweEnc::A128GCM)
+            .expect("Unable to encrypt jwe")
+            .to_string();
+        let (hdr, rest) = jwe_str.split_once('.').expect("Invalid compact jwe");
+        let mut header: serde_json::Value =
+            serde_json::from_slice(&b64(hdr)).expect("Invalid header");
+        header["epk"] = serde_json::to_value(&off_curve).expect("Invalid jwk");
+        let hdr = base64::encode_config(
+            serde_json::to_vec(&header).expect("Invalid header"),
+            base64::URL_SAFE_NO_PAD,
+        );
+        let jwec = JweCompact::from_str(&format!("{}.{}", hdr, rest)).expect("Invalid compact jwe");
+        assert!(matches!(
+            jwec.decrypt(&jwe_decrypter),
+            Err(JwtError::InvalidKey)
+        ));
+
+        // The ephemeral key is required.
+        header
+            .as_object_mut()
+            .expect("Invalid header")
+            .remove("epk");
+        let hdr = base64::encode_config(
+            serde_json::to_vec(&header).expect("Invalid header"),
+            base64::URL_SAFE_NO_PAD,
+        );
+        let jwec = JweCompact::from_str(&format!("{}.{}", hdr, rest)).expect("Invalid compact jwe");
+        assert!(matches!(
+            jwec.decrypt(&jwe_decrypter),
+            Err(JwtError::InvalidKey)
+        ));
+    }
+
+    fn oct_jwk(k: &[u8]) -> Jwk {
+        Jwk::oct {
+            k: Base64UrlSafeData(k.to_vec()),
+            alg: None,
+            use_: None,
+            kid: None,
+        }
+    }
+
+    // https://datatracker.ietf.org/doc/html/rfc3394#section-4.1
+    #[test]
+    fn rfc3394_aes_key_wrap_example() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let kek: Vec<u8> = (0..16).collect();
+        let key: Vec<u8> = (0..16).map(|i| i * 0x11).collect();
+        let wrapped = vec![
+            0x1f, 0xa6, 0x8b, 0x0a, 0x81, 0x12, 0xb4, 0x47, 0xae, 0xf3, 0x4b, 0xd8, 0xfb, 0x5a,
+            0x7b, 0x82, 0x9d, 0x3e, 0x86, 0x23, 0x71, 0xd2, 0xcf, 0xe5,
+        ];
+
+        assert!(aes_key_wrap(&kek, &key).expect("Unable to wrap key") == wrapped);
+        assert!(
+            aes_key_unwrap(&kek, &wrapped, JweEnc::A128GCM).expect("Unable to unwrap key") == key
+        );
+
+        // Integrity is checked, and lengths are validated before unwrapping.
+        let mut tampered = wrapped.clone();
+        tampered[0] ^= 0x01;
+        assert!(matches!(
+            aes_key_unwrap(&kek, &tampered, JweEnc::A128GCM),
+            Err(JwtError::DecryptionFailed)
+        ));
+        for len in [0, 8, 23] {
+            assert!(matches!(
+                aes_key_unwrap(&kek, &wrapped[..len], JweEnc::A128GCM),
+                Err(JwtError::DecryptionFailed)
+            ));
+        }
+        // The key is the wrong size for the enc.
+        assert!(matches!(
+            aes_key_unwrap(&kek, &wrapped, JweEnc::A256GCM),
+            Err(JwtError::DecryptionFailed)
+        ));
+    }
+
+    // Tokens created with python joserfc 1.6.5, using jwe.encrypt_compact with keys from
+    // OctKey.import_key(bytes(range(16))) and OctKey.import_key(bytes(range(32))), and
+    // the payload b"Live long and prosper.". The PBES2 token uses the password below
+    // with a p2c of 4096.
+    #[test]
+    fn test_symmetric_interop() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let k16: Vec<u8> = (0..16).collect();
+        let k32: Vec<u8> = (0..32).collect();
+
+        let vectors = [
+            (
+                JweDecrypter::from_a128kw_jwk(&oct_jwk(&k16)),
+                "eyJhbGciOiJBMTI4S1ciLCJlbmMiOiJBMTI4R0NNIn0.XsCfbx4NNScqcDwkrCNenUpNGfo3pKDt.wkJdLjjiDhAWagFm.SjKnhDyAE2HGzYfepoIPtxa_Oydq4A.GiUdtNeKeFsM0OsJkNWzVQ",
+            ),
+            (
+                JweDecrypter::from_a256kw_jwk(&oct_jwk(&k32)),
+                "eyJhbGciOiJBMjU2S1ciLCJlbmMiOiJBMjU2R0NNIn0.OBF65cUougMPMLS64l8HF9YFQeadhzryeYKZtT5-rn6iQhi43z7B2Q.f5d0qeyi4W7yYUhZ.WYzUqh1qPYhnJMihrN6Txs_7PAfB4g.OyDQ7vzME4nahtEnUx5kgA",
+            ),
+            (
+                JweDecrypter::from_a256gcmkw_jwk(&oct_jwk(&k32)),
+                "eyJhbGciOiJBMjU2R0NNS1ciLCJlbmMiOiJBMTI4R0NNIiwiaXYiOiIzbDYzdzY2TlVEV09wOFl5IiwidGFnIjoibENHLUl3c3d6dFhNQzh0MHBQTzBQUSJ9.uTiWz_HwNPSogiKMfxodYg.OUGr_F-6u8tqX51r.vkKXpk07YPdILLKBAJPpDnX-zyUnuA.RI5PkKuqU8Zd96TGdfLhdw",
+            ),
+            (
+                JweDecrypter::from_dir_jwk(&oct_jwk(&k32)),
+                "eyJhbGciOiJkaXIiLCJlbmMiOiJBMjU2R0NNIn0..L_2ZV7tcDirAqUeU.cbQ0X27WzLmrbizQz86svw89BHFMlg.2wKMUyzc1Nl95D2nEwctQg",
+            ),
+            (
+                JweDecrypter::from_pbes2_hs256_a128kw_password(
+                    b"Thus from my lips, by yours, my sin is purged.",
+                    4096,
+                ),
+                "eyJhbGciOiJQQkVTMi1IUzI1NitBMTI4S1ciLCJlbmMiOiJBMTI4R0NNIiwicDJjIjo0MDk2LCJwMnMiOiJIWHJrMTdSMjl4bGhTMDJkRG5NM253In0.F3BUKIhzQ1ZnRxM2zoF5cvHiKtg21lMo.OxB41IHFwdi3B7gq.FV8DUHdDNDBicFRaRZeHK4p2v8XvRw.-KWj4Kq5BByBQin2fjCsCA",
+            ),
+        ];
+
+        for (jwe_decrypter, jwe_str) in vectors {
+            let jwe_decrypter = jwe_decrypter.expect("Invalid key");
+            let jwec = JweCompact::from_str(jwe_str).expect("Invalid compact jwe");
+            assert!(jwec.header().alg() == jwe_decrypter.alg());
+            assert!(jwec.to_string() == jwe_str);
+
+            let released = jwec.decrypt(&jwe_decrypter).expect("Unable to decrypt jwe");
+            assert!(released.payload() == b"Live long and prosper.");
+        }
+    }
+
+    #[test]
+    fn test_symmetric_encrypt_and_decrypt() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let decrypters = [
+            JweDecrypter::generate_dir(JweEnc::A128GCM).expect("Unable to generate key"),
+            JweDecrypter::generate_dir(JweEnc::A256GCM).expect("Unable to generate key"),
+            JweDecrypter::generate_a128kw().expect("Unable to generate key"),
+            JweDecrypter::generate_a256kw().expect("Unable to generate key"),
+            JweDecrypter::generate_a256gcmkw().expect("Unable to generate key"),
+        ];
+
+        for jwe_decrypter in decrypters.iter() {
+            // The shared key round trips through an oct jwk.
+            let oct = jwe_decrypter
+                .secret_key_as_jwk(Some("enc-1"))
+                .expect("Unable to export key");
+            let oct_str = serde_json::to_string(&oct).expect("Unable to serialise jwk");
+            assert!(oct_str.contains(r#""kty":"oct""#));
+            let oct: Jwk = serde_json::from_str(&oct_str).expect("Invalid jwk");
+
+            let (restored, jwe_encrypter) = match jwe_decrypter.alg() {
+                JweAlg::dir => (
+                    JweDecrypter::from_dir_jwk(&oct),
+                    JweEncrypter::from_dir_jwk(&oct),
+                ),
+                JweAlg::A128KW => (
+                    JweDecrypter::from_a128kw_jwk(&oct),
+                    JweEncrypter::from_a128kw_jwk(&oct),
+                ),
+                JweAlg::A256KW => (
+                    JweDecrypter::from_a256kw_jwk(&oct),
+                    JweEncrypter::from_a256kw_jwk(&oct),
+                ),
+                _ => (
+                    JweDecrypter::from_a256gcmkw_jwk(&oct),
+                    JweEncrypter::from_a256gcmkw_jwk(&oct),
+                ),
+            };
+            let restored = restored.expect("Invalid key");
+            let jwe_encrypter = jwe_encrypter.expect("Invalid key");
+            assert!(jwe_encrypter.alg() == jwe_decrypter.alg());
+
+            // A shared key can not be published.
+            assert!(matches!(
+                jwe_decrypter.public_key_as_jwk(None),
+                Err(JwtError::JwkPublicKeyDenied)
+            ));
+
+            for enc in [JweEnc::A128GCM, JweEnc::A256GCM] {
+                let jwec = match Jwe::new(b"Hello".to_vec()).encrypt(&jwe_encrypter, enc) {
+                    Ok(jwec) => jwec,
+                    // A direct key can only be used with the enc of the same size.
+                    Err(JwtError::ValidatorAlgMismatch) if jwe_decrypter.alg() == JweAlg::dir => {
+                        continue
+                    }
+                    Err(e) => panic!("Unable to encrypt jwe {:?}", e),
+                };
+
+                let jwec = JweCompact::from_str(&jwec.to_string()).expect("Invalid compact jwe");
+                assert!(jwec.header().alg() == jwe_decrypter.alg());
+                assert!(jwec.header().enc() == enc);
+
+                let released = jwec.decrypt(jwe_decrypter).expect("Unable to decrypt jwe");
+                assert!(released.payload() == b"Hello");
+                let released = jwec.decrypt(&restored).expect("Unable to decrypt jwe");
+                assert!(released.payload() == b"Hello");
+            }
+        }
+
+        // Only the holder of the shared key can decrypt.
+        let jwe_encrypter = decrypters[4]
+            .get_encrypter()
+            .expect("Unable to create encrypter");
+        let jwec = Jwe::new(b"Hello".to_vec())
+            .encrypt(&jwe_encrypter, JweEnc::A256GCM)
+            .expect("Unable to encrypt jwe");
+        let other = JweDecrypter::generate_a256gcmkw().expect("Unable to generate key");
+        assert!(matches!(
+            jwec.decrypt(&other),
+            Err(JwtError::DecryptionFailed)
+        ));
+
+        // The key must be the size of the alg, and not limited to signing.
+        assert!(matches!(
+            JweDecrypter::from_a128kw_jwk(&oct_jwk(&[0; 32])),
+            Err(JwtError::InvalidKey)
+        ));
+        let mut sig_jwk = oct_jwk(&[0; 16]);
+        if let Jwk::oct { use_, .. } = &mut sig_jwk {
+            *use_ = Some(JwkUse::Sig);
+        }
+        assert!(matches!(
+            JweDecrypter::from_a128kw_jwk(&sig_jwk),
+            Err(JwtError::InvalidKey)
+        ));
+    }
+
+    #[test]
+    fn test_pbes2_iteration_floor() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let password = b"correct horse battery staple";
+        let jwe_decrypter = JweDecrypter::from_pbes2_hs256_a128kw_password(password, 2000)
+            .expect("Invalid password");
+
+        for iterations in [2000, 4000] {
+            let jwe_encrypter =
+                JweEncrypter::from_pbes2_hs256_a128kw_password(password, iterations)
+                    .expect("Invalid password");
+            let jwe_str = Jwe::new(b"Hello".to_vec())
+                .encrypt(&jwe_encrypter, JweEnc::A256GCM)
+                .expect("Unable to encrypt jwe")
+                .to_string();
+
+            let jwec = JweCompact::from_str(&jwe_str).expect("Invalid compact jwe");
+            assert!(jwec.header().p2c() == Some(iterations));
+            assert!(jwec.header().p2s().map(<[u8]>::len) == Some(16));
+
+            let released = jwec.decrypt(&jwe_decrypter).expect("Unable to decrypt jwe");
+            assert!(released.payload() == b"Hello");
+        }
+
+        // Tokens below the floor are rejected, even with the correct password.
+        let jwe_encrypter = JweEncrypter::from_pbes2_hs256_a128kw_password(password, 1000)
+            .expect("Invalid password");
+        let jwec = Jwe::new(b"Hello".to_vec())
+            .encrypt(&jwe_encrypter, JweEnc::A256GCM)
+            .expect("Unable to encrypt jwe");
+        assert!(matches!(
+            jwec.decrypt(&jwe_decrypter),
+            Err(JwtError::DecryptionFailed)
+        ));
+
+        // As are tokens that demand excessive work from the recipient.
+        let jwe_str = jwec.to_string();
+        let (hdr, rest) = jwe_str.split_once('.').expect("Invalid compact jwe");
+        let mut header: serde_json::Value =
+            serde_json::from_slice(&b64(hdr)).expect("Invalid header");
+        header["p2c"] = (PBES2_MAX_ITERATIONS + 1).into();
+        let hdr = base64::encode_config(
+            serde_json::to_vec(&header).expect("Invalid header"),
+            base64::URL_SAFE_NO_PAD,
+        );
+        let jwec = JweCompact::from_str(&format!("{}.{}", hdr, rest)).expect("Invalid compact jwe");
+        assert!(matches!(
+            jwec.decrypt(&jwe_decrypter),
+            Err(JwtError::DecryptionFailed)
+        ));
+
+        // The floor and the iteration count must be within the supported range.
+        for iterations in [0, 999, PBES2_MAX_ITERATIONS + 1] {
+            assert!(matches!(
+                JweDecrypter::from_pbes2_hs256_a128kw_password(password, iterations),
+                Err(JwtError::InvalidKey)
+            ));
+            assert!(matches!(
+                JweEncrypter::from_pbes2_hs256_a128kw_password(password, iterations),
+                Err(JwtError::InvalidKey)
+            ));
+        }
+        assert!(matches!(
+            JweDecrypter::from_pbes2_hs256_a128kw_password(b"", 2000),
+            Err(JwtError::InvalidKey)
+        ));
+    }
+}
@@ -1,11 +1,13 @@
 //! Jws Implementation
-use crate::crypto::{Jwk, JwsCompact, JwsInner, JwsSigner, JwsValidator};
+use crate::crypto::{
+    CriticalExtensions, Jwk, JwsCompact, JwsHeader, JwsInner, JwsJson, JwsSignaturePolicy,
+    JwsSigner, JwsValidator, JwsValidatorSelector, ProtectedHeader,
+};
 use crate::error::JwtError;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::str::FromStr;
-use url::Url;
 
 /// An unverified jws input which is ready to validate
 pub struct JwsUnverified {
@@ -17,6 +19,17 @@ pub struct JwsSigned {
     jwsc: JwsCompact,
 }
 
+/// An unverified jws in JSON serialization, in either the flattened or general form,
+/// which is ready to validate. It may have more than one signature.
+pub struct JwsJsonUnverified {
+    jwsj: JwsJson,
+}
+
+/// A signed jws in JSON serialization which can be converted to a string.
+pub struct JwsJsonSigned {
+    jwsj: JwsJson,
+}
+
 /// A Jwt that is being created or has succeeded in being validated
 #[derive(Serialize, Clone, Deserialize)]
 pub struct Jws<V>
@@ -64,44 +77,96 @@ where
     fn sign_inner(
         &self,
         signer: &JwsSigner,
-        jku: Option<Url>,
+        header: JwsHeader,
         jwk: Option<Jwk>,
+        detached: bool,
     ) -> Result<JwsSigned, JwtError> {
         // We need to convert this payload to a set of bytes.
-        // eprintln!("{:?}", serde_json::to_string(&self));
-        let payload = serde_json::to_vec(&self).map_err(|e| {
-            error!(?e);
-            JwtError::InvalidJwt
-        })?;
+        let payload = self.payload()?;
 
-        let jws = JwsInner::new(payload).set_typ("JWT".to_string());
+        let jws = JwsInner::new(payload).set_header(header);
 
-        jws.sign_inner(signer, jku, jwk)
-            .map(|jwsc| JwsSigned { jwsc })
+        if detached {
+            jws.sign_detached_inner(signer, jwk)
+        } else {
+            jws.sign_inner(signer, jwk)
+        }
+        .map(|jwsc| JwsSigned { jwsc })
     }
 
     /// Use this private signer to created a signed jwt.
     pub fn sign(&self, signer: &JwsSigner) -> Result<JwsSigned, JwtError> {
-        self.sign_inner(signer, None, None)
+        self.sign_inner(signer, JwsHeader::new().set_typ("JWT"), None, false)
+    }
+
+    /// Use this private signer to created a signed jwt with these header parameters. The
+    /// typ is not set unless it is part of the header.
+    pub fn sign_with_header(
+        &self,
+        signer: &JwsSigner,
+        header: &JwsHeader,
+    ) -> Result<JwsSigned, JwtError> {
+        self.sign_inner(signer, header.clone(), None, false)
+    }
+
+    /// Use this private signer to create a signed jws where the payload is not part of the
+    /// compact form, which is written as `header..signature`. The payload must be sent to
+    /// the recipient separately.
+    pub fn sign_detached(&self, signer: &JwsSigner) -> Result<JwsSigned, JwtError> {
+        self.sign_inner(signer, JwsHeader::new(), None, true)
+    }
+
+    /// Use this private signer to create a signed jws with these header parameters where
+    /// the payload is not part of the compact form.
+    pub fn sign_detached_with_header(
+        &self,
+        signer: &JwsSigner,
+        header: &JwsHeader,
+    ) -> Result<JwsSigned, JwtError> {
+        self.sign_inner(signer, header.clone(), None, true)
+    }
+
+    /// Sign this jws with each of these signers, creating a jws in the general JSON
+    /// serialization with one signature per signer. Each signature has its own protected
+    /// header, which should set a kid so that the recipient can select the key.
+    pub fn sign_json(
+        &self,
+        signers: &[(&JwsSigner, &JwsHeader)],
+    ) -> Result<JwsJsonSigned, JwtError> {
+        let payload = self.payload()?;
+
+        JwsJson::sign(&payload, signers).map(|jwsj| JwsJsonSigned { jwsj })
+    }
+
+    /// The payload of this jws as it is signed. This is the payload that must be sent with
+    /// a detached jws.
+    pub fn payload(&self) -> Result<Vec<u8>, JwtError> {
+        serde_json::to_vec(&self).map_err(|e| {
+            error!(?e);
+            JwtError::InvalidJwt(e.into())
+        })
     }
 
     /// Use this to create a signed jwt that includes the public key used in the signing process
     pub fn sign_embed_public_jwk(&self, signer: &JwsSigner) -> Result<JwsSigned, JwtError> {
         let jwk = signer.public_key_as_jwk(None)?;
-        self.sign_inner(signer, None, Some(jwk))
+        self.sign_inner(signer, JwsHeader::new().set_typ("JWT"), Some(jwk), false)
     }
 }
 
 impl JwsUnverified {
     /// Using this JwsValidator, assert the correct signature of the data contained in
-    /// this jwt.
-    pub fn validate<V>(&self, validator: &JwsValidator) -> Result<Jws<V>, JwtError>
+    /// this jwt. A JwkKeySet may also be used, which selects the key by the kid and alg
+    /// of this jwt.
+    pub fn validate<V, S>(&self, validator: &S) -> Result<Jws<V>, JwtError>
     where
         V: Clone + DeserializeOwned,
+        S: JwsValidatorSelector + ?Sized,
     {
-        let released = self.jwsc.validate(validator)?;
+        let validator = validator.select_validator(self.jwsc.get_jwk_kid(), self.jwsc.get_alg())?;
+        let released = self.jwsc.validate(&validator)?;
 
-        serde_json::from_slice(released.payload()).map_err(|_| JwtError::InvalidJwt)
+        serde_json::from_slice(released.payload()).map_err(|e| JwtError::InvalidJwt(e.into()))
     }
 
     /// Using this JwsValidator, assert the correct signature of the data contained in
@@ -124,6 +189,42 @@ impl JwsUnverified {
     pub fn get_jwk_pubkey(&self) -> Option<&Jwk> {
         self.jwsc.get_jwk_pubkey()
     }
+
+    /// Retrieve the protected header of this jws. These values are not trustworthy until
+    /// the jws is validated.
+    pub fn header(&self) -> &ProtectedHeader {
+        self.jwsc.header()
+    }
+
+    /// Parse this jws, accepting the critical header parameters that are understood by
+    /// these extensions.
+    pub fn from_str_with_critical(
+        s: &str,
+        critical: &CriticalExtensions,
+    ) -> Result<Self, JwtError> {
+        JwsCompact::from_str_with_critical(s, critical).map(|jwsc| JwsUnverified { jwsc })
+    }
+
+    /// Parse a jws with a detached payload, in the form `header..signature`, where the
+    /// payload is carried separately.
+    pub fn from_str_detached(s: &str, payload: &[u8]) -> Result<Self, JwtError> {
+        Self::from_str_detached_with_critical(s, payload, &CriticalExtensions::default())
+    }
+
+    /// Parse a jws with a detached payload, accepting the critical header parameters that
+    /// are understood by these extensions.
+    pub fn from_str_detached_with_critical(
+        s: &str,
+        payload: &[u8],
+        critical: &CriticalExtensions,
+    ) -> Result<Self, JwtError> {
+        JwsCompact::from_str_detached(s, payload, critical).map(|jwsc| JwsUnverified { jwsc })
+    }
+
+    /// Convert this jws to the flattened JSON serialization.
+    pub fn to_json(&self) -> Result<JwsJsonUnverified, JwtError> {
+        JwsJson::from_compact(&self.jwsc).map(|jwsj| JwsJsonUnverified { jwsj })
+    }
 }
 
 impl FromStr for JwsUnverified {
@@ -140,6 +241,11 @@ impl JwsSigned {
     pub fn invalidate(self) -> JwsUnverified {
         JwsUnverified { jwsc: self.jwsc }
     }
+
+    /// Convert this jws to the flattened JSON serialization.
+    pub fn to_json(&self) -> Result<JwsJsonSigned, JwtError> {
+        JwsJson::from_compact(&self.jwsc).map(|jwsj| JwsJsonSigned { jwsj })
+    }
 }
 
 impl fmt::Display for JwsSigned {
@@ -148,12 +254,86 @@ impl fmt::Display for JwsSigned {
     }
 }
 
+impl JwsJsonUnverified {
+    /// Using this JwsValidator, assert the correct signatures of the data contained in
+    /// this jws. A JwkKeySet may also be used, which selects the key for each signature by
+    /// its kid and alg. The policy decides if any or all of the signatures must be valid.
+    pub fn validate<V, S>(
+        &self,
+        validator: &S,
+        policy: JwsSignaturePolicy,
+    ) -> Result<Jws<V>, JwtError>
+    where
+        V: Clone + DeserializeOwned,
+        S: JwsValidatorSelector + ?Sized,
+    {
+        let payload = self.jwsj.validate(validator, policy)?;
+
+        serde_json::from_slice(payload).map_err(|e| JwtError::InvalidJwt(e.into()))
+    }
+
+    /// Retrieve the header of each signature of this jws, which includes both the
+    /// protected and unprotected header parameters. These values are not trustworthy until
+    /// the jws is validated, and unprotected values are never covered by the signature.
+    pub fn headers(&self) -> Vec<&ProtectedHeader> {
+        self.jwsj.headers().collect()
+    }
+
+    /// Convert this jws to compact form. This requires that the jws has exactly one
+    /// signature and no unprotected header.
+    pub fn to_compact(&self) -> Result<JwsUnverified, JwtError> {
+        self.jwsj.to_compact().map(|jwsc| JwsUnverified { jwsc })
+    }
+
+    /// Parse this jws, accepting the critical header parameters that are understood by
+    /// these extensions.
+    pub fn from_str_with_critical(
+        s: &str,
+        critical: &CriticalExtensions,
+    ) -> Result<Self, JwtError> {
+        JwsJson::from_str_with_critical(s, critical).map(|jwsj| JwsJsonUnverified { jwsj })
+    }
+}
+
+impl FromStr for JwsJsonUnverified {
+    type Err = JwtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_str_with_critical(s, &CriticalExtensions::default())
+    }
+}
+
+impl JwsJsonSigned {
+    /// Invalidate this signed jws, causing it to require validation before you can use it
+    /// again.
+    pub fn invalidate(self) -> JwsJsonUnverified {
+        JwsJsonUnverified { jwsj: self.jwsj }
+    }
+
+    /// Convert this jws to compact form. This requires that the jws has exactly one
+    /// signature and no unprotected header.
+    pub fn to_compact(&self) -> Result<JwsSigned, JwtError> {
+        self.jwsj.to_compact().map(|jwsc| JwsSigned { jwsc })
+    }
+}
+
+impl fmt::Display for JwsJsonSigned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.jwsj.fmt(f)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Jws;
-    use crate::crypto::{JwsSigner, JwsValidator};
+    use super::{Jws, JwsJsonUnverified, JwsUnverified};
+    use crate::crypto::{
+        CriticalExtensions, JwaAlg, JwkKeySet, JwsHeader, JwsSignaturePolicy, JwsSigner,
+        JwsValidator,
+    };
+    use crate::error::JwtError;
     use serde::{Deserialize, Serialize};
     use std::convert::TryFrom;
+    use std::str::FromStr;
 
     #[derive(Default, Debug, Serialize, Clone, Deserialize, PartialEq)]
     struct CustomExtension {
@@ -204,4 +384,253 @@ mod tests {
 
         assert!(released == jwt);
     }
+
+    #[test]
+    fn test_critical_extensions() {
+        let jwss = JwsSigner::generate_es256().expect("failed to construct signer.");
+        let jws_validator = jwss.get_validator().expect("Unable to create validator");
+
+        let jwt = Jws {
+            inner: CustomExtension {
+                my_exten: "Hello".to_string(),
+            },
+        };
+
+        let header = JwsHeader::new()
+            .set_typ("JWT")
+            .set_critical("x-tenant", serde_json::json!("acme"));
+        let jwt_str = jwt
+            .sign_with_header(&jwss, &header)
+            .expect("failed to sign jwt")
+            .to_string();
+
+        // Critical parameters are rejected unless they are understood.
+        assert!(matches!(
+            JwsUnverified::from_str(&jwt_str),
+            Err(JwtError::CriticalExtension { name }) if name == "x-tenant"
+        ));
+
+        let critical = CriticalExtensions::new().register("x-tenant", |value| {
+            if value == "acme" {
+                Ok(())
+            } else {
+                Err(JwtError::CriticalExtension {
+                    name: "x-tenant".to_string(),
+                })
+            }
+        });
+        let jwtu = JwsUnverified::from_str_with_critical(&jwt_str, &critical)
+            .expect("Unable to parse jws");
+        assert!(jwtu.header().crit() == ["x-tenant".to_string()]);
+        let released = jwtu
+            .validate(&jws_validator)
+            .expect("Unable to validate jwt");
+        assert!(released == jwt);
+
+        // The handler decides if the value is acceptable.
+        let header = JwsHeader::new().set_critical("x-tenant", serde_json::json!("other"));
+        let jwt_str = jwt
+            .sign_with_header(&jwss, &header)
+            .expect("failed to sign jwt")
+            .to_string();
+        assert!(matches!(
+            JwsUnverified::from_str_with_critical(&jwt_str, &critical),
+            Err(JwtError::CriticalExtension { .. })
+        ));
+    }
+
+    #[test]
+    fn test_sign_and_validate_detached() {
+        let jwss = JwsSigner::generate_es256().expect("failed to construct signer.");
+        let jws_validator = jwss.get_validator().expect("Unable to create validator");
+
+        let jwt = Jws {
+            inner: CustomExtension {
+                my_exten: "Hello".to_string(),
+            },
+        };
+
+        let jws_str = jwt
+            .sign_detached(&jwss)
+            .expect("failed to sign jws")
+            .to_string();
+        let (hdr, sig) = jws_str.split_once("..").expect("payload is not detached");
+        assert!(!hdr.contains('.') && !sig.contains('.'));
+
+        let payload = jwt.payload().expect("failed to serialise payload");
+        let released = JwsUnverified::from_str_detached(&jws_str, &payload)
+            .expect("Unable to parse jws")
+            .validate::<CustomExtension, _>(&jws_validator)
+            .expect("Unable to validate jws");
+        assert!(released == jwt);
+
+        // A different payload does not match the signature.
+        let other = Jws {
+            inner: CustomExtension {
+                my_exten: "Goodbye".to_string(),
+            },
+        };
+        let payload = other.payload().expect("failed to serialise payload");
+        assert!(matches!(
+            JwsUnverified::from_str_detached(&jws_str, &payload)
+                .expect("Unable to parse jws")
+                .validate::<CustomExtension, _>(&jws_validator),
+            Err(JwtError::InvalidSignature)
+        ));
+
+        // Without the payload, the signature can not be validated.
+        assert!(matches!(
+            JwsUnverified::from_str(&jws_str)
+                .expect("Unable to parse jws")
+                .validate::<CustomExtension, _>(&jws_validator),
+            Err(JwtError::InvalidSignature)
+        ));
+
+        // An attached payload can not be replaced.
+        let attached = jwt.sign(&jwss).expect("failed to sign jws").to_string();
+        assert!(matches!(
+            JwsUnverified::from_str_detached(&attached, b"{}"),
+            Err(JwtError::InvalidCompactFormat)
+        ));
+
+        // The payload may also be left unencoded.
+        let header = JwsHeader::new().set_unencoded_payload();
+        let jws_str = jwt
+            .sign_detached_with_header(&jwss, &header)
+            .expect("failed to sign jws")
+            .to_string();
+        let payload = jwt.payload().expect("failed to serialise payload");
+        let jwtu =
+            JwsUnverified::from_str_detached(&jws_str, &payload).expect("Unable to parse jws");
+        assert!(!jwtu.header().b64());
+        let released = jwtu
+            .validate::<CustomExtension, _>(&jws_validator)
+            .expect("Unable to validate jws");
+        assert!(released == jwt);
+    }
+
+    #[test]
+    fn test_json_serialization() {
+        let jwss = JwsSigner::generate_es256().expect("failed to construct signer.");
+        let jws_validator = jwss.get_validator().expect("Unable to create validator");
+
+        let jwt = Jws {
+            inner: CustomExtension {
+                my_exten: "Hello".to_string(),
+            },
+        };
+
+        let jwts = jwt.sign(&jwss).expect("failed to sign jwt");
+        let compact = jwts.to_string();
+        let json = jwts.to_json().expect("Unable to convert jws").to_string();
+        assert!(json.starts_with("{\"payload\":"));
+
+        let jwsu = JwsJsonUnverified::from_str(&json).expect("Unable to parse jws");
+        assert!(jwsu.headers().len() == 1);
+        let released = jwsu
+            .validate::<CustomExtension, _>(&jws_validator, JwsSignaturePolicy::All)
+            .expect("Unable to validate jws");
+        assert!(released == jwt);
+
+        // Converting back to compact form reproduces the original token.
+        let jwtu = jwsu.to_compact().expect("Unable to convert jws");
+        assert!(jwtu
+            .to_json()
+            .expect("Unable to convert jws")
+            .to_compact()
+            .is_ok());
+        let released = jwtu
+            .validate(&jws_validator)
+            .expect("Unable to validate jws");
+        assert!(released == jwt);
+        assert!(
+            jwts.to_json()
+                .and_then(|jwsj| jwsj.to_compact())
+                .expect("Unable to convert jws")
+                .to_string()
+                == compact
+        );
+
+        assert!(matches!(
+            JwsJsonUnverified::from_str(&compact),
+            Err(JwtError::InvalidJsonFormat)
+        ));
+    }
+
+    #[test]
+    fn test_sign_json_multiple_signers() {
+        let es256 = JwsSigner::generate_es256().expect("failed to construct signer.");
+        let ps256 = JwsSigner::generate_ps256().expect("failed to construct signer.");
+        let es256_header = JwsHeader::new().set_kid("es256");
+        let ps256_header = JwsHeader::new().set_kid("ps256");
+
+        let jwt = Jws {
+            inner: CustomExtension {
+                my_exten: "Hello".to_string(),
+            },
+        };
+
+        let jws_str = jwt
+            .sign_json(&[(&es256, &es256_header), (&ps256, &ps256_header)])
+            .expect("failed to sign jws")
+            .to_string();
+        assert!(jws_str.contains("\"signatures\":["));
+
+        let jwsu = JwsJsonUnverified::from_str(&jws_str).expect("Unable to parse jws");
+        let headers: Vec<_> = jwsu
+            .headers()
+            .into_iter()
+            .map(|h| (h.alg().clone(), h.kid().map(str::to_string)))
+            .collect();
+        assert!(
+            headers
+                == [
+                    (JwaAlg::ES256, Some("es256".to_string())),
+                    (JwaAlg::PS256, Some("ps256".to_string()))
+                ]
+        );
+
+        let both = JwkKeySet {
+            keys: vec![
+                es256
+                    .public_key_as_jwk(Some("es256"))
+                    .expect("Unable to export key"),
+                ps256
+                    .public_key_as_jwk(Some("ps256"))
+                    .expect("Unable to export key"),
+            ],
+        };
+        let released = jwsu
+            .validate::<CustomExtension, _>(&both, JwsSignaturePolicy::All)
+            .expect("Unable to validate jws");
+        assert!(released == jwt);
+
+        // During a migration, a recipient may only know one of the keys.
+        let es256_validator = es256.get_validator().expect("Unable to create validator");
+        let released = jwsu
+            .validate::<CustomExtension, _>(&es256_validator, JwsSignaturePolicy::Any)
+            .expect("Unable to validate jws");
+        assert!(released == jwt);
+        assert!(matches!(
+            jwsu.validate::<CustomExtension, _>(&es256_validator, JwsSignaturePolicy::All),
+            Err(JwtError::ValidatorAlgMismatch)
+        ));
+
+        assert!(matches!(
+            jwsu.to_compact(),
+            Err(JwtError::CompactFormUnavailable)
+        ));
+
+        // The signatures must agree on how the payload is encoded.
+        let unencoded = JwsHeader::new().set_unencoded_payload();
+        assert!(matches!(
+            jwt.sign_json(&[(&es256, &es256_header), (&ps256, &unencoded)]),
+            Err(JwtError::InvalidHeaderParameter { name }) if name == "b64"
+        ));
+
+        assert!(matches!(
+            jwt.sign_json(&[]),
+            Err(JwtError::InvalidJsonFormat)
+        ));
+    }
 }
@@ -1,14 +1,20 @@
 //! Jwt implementation
 
+use crate::audience::Audience;
 use crate::btreemap_empty;
-use crate::crypto::{Jwk, JwsCompact, JwsInner, JwsSigner, JwsValidator};
+use crate::clock::Clock;
+use crate::crypto::{
+    CriticalExtensions, Jwk, JwsCompact, JwsHeader, JwsInner, JwsSigner, JwsValidatorSelector,
+    ProtectedHeader,
+};
 use crate::error::JwtError;
+use crate::numeric_date::NumericDate;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::fmt;
 use std::str::FromStr;
-use url::Url;
+use std::time::Duration;
 
 /// An unverified jwt input which is ready to validate
 pub struct JwtUnverified {
@@ -34,16 +40,16 @@ where
     pub sub: Option<String>,
     /// client_id of the oauth2 rp
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub aud: Option<String>,
+    pub aud: Option<Audience>,
     /// Expiry in utc epoch seconds
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub exp: Option<i64>,
+    pub exp: Option<NumericDate>,
     /// Not valid before.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub nbf: Option<i64>,
+    pub nbf: Option<NumericDate>,
     /// Issued at time.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub iat: Option<i64>,
+    pub iat: Option<NumericDate>,
     /// -- not used.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub jti: Option<String>,
@@ -119,47 +125,267 @@ where
     fn sign_inner(
         &self,
         signer: &JwsSigner,
-        jku: Option<Url>,
+        header: JwsHeader,
         jwk: Option<Jwk>,
     ) -> Result<JwtSigned, JwtError> {
         // We need to convert this payload to a set of bytes.
         // eprintln!("{:?}", serde_json::to_string(&self));
-        let payload = serde_json::to_vec(&self).map_err(|_| JwtError::InvalidJwt)?;
+        let payload = serde_json::to_vec(&self).map_err(|e| JwtError::InvalidJwt(e.into()))?;
 
-        let jws = JwsInner::new(payload).set_typ("JWT".to_string());
+        let jws = JwsInner::new(payload).set_header(header);
 
-        jws.sign_inner(signer, jku, jwk)
-            .map(|jwsc| JwtSigned { jwsc })
+        jws.sign_inner(signer, jwk).map(|jwsc| JwtSigned { jwsc })
     }
 
     /// Use this private signer to created a signed jwt.
     pub fn sign(&self, signer: &JwsSigner) -> Result<JwtSigned, JwtError> {
-        self.sign_inner(signer, None, None)
+        self.sign_inner(signer, JwsHeader::new().set_typ("JWT"), None)
+    }
+
+    /// set the key id (kid) into the header.
+    pub fn sign_with_kid(&self, signer: &JwsSigner, kid: &str) -> Result<JwtSigned, JwtError> {
+        self.sign_inner(signer, JwsHeader::new().set_typ("JWT").set_kid(kid), None)
+    }
+
+    /// Use this private signer to created a signed jwt with these header parameters, for
+    /// example to set a typ of `at+jwt`. The typ is not set unless it is part of the header.
+    pub fn sign_with_header(
+        &self,
+        signer: &JwsSigner,
+        header: &JwsHeader,
+    ) -> Result<JwtSigned, JwtError> {
+        self.sign_inner(signer, header.clone(), None)
     }
 
     /// Use this to create a signed jwt that includes the public key used in the signing process
     pub fn sign_embed_public_jwk(&self, signer: &JwsSigner) -> Result<JwtSigned, JwtError> {
         let jwk = signer.public_key_as_jwk(None)?;
-        self.sign_inner(signer, None, Some(jwk))
+        self.sign_inner(signer, JwsHeader::new().set_typ("JWT"), Some(jwk))
+    }
+}
+
+/// A policy of the claims that must be asserted when validating a Jwt. By default only the
+/// time claims that are present in the token (exp, nbf and iat) are checked.
+#[derive(Debug, Clone, Default)]
+pub struct JwtValidationPolicy {
+    issuers: Vec<String>,
+    audiences: Vec<String>,
+    required_claims: Vec<String>,
+    leeway: Duration,
+    max_age: Option<Duration>,
+}
+
+impl JwtValidationPolicy {
+    /// Create a new policy that only checks the time claims present in the token.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an issuer that is accepted. If any issuers are set, the token must contain an
+    /// iss claim matching one of them.
+    pub fn add_issuer(mut self, iss: &str) -> Self {
+        self.issuers.push(iss.to_string());
+        self
+    }
+
+    /// Add an audience that is accepted. If any audiences are set, the token must contain an
+    /// aud claim matching one of them.
+    pub fn add_audience(mut self, aud: &str) -> Self {
+        self.audiences.push(aud.to_string());
+        self
+    }
+
+    /// Require that this claim is present in the token. This may be a registered claim such
+    /// as exp, or the name of any custom claim.
+    pub fn require_claim(mut self, claim: &str) -> Self {
+        self.required_claims.push(claim.to_string());
+        self
+    }
+
+    /// Set the allowed clock skew between the issuer and this host when checking the
+    /// exp, nbf and iat claims.
+    pub fn set_leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    /// Set the maximum age of the token since it was issued. This requires the token to
+    /// contain an iat claim.
+    pub fn set_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    fn check_required_claims(
+        &self,
+        claims: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<(), JwtError> {
+        match self.required_claims.iter().find(|claim| {
+            matches!(
+                claims.get(claim.as_str()),
+                None | Some(serde_json::Value::Null)
+            )
+        }) {
+            Some(claim) => {
+                debug!(%claim, "Required claim is not present");
+                Err(JwtError::MissingClaim {
+                    claim: claim.clone(),
+                })
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn check_claims<V>(&self, jwt: &Jwt<V>, curtime: i64) -> Result<(), JwtError>
+    where
+        V: Clone,
+    {
+        if !self.issuers.is_empty() {
+            match &jwt.iss {
+                Some(iss) if self.issuers.contains(iss) => {}
+                _ => {
+                    return Err(JwtError::InvalidIssuer {
+                        expected: self.issuers.clone(),
+                        actual: jwt.iss.clone(),
+                    })
+                }
+            }
+        }
+
+        if !self.audiences.is_empty() {
+            match &jwt.aud {
+                Some(aud) if self.audiences.iter().any(|a| aud.contains(a)) => {}
+                _ => {
+                    return Err(JwtError::InvalidAudience {
+                        expected: self.audiences.clone(),
+                        actual: jwt.aud.clone(),
+                    })
+                }
+            }
+        }
+
+        let leeway = i64::try_from(self.leeway.as_secs()).unwrap_or(i64::MAX);
+        check_time_claims(jwt.exp, jwt.nbf, jwt.iat, curtime, leeway)?;
+
+        if let Some(max_age) = self.max_age {
+            let iat = jwt.iat.ok_or_else(|| JwtError::MissingClaim {
+                claim: "iat".to_string(),
+            })?;
+            let max_age_secs = i64::try_from(max_age.as_secs()).unwrap_or(i64::MAX);
+            if curtime.saturating_sub(iat.as_secs()) > max_age_secs.saturating_add(leeway) {
+                return Err(JwtError::TokenTooOld {
+                    iat,
+                    max_age,
+                    now: NumericDate::from_secs(curtime),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Check the exp, nbf and iat claims, if present, against the current time in seconds
+/// since the epoch, allowing leeway seconds of clock skew. A fractional nbf or iat is
+/// rounded up, so that a token is never accepted before it is valid.
+pub(crate) fn check_time_claims(
+    exp: Option<NumericDate>,
+    nbf: Option<NumericDate>,
+    iat: Option<NumericDate>,
+    curtime: i64,
+    leeway: i64,
+) -> Result<(), JwtError> {
+    let now = NumericDate::from_secs(curtime);
+
+    if let Some(exp) = exp {
+        if exp.as_secs().saturating_add(leeway) < curtime {
+            return Err(JwtError::TokenExpired { exp, now });
+        }
+    }
+
+    if let Some(nbf) = nbf {
+        if curtime.saturating_add(leeway) < nbf.as_secs_ceil() {
+            return Err(JwtError::NotYetValid { nbf, now });
+        }
+    }
+
+    if let Some(iat) = iat {
+        if curtime.saturating_add(leeway) < iat.as_secs_ceil() {
+            return Err(JwtError::IssuedInFuture { iat, now });
+        }
     }
+
+    Ok(())
 }
 
 impl JwtUnverified {
     /// Using this JwsValidator, assert the correct signature of the data contained in
-    /// this jwt.
-    pub fn validate<V>(&self, validator: &JwsValidator) -> Result<Jwt<V>, JwtError>
+    /// this jwt. A JwkKeySet may also be used, which selects the key by the kid and alg
+    /// of this jwt.
+    pub fn validate<V, S>(&self, validator: &S) -> Result<Jwt<V>, JwtError>
     where
         V: Clone + DeserializeOwned,
+        S: JwsValidatorSelector + ?Sized,
     {
-        let released = self.jwsc.validate(validator)?;
+        let validator = validator.select_validator(self.jwsc.get_jwk_kid(), self.jwsc.get_alg())?;
+        let released = self.jwsc.validate(&validator)?;
 
-        serde_json::from_slice(released.payload()).map_err(|_| JwtError::InvalidJwt)
+        serde_json::from_slice(released.payload()).map_err(|e| JwtError::InvalidJwt(e.into()))
+    }
+
+    /// Using this JwsValidator, assert the correct signature of the data contained in
+    /// this jwt, and then assert the claims of the jwt are valid for this policy. The
+    /// current time is read from this clock, which may be a fixed number of seconds since
+    /// the epoch.
+    pub fn validate_with_policy<V, S, C>(
+        &self,
+        validator: &S,
+        policy: &JwtValidationPolicy,
+        clock: C,
+    ) -> Result<Jwt<V>, JwtError>
+    where
+        V: Clone + DeserializeOwned,
+        S: JwsValidatorSelector + ?Sized,
+        C: Clock,
+    {
+        let validator = validator.select_validator(self.jwsc.get_jwk_kid(), self.jwsc.get_alg())?;
+        let released = self.jwsc.validate(&validator)?;
+
+        // Required claims are checked on the raw claims, since they may be consumed by
+        // the extensions of the jwt.
+        let claims: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_slice(released.payload())
+                .map_err(|e| JwtError::InvalidJwt(e.into()))?;
+
+        policy.check_required_claims(&claims)?;
+
+        let jwt: Jwt<V> = serde_json::from_value(serde_json::Value::Object(claims))
+            .map_err(|e| JwtError::InvalidJwt(e.into()))?;
+
+        policy.check_claims(&jwt, clock.now().as_secs())?;
+
+        Ok(jwt)
     }
 
     /// Get the embedded public key used to sign this jwt, if present.
     pub fn get_jwk_pubkey(&self) -> Option<&Jwk> {
         self.jwsc.get_jwk_pubkey()
     }
+
+    /// Retrieve the protected header of this jwt. These values are not trustworthy until
+    /// the jwt is validated.
+    pub fn header(&self) -> &ProtectedHeader {
+        self.jwsc.header()
+    }
+
+    /// Parse this jwt, accepting the critical header parameters that are understood by
+    /// these extensions.
+    pub fn from_str_with_critical(
+        s: &str,
+        critical: &CriticalExtensions,
+    ) -> Result<Self, JwtError> {
+        JwsCompact::from_str_with_critical(s, critical).map(|jwsc| JwtUnverified { jwsc })
+    }
 }
 
 impl FromStr for JwtUnverified {
@@ -186,11 +412,15 @@ impl fmt::Display for JwtSigned {
 
 #[cfg(test)]
 mod tests {
-    use super::{Jwt, JwtUnverified};
-    use crate::crypto::{JwsSigner, JwsValidator};
+    use super::{Jwt, JwtUnverified, JwtValidationPolicy};
+    use crate::crypto::{Jwk, JwkKeySet, JwsHeader, JwsInner, JwsSigner, JwsValidator};
+    use crate::error::JwtError;
+    use crate::numeric_date::NumericDate;
     use serde::{Deserialize, Serialize};
     use std::convert::TryFrom;
     use std::str::FromStr;
+    use std::time::Duration;
+    use url::Url;
 
     #[derive(Default, Debug, Serialize, Clone, Deserialize, PartialEq)]
     struct CustomExtension {
@@ -237,6 +467,342 @@ mod tests {
         assert!(released == jwt);
     }
 
+    #[test]
+    fn test_validate_with_jwk_key_set() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let jwt = Jwt::<()> {
+            iss: Some("test".to_string()),
+            ..Default::default()
+        };
+
+        let jwss_a = JwsSigner::generate_es256().expect("failed to construct signer.");
+        let jwss_b = JwsSigner::generate_es384().expect("failed to construct signer.");
+        let jwss_c = JwsSigner::generate_es256().expect("failed to construct signer.");
+
+        // Remove the alg from c to show it is inferred from the key type.
+        let jwk_c = match jwss_c.public_key_as_jwk(Some("c")).unwrap() {
+            Jwk::EC {
+                crv,
+                x,
+                y,
+                use_,
+                kid,
+                alg: _,
+            } => Jwk::EC {
+                crv,
+                x,
+                y,
+                use_,
+                kid,
+                alg: None,
+            },
+            _ => unreachable!(),
+        };
+
+        let jwks = JwkKeySet {
+            keys: vec![
+                jwss_a.public_key_as_jwk(Some("a")).unwrap(),
+                jwss_b.public_key_as_jwk(Some("b")).unwrap(),
+                jwk_c,
+            ],
+        };
+
+        // Selected by kid.
+        for (jwss, kid) in [(&jwss_a, "a"), (&jwss_b, "b"), (&jwss_c, "c")] {
+            let jwts = jwt.sign_with_kid(jwss, kid).expect("failed to sign jwt");
+            let jwtu = JwtUnverified::from_str(&jwts.to_string()).unwrap();
+            let released = jwtu.validate(&jwks).expect("Unable to validate jwt");
+            assert!(released == jwt);
+        }
+
+        // The kid is for a different key.
+        let jwts = jwt.sign_with_kid(&jwss_a, "c").expect("failed to sign jwt");
+        assert!(matches!(
+            jwts.invalidate().validate::<(), _>(&jwks),
+            Err(JwtError::InvalidSignature)
+        ));
+
+        // The kid does not exist, or exists with the wrong alg.
+        let jwts = jwt.sign_with_kid(&jwss_a, "d").expect("failed to sign jwt");
+        assert!(matches!(
+            jwts.invalidate().validate::<(), _>(&jwks),
+            Err(JwtError::JwkKeyNotFound)
+        ));
+        let jwts = jwt.sign_with_kid(&jwss_b, "a").expect("failed to sign jwt");
+        assert!(matches!(
+            jwts.invalidate().validate::<(), _>(&jwks),
+            Err(JwtError::JwkKeyNotFound)
+        ));
+
+        // No kid, and only one key can validate ES384.
+        let jwts = jwt.sign(&jwss_b).expect("failed to sign jwt");
+        let released = jwts
+            .invalidate()
+            .validate(&jwks)
+            .expect("Unable to validate jwt");
+        assert!(released == jwt);
+
+        // No kid, but there are two ES256 keys.
+        let jwts = jwt.sign(&jwss_a).expect("failed to sign jwt");
+        assert!(matches!(
+            jwts.invalidate().validate::<(), _>(&jwks),
+            Err(JwtError::JwkKeyAmbiguous)
+        ));
+    }
+
+    #[test]
+    fn test_validate_with_policy() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let jwss = JwsSigner::generate_hs256().expect("failed to construct signer.");
+        let jws_validator = jwss.get_validator().expect("Unable to create validator");
+
+        let jwt = Jwt {
+            iss: Some("https://idm.example.com".to_string()),
+            aud: Some("client".into()),
+            exp: Some(NumericDate::from_secs(1000)),
+            nbf: Some(NumericDate::from_secs(100)),
+            iat: Some(NumericDate::from_secs(100)),
+            extensions: CustomExtension {
+                my_exten: "Hello".to_string(),
+            },
+            ..Default::default()
+        };
+
+        let jwtu = jwt.sign(&jwss).expect("failed to sign jwt").invalidate();
+
+        let check = |policy: &JwtValidationPolicy, curtime: i64| {
+            jwtu.validate_with_policy::<CustomExtension, _, _>(&jws_validator, policy, curtime)
+        };
+
+        let policy = JwtValidationPolicy::new()
+            .add_issuer("https://other.example.com")
+            .add_issuer("https://idm.example.com")
+            .add_audience("client")
+            .require_claim("exp")
+            .require_claim("my_exten");
+
+        let released = check(&policy, 500).expect("Unable to validate jwt");
+        assert!(released == jwt);
+
+        // Issuer and audience.
+        let policy = JwtValidationPolicy::new().add_issuer("https://other.example.com");
+        assert!(
+            check(&policy, 500)
+                == Err(JwtError::InvalidIssuer {
+                    expected: vec!["https://other.example.com".to_string()],
+                    actual: Some("https://idm.example.com".to_string()),
+                })
+        );
+
+        let policy = JwtValidationPolicy::new().add_audience("other");
+        assert!(matches!(
+            check(&policy, 500),
+            Err(JwtError::InvalidAudience { .. })
+        ));
+
+        // Any of the audiences of a multi-audience token may match.
+        let multi = Jwt::<()> {
+            aud: Some(vec!["client".to_string(), "api".to_string()].into()),
+            ..Default::default()
+        };
+        let multiu = multi.sign(&jwss).expect("failed to sign jwt").invalidate();
+        for aud in ["client", "api"] {
+            let policy = JwtValidationPolicy::new()
+                .add_audience("other")
+                .add_audience(aud);
+            assert!(multiu
+                .validate_with_policy::<(), _, _>(&jws_validator, &policy, 500)
+                .is_ok());
+        }
+        let policy = JwtValidationPolicy::new().add_audience("other");
+        assert!(matches!(
+            multiu.validate_with_policy::<(), _, _>(&jws_validator, &policy, 500),
+            Err(JwtError::InvalidAudience { .. })
+        ));
+
+        // Required claims, including those that are not registered claims.
+        let policy = JwtValidationPolicy::new().require_claim("sub");
+        assert!(
+            check(&policy, 500)
+                == Err(JwtError::MissingClaim {
+                    claim: "sub".to_string()
+                })
+        );
+
+        let policy = JwtValidationPolicy::new().require_claim("other_exten");
+        assert!(matches!(
+            check(&policy, 500),
+            Err(JwtError::MissingClaim { .. })
+        ));
+
+        // A required claim that is null is missing, and must not skip the expiry check.
+        for claim in ["exp", "sub"] {
+            let jwt = Jwt::<()> {
+                claims: [(claim.to_string(), serde_json::Value::Null)]
+                    .into_iter()
+                    .collect(),
+                ..Default::default()
+            };
+            let jwtu = jwt.sign(&jwss).expect("failed to sign jwt").invalidate();
+            let policy = JwtValidationPolicy::new().require_claim(claim);
+            assert!(
+                jwtu.validate_with_policy::<(), _, _>(&jws_validator, &policy, 500)
+                    == Err(JwtError::MissingClaim {
+                        claim: claim.to_string()
+                    })
+            );
+        }
+
+        // Time claims, with and without leeway.
+        let policy = JwtValidationPolicy::new();
+        assert!(check(&policy, 1000).is_ok());
+        assert!(
+            check(&policy, 1001)
+                == Err(JwtError::TokenExpired {
+                    exp: NumericDate::from_secs(1000),
+                    now: NumericDate::from_secs(1001),
+                })
+        );
+        assert!(matches!(
+            check(&policy, 99),
+            Err(JwtError::NotYetValid { .. })
+        ));
+
+        let policy = JwtValidationPolicy::new().set_leeway(Duration::from_secs(60));
+        assert!(check(&policy, 1060).is_ok());
+        assert!(matches!(
+            check(&policy, 1061),
+            Err(JwtError::TokenExpired { .. })
+        ));
+        assert!(check(&policy, 40).is_ok());
+        assert!(matches!(
+            check(&policy, 39),
+            Err(JwtError::NotYetValid { .. })
+        ));
+
+        // Max age since issue.
+        let policy = JwtValidationPolicy::new().set_max_age(Duration::from_secs(300));
+        assert!(check(&policy, 400).is_ok());
+        assert!(matches!(
+            check(&policy, 401),
+            Err(JwtError::TokenTooOld { .. })
+        ));
+
+        // A token issued in the future, with no nbf.
+        let jwt = Jwt::<()> {
+            iat: Some(NumericDate::from_secs(100)),
+            ..Default::default()
+        };
+        let jwtu = jwt.sign(&jwss).expect("failed to sign jwt").invalidate();
+        let policy = JwtValidationPolicy::new();
+        assert!(matches!(
+            jwtu.validate_with_policy::<(), _, _>(&jws_validator, &policy, 99),
+            Err(JwtError::IssuedInFuture { .. })
+        ));
+
+        // max_age requires iat.
+        let jwt = Jwt::<()>::default();
+        let jwtu = jwt.sign(&jwss).expect("failed to sign jwt").invalidate();
+        let policy = JwtValidationPolicy::new().set_max_age(Duration::from_secs(300));
+        assert!(matches!(
+            jwtu.validate_with_policy::<(), _, _>(&jws_validator, &policy, 99),
+            Err(JwtError::MissingClaim { .. })
+        ));
+    }
+
+    #[test]
+    fn test_validate_with_policy_time_overflow() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let jwss = JwsSigner::generate_hs256().expect("failed to construct signer.");
+        let jws_validator = jwss.get_validator().expect("Unable to create validator");
+
+        // Validly signed time claims at the extremes must not overflow.
+        let jwt = Jwt::<()> {
+            exp: Some(NumericDate::from_secs(i64::MAX)),
+            nbf: Some(NumericDate::from_secs(i64::MIN)),
+            iat: Some(NumericDate::from_secs(i64::MIN)),
+            ..Default::default()
+        };
+        let jwtu = jwt.sign(&jwss).expect("failed to sign jwt").invalidate();
+
+        let policy = JwtValidationPolicy::new()
+            .set_leeway(Duration::from_secs(u64::MAX))
+            .set_max_age(Duration::from_secs(u64::MAX));
+        for curtime in [i64::MIN, 0, i64::MAX] {
+            assert!(jwtu
+                .validate_with_policy::<(), _, _>(&jws_validator, &policy, curtime)
+                .is_ok());
+        }
+
+        let policy = JwtValidationPolicy::new().set_max_age(Duration::from_secs(300));
+        assert!(matches!(
+            jwtu.validate_with_policy::<(), _, _>(&jws_validator, &policy, i64::MAX),
+            Err(JwtError::TokenTooOld { .. })
+        ));
+
+        let jwt = Jwt::<()> {
+            exp: Some(NumericDate::from_secs(i64::MIN)),
+            iat: Some(NumericDate::from_secs(i64::MAX)),
+            ..Default::default()
+        };
+        let jwtu = jwt.sign(&jwss).expect("failed to sign jwt").invalidate();
+        let policy = JwtValidationPolicy::new().set_leeway(Duration::from_secs(u64::MAX));
+        assert!(matches!(
+            jwtu.validate_with_policy::<(), _, _>(&jws_validator, &policy, i64::MIN),
+            Err(JwtError::IssuedInFuture { .. })
+        ));
+        let policy = JwtValidationPolicy::new();
+        assert!(matches!(
+            jwtu.validate_with_policy::<(), _, _>(&jws_validator, &policy, i64::MAX),
+            Err(JwtError::TokenExpired { .. })
+        ));
+    }
+
+    #[test]
+    fn test_validate_with_policy_fractional_time() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let jwss = JwsSigner::generate_hs256().expect("failed to construct signer.");
+        let jws_validator = jwss.get_validator().expect("Unable to create validator");
+
+        // We never encode fractional seconds, so sign this payload directly.
+        let jwsc = JwsInner::new(br#"{"exp":2000.75,"nbf":1000.5,"iat":999.25}"#.to_vec())
+            .sign(&jwss)
+            .expect("failed to sign jws");
+        let jwtu = JwtUnverified::from_str(&jwsc.to_string()).expect("Invalid jwt");
+        let policy = JwtValidationPolicy::new();
+
+        // The nbf is rounded up, so the token is not accepted in the second before it.
+        assert!(matches!(
+            jwtu.validate_with_policy::<(), _, _>(&jws_validator, &policy, 1000),
+            Err(JwtError::NotYetValid { .. })
+        ));
+        assert!(jwtu
+            .validate_with_policy::<(), _, _>(&jws_validator, &policy, 1001)
+            .is_ok());
+
+        // The exp is rounded down.
+        assert!(jwtu
+            .validate_with_policy::<(), _, _>(&jws_validator, &policy, 2000)
+            .is_ok());
+        assert!(matches!(
+            jwtu.validate_with_policy::<(), _, _>(&jws_validator, &policy, 2001),
+            Err(JwtError::TokenExpired { .. })
+        ));
+
+        // The iat is rounded up in the same way as the nbf.
+        let jwsc = JwsInner::new(br#"{"iat":999.25}"#.to_vec())
+            .sign(&jwss)
+            .expect("failed to sign jws");
+        let jwtu = JwtUnverified::from_str(&jwsc.to_string()).expect("Invalid jwt");
+        assert!(matches!(
+            jwtu.validate_with_policy::<(), _, _>(&jws_validator, &policy, 999),
+            Err(JwtError::IssuedInFuture { .. })
+        ));
+        assert!(jwtu
+            .validate_with_policy::<(), _, _>(&jws_validator, &policy, 1000)
+            .is_ok());
+    }
+
     #[test]
     fn test_sign_and_validate_str() {
         let jwt = Jwt::<()> {
@@ -260,4 +826,54 @@ mod tests {
 
         assert!(released == jwt);
     }
+
+    #[test]
+    fn test_sign_with_header() {
+        let _ = tracing_subscriber::fmt().try_init();
+        let jwss = JwsSigner::generate_es256().expect("failed to construct signer.");
+        let jws_validator = jwss.get_validator().expect("Unable to create validator");
+
+        let jwt = Jwt {
+            sub: Some("a unique id".to_string()),
+            extensions: CustomExtension {
+                my_exten: "Hello".to_string(),
+            },
+            ..Default::default()
+        };
+
+        let header = JwsHeader::new()
+            .set_typ("at+jwt")
+            .set_kid("current")
+            .set_cty("json")
+            .set_jku(Url::parse("https://idm.example.com/jwks").unwrap())
+            .set_x5t(&[1, 2, 3, 4])
+            .set_extra("x-tenant", serde_json::json!("acme"));
+
+        let jwts = jwt
+            .sign_with_header(&jwss, &header)
+            .expect("failed to sign jwt");
+
+        let jwtu = JwtUnverified::from_str(&jwts.to_string()).expect("Unable to parse jws/jwt");
+        let phdr = jwtu.header();
+        assert!(phdr.typ() == Some("at+jwt"));
+        assert!(phdr.kid() == Some("current"));
+        assert!(phdr.cty() == Some("json"));
+        assert!(phdr.jku().map(|u| u.as_str()) == Some("https://idm.example.com/jwks"));
+        assert!(phdr.x5t().map(|t| t.0.as_slice()) == Some([1, 2, 3, 4].as_slice()));
+        assert!(phdr.extra().get("x-tenant") == Some(&serde_json::json!("acme")));
+
+        let released = jwtu
+            .validate::<CustomExtension, _>(&jws_validator)
+            .expect("Unable to validate jwt");
+        assert!(released == jwt);
+
+        // Registered parameters can only be set by their own setter.
+        let header = JwsHeader::new().set_extra("alg", serde_json::json!("none"));
+        assert!(
+            jwt.sign_with_header(&jwss, &header).map(|_| ())
+                == Err(JwtError::InvalidHeaderParameter {
+                    name: "alg".to_string()
+                })
+        );
+    }
 }
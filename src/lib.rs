@@ -13,20 +13,22 @@
 //! ```
 //! use std::str::FromStr;
 //! use std::convert::TryFrom;
-//! use std::time::SystemTime;
 //! use url::Url;
-//! use compact_jwt::{JwsValidator, JwsSigner, OidcToken, OidcSubject, OidcUnverified};
+//! use compact_jwt::{Clock, JwsValidator, JwsSigner, OidcToken, OidcSubject, OidcUnverified, SystemClock};
+//!
+//! let curtime = SystemClock.now().as_secs();
 //!
 //! let oidc = OidcToken {
 //!         iss: Url::parse("https://oidc.example.com").unwrap(),
 //!         sub: OidcSubject::S("UniqueId".to_string()),
-//! #       aud: "test".to_string(),
-//! #       exp: 0,
-//! #       nbf: Some(0),
-//! #       iat: 0,
-//! #       auth_time: Some(0),
+//! #       aud: "test".into(),
+//!         exp: (curtime + 300).into(),
+//! #       nbf: Some(curtime.into()),
+//! #       iat: curtime.into(),
+//! #       auth_time: Some(0.into()),
 //! #       nonce: None,
 //! #       at_hash: None,
+//! #       c_hash: None,
 //! #       acr: None,
 //! #       amr: None,
 //! #       azp: None,
@@ -55,13 +57,8 @@
 //! let oidc_unverified = OidcUnverified::from_str(&token_str)
 //!     .unwrap();
 //!
-//! let curtime = SystemTime::now()
-//!     .duration_since(SystemTime::UNIX_EPOCH)
-//!     .expect("Failed to retrieve current time")
-//!     .as_secs() as i64;
-//!
 //! let oidc_validated = oidc_unverified
-//!     .validate(&jws_validator, curtime)
+//!     .validate(&jws_validator, SystemClock)
 //!     .unwrap();
 //!
 //! // Prove we got back the same content.
@@ -71,18 +68,34 @@
 #[macro_use]
 extern crate tracing;
 
+pub mod audience;
 pub mod base64_data;
+pub mod clock;
 pub mod crypto;
 pub mod error;
+pub mod jwe;
 pub mod jws;
 pub mod jwt;
+pub mod numeric_date;
 pub mod oidc;
 
-pub use crate::crypto::{JwaAlg, Jwk, JwkKeySet, JwkUse, JwsSigner, JwsValidator};
+pub use crate::audience::Audience;
+pub use crate::clock::{Clock, FixedClock, OffsetClock, SystemClock};
+pub use crate::crypto::{
+    CriticalExtensions, CriticalHandler, JwaAlg, Jwk, JwkAlg, JwkKeySet, JwkUse, JwsHeader,
+    JwsSignaturePolicy, JwsSigner, JwsValidator, JwsValidatorSelector, ProtectedHeader,
+};
 pub use crate::error::JwtError;
-pub use crate::jws::{Jws, JwsSigned, JwsUnverified};
-pub use crate::jwt::{Jwt, JwtSigned, JwtUnverified};
-pub use crate::oidc::{OidcClaims, OidcSigned, OidcSubject, OidcToken, OidcUnverified};
+pub use crate::jwe::{
+    Jwe, JweAlg, JweCompact, JweDecrypter, JweEnc, JweEncrypter, JweHeader, JweProtectedHeader,
+};
+pub use crate::jws::{Jws, JwsJsonSigned, JwsJsonUnverified, JwsSigned, JwsUnverified};
+pub use crate::jwt::{Jwt, JwtSigned, JwtUnverified, JwtValidationPolicy};
+pub use crate::numeric_date::NumericDate;
+pub use crate::oidc::{
+    OidcClaims, OidcIdTokenVerifier, OidcSigned, OidcSubject, OidcToken, OidcUnverified,
+    OidcValidationOptions,
+};
 
 pub(crate) fn btreemap_empty(
     m: &std::collections::BTreeMap<String, serde_json::value::Value>,
@@ -0,0 +1,207 @@
+//! A NumericDate as used by the time claims of a token. This is the number of seconds
+//! since the unix epoch, which may be decoded from an integer or a fractional value, and
+//! is always encoded as an integer.
+
+use serde::de::{Error, Unexpected, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Seconds since the unix epoch, ignoring leap seconds.
+/// `https://datatracker.ietf.org/doc/html/rfc7519#section-2`
+pub struct NumericDate {
+    secs: i64,
+    subsec_nanos: u32,
+}
+
+impl NumericDate {
+    /// Create a NumericDate from seconds since the unix epoch.
+    pub fn from_secs(secs: i64) -> Self {
+        NumericDate {
+            secs,
+            subsec_nanos: 0,
+        }
+    }
+
+    /// The seconds since the unix epoch of this NumericDate. Any fractional seconds are
+    /// truncated towards the past.
+    pub fn as_secs(&self) -> i64 {
+        self.secs
+    }
+
+    /// The fractional part of this NumericDate in nanoseconds, if it was decoded from a
+    /// fractional value.
+    pub fn subsec_nanos(&self) -> u32 {
+        self.subsec_nanos
+    }
+
+    /// The seconds since the unix epoch of this NumericDate, with any fractional seconds
+    /// rounded towards the future.
+    pub(crate) fn as_secs_ceil(&self) -> i64 {
+        if self.subsec_nanos > 0 {
+            self.secs.saturating_add(1)
+        } else {
+            self.secs
+        }
+    }
+
+    /// The current system time as a NumericDate.
+    pub fn now() -> Self {
+        NumericDate::from(SystemTime::now())
+    }
+}
+
+impl fmt::Display for NumericDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.secs)
+    }
+}
+
+impl From<i64> for NumericDate {
+    fn from(secs: i64) -> Self {
+        NumericDate::from_secs(secs)
+    }
+}
+
+impl From<NumericDate> for i64 {
+    fn from(date: NumericDate) -> i64 {
+        date.secs
+    }
+}
+
+impl From<SystemTime> for NumericDate {
+    fn from(time: SystemTime) -> Self {
+        // Any fractional seconds are truncated towards the past.
+        let secs = match time.duration_since(UNIX_EPOCH) {
+            Ok(d) => i64::try_from(d.as_secs()).unwrap_or(i64::MAX),
+            Err(e) => {
+                let d = e.duration();
+                let secs = i64::try_from(d.as_secs()).unwrap_or(i64::MAX);
+                if d.subsec_nanos() > 0 {
+                    secs.saturating_neg().saturating_sub(1)
+                } else {
+                    secs.saturating_neg()
+                }
+            }
+        };
+        NumericDate::from_secs(secs)
+    }
+}
+
+impl From<NumericDate> for SystemTime {
+    fn from(date: NumericDate) -> SystemTime {
+        let d = Duration::from_secs(date.secs.unsigned_abs());
+        if date.secs >= 0 {
+            UNIX_EPOCH + d
+        } else {
+            UNIX_EPOCH - d
+        }
+    }
+}
+
+struct NumericDateVisitor;
+
+impl<'de> Visitor<'de> for NumericDateVisitor {
+    type Value = NumericDate;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a number of seconds since the unix epoch")
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+    where
+        E: Error,
+    {
+        Ok(NumericDate::from_secs(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+    where
+        E: Error,
+    {
+        i64::try_from(v)
+            .map(NumericDate::from_secs)
+            .map_err(|_| Error::invalid_value(Unexpected::Unsigned(v), &self))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E>
+    where
+        E: Error,
+    {
+        // The seconds are truncated towards the past, and the fraction is kept so that
+        // nbf and iat can be rounded towards the future when they are checked.
+        let secs = v.floor();
+        if secs.is_finite() && secs >= i64::MIN as f64 && secs < i64::MAX as f64 {
+            let subsec_nanos = (((v - secs) * 1e9) as u32).min(999_999_999);
+            Ok(NumericDate {
+                secs: secs as i64,
+                subsec_nanos,
+            })
+        } else {
+            Err(Error::invalid_value(Unexpected::Float(v), &self))
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for NumericDate {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(NumericDateVisitor)
+    }
+}
+
+impl Serialize for NumericDate {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_i64(self.secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::numeric_date::NumericDate;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn test_numeric_date_forms() {
+        let date: NumericDate = serde_json::from_str("1650000000").expect("Invalid date");
+        assert!(date.as_secs() == 1650000000);
+        assert!(serde_json::to_string(&date).unwrap() == "1650000000");
+
+        let date: NumericDate = serde_json::from_str("1650000000.5").expect("Invalid date");
+        assert!(date.as_secs() == 1650000000);
+        assert!(date.subsec_nanos() == 500_000_000);
+        assert!(date.as_secs_ceil() == 1650000001);
+        assert!(serde_json::to_string(&date).unwrap() == "1650000000");
+
+        let date: NumericDate = serde_json::from_str("-1.5").expect("Invalid date");
+        assert!(date.as_secs() == -2);
+        assert!(date.as_secs_ceil() == -1);
+
+        let date: NumericDate = serde_json::from_str("1650000000.0").expect("Invalid date");
+        assert!(date == NumericDate::from_secs(1650000000));
+        assert!(date.as_secs_ceil() == 1650000000);
+
+        assert!(serde_json::from_str::<NumericDate>("1e300").is_err());
+        assert!(serde_json::from_str::<NumericDate>("18446744073709551615").is_err());
+        assert!(serde_json::from_str::<NumericDate>("\"1650000000\"").is_err());
+    }
+
+    #[test]
+    fn test_numeric_date_system_time() {
+        let time = UNIX_EPOCH + Duration::from_millis(1650000000123);
+        let date = NumericDate::from(time);
+        assert!(date.as_secs() == 1650000000);
+        assert!(SystemTime::from(date) == UNIX_EPOCH + Duration::from_secs(1650000000));
+
+        let time = UNIX_EPOCH - Duration::from_millis(1500);
+        let date = NumericDate::from(time);
+        assert!(date.as_secs() == -2);
+        assert!(SystemTime::from(date) == UNIX_EPOCH - Duration::from_secs(2));
+    }
+}
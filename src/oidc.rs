@@ -1,12 +1,21 @@
 //! Oidc token implementation
 
-use crate::crypto::{JwsCompact, JwsInner, JwsSigner, JwsValidator};
+use crate::audience::Audience;
+use crate::clock::Clock;
+use crate::crypto::{
+    CriticalExtensions, JwsCompact, JwsHeader, JwsInner, JwsSigner, JwsValidator,
+    JwsValidatorSelector, ProtectedHeader,
+};
 use crate::error::JwtError;
+use crate::jwt::check_time_claims;
+use crate::numeric_date::NumericDate;
 use crate::{btreemap_empty, vec_empty};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::fmt;
 use std::str::FromStr;
+use std::time::Duration;
 use url::Url;
 use uuid::Uuid;
 
@@ -74,30 +83,35 @@ pub struct OidcToken {
     pub iss: Url,
     /// Unique id of the subject
     pub sub: OidcSubject,
-    /// client_id of the oauth2 rp
-    pub aud: String,
+    /// client_id of the oauth2 rp, and any other audiences of the token
+    pub aud: Audience,
     /// Expiry in utc epoch seconds
-    pub exp: i64,
+    pub exp: NumericDate,
     /// Not valid before.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub nbf: Option<i64>,
+    pub nbf: Option<NumericDate>,
     /// Issued at time.
-    pub iat: i64,
+    pub iat: NumericDate,
     /// Time when the user originally authenticated.
-    pub auth_time: Option<i64>,
+    pub auth_time: Option<NumericDate>,
     /// Comes from authn req
     #[serde(skip_serializing_if = "Option::is_none")]
     pub nonce: Option<String>,
-    /// -- not used.
+    /// Access token hash.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub at_hash: Option<String>,
-    /// -- not used.
+    /// Authorization code hash.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub c_hash: Option<String>,
+    /// Authentication context class reference. Checked by the [OidcIdTokenVerifier] when
+    /// acr values were requested.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub acr: Option<String>,
     /// List of auth methods
     #[serde(skip_serializing_if = "Option::is_none")]
     pub amr: Option<Vec<String>>,
-    /// Do not use.
+    /// Authorized party - the client id the token was issued to. Checked by the
+    /// [OidcIdTokenVerifier] when present, and required when there are multiple audiences.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub azp: Option<String>,
     /// -- not used.
@@ -111,36 +125,60 @@ pub struct OidcToken {
     pub claims: BTreeMap<String, serde_json::value::Value>,
 }
 
+/// The issuer of a token as it appears in the payload.
+#[derive(Deserialize)]
+struct OidcIssuer {
+    iss: String,
+}
+
 impl OidcToken {
-    fn sign_inner(&self, signer: &JwsSigner, kid: Option<&str>) -> Result<OidcSigned, JwtError> {
+    fn sign_inner(&self, signer: &JwsSigner, header: JwsHeader) -> Result<OidcSigned, JwtError> {
         // We need to convert this payload to a set of bytes.
         trace!(
             "✅ {}",
-            serde_json::to_string(&self).map_err(|_| JwtError::InvalidJwt)?
+            serde_json::to_string(&self).map_err(|e| JwtError::InvalidJwt(e.into()))?
         );
-        let payload = serde_json::to_vec(&self).map_err(|_| JwtError::InvalidJwt)?;
+        let payload = serde_json::to_vec(&self).map_err(|e| JwtError::InvalidJwt(e.into()))?;
 
-        let jws = JwsInner::new(payload).set_typ("JWT".to_string());
+        let jws = JwsInner::new(payload).set_header(header);
 
-        let jws = if let Some(k) = kid {
-            jws.set_kid(k.to_string())
-        } else {
-            jws
-        };
+        jws.sign_inner(signer, None).map(|jwsc| OidcSigned { jwsc })
+    }
+
+    /// Set the at_hash of this token from the access token it is issued alongside, using the
+    /// hash function of the algorithm of this signer.
+    pub fn set_at_hash(mut self, signer: &JwsSigner, access_token: &str) -> Result<Self, JwtError> {
+        self.at_hash = Some(signer.oidc_token_hash(access_token)?);
+        Ok(self)
+    }
 
-        jws.sign_inner(signer, None, None)
-            .map(|jwsc| OidcSigned { jwsc })
+    /// Set the c_hash of this token from the authorization code it is issued alongside, using
+    /// the hash function of the algorithm of this signer.
+    pub fn set_c_hash(mut self, signer: &JwsSigner, code: &str) -> Result<Self, JwtError> {
+        self.c_hash = Some(signer.oidc_token_hash(code)?);
+        Ok(self)
     }
 
     /// Use this private signer to created a signed oidc token.
     pub fn sign(&self, signer: &JwsSigner) -> Result<OidcSigned, JwtError> {
-        self.sign_inner(signer, None)
+        self.sign_inner(signer, JwsHeader::new().set_typ("JWT"))
     }
 
     /// set the key id (kid) into the header.
     /// use set_kid on jws.
     pub fn sign_with_kid(&self, signer: &JwsSigner, kid: &str) -> Result<OidcSigned, JwtError> {
-        self.sign_inner(signer, Some(kid))
+        self.sign_inner(signer, JwsHeader::new().set_typ("JWT").set_kid(kid))
+    }
+
+    /// Use this private signer to created a signed oidc token with these header parameters,
+    /// for example to set a typ of `logout+jwt`. The typ is not set unless it is part of the
+    /// header.
+    pub fn sign_with_header(
+        &self,
+        signer: &JwsSigner,
+        header: &JwsHeader,
+    ) -> Result<OidcSigned, JwtError> {
+        self.sign_inner(signer, header.clone())
     }
 
     /*
@@ -153,22 +191,91 @@ impl OidcToken {
     */
 }
 
+/// Options that control how the time claims (exp, nbf, iat) of an oidc token are validated.
+#[derive(Debug, Clone, Default)]
+pub struct OidcValidationOptions {
+    leeway: Duration,
+    skip_expiry: bool,
+}
+
+impl OidcValidationOptions {
+    /// Create the default options, which check exp, nbf and iat with no leeway.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the allowed clock skew between the issuer and this host when checking the
+    /// exp, nbf and iat claims.
+    pub fn set_leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    /// Do not check the exp claim of the token, allowing expired tokens to be accepted.
+    /// This is DANGEROUS.
+    pub fn dangerous_skip_expiry(mut self) -> Self {
+        self.skip_expiry = true;
+        self
+    }
+}
+
 impl OidcUnverified {
     /// Using this JwsValidator, assert the correct signature of the data contained in
-    /// this token. The current time is represented by seconds since the epoch. You may
-    /// choose to ignore exp validation by setting this to 0, but this is DANGEROUS.
-    pub fn validate(&self, validator: &JwsValidator, curtime: i64) -> Result<OidcToken, JwtError> {
-        let released = self.jwsc.validate(validator)?;
-
-        let tok: OidcToken =
-            serde_json::from_slice(released.payload()).map_err(|_| JwtError::InvalidJwt)?;
-
-        // Check the exp
-        if tok.exp != 0 && tok.exp < curtime {
-            Err(JwtError::OidcTokenExpired)
-        } else {
-            Ok(tok)
-        }
+    /// this token. A JwkKeySet may also be used, which selects the key by the kid and alg
+    /// of this token. The current time is read from this clock, which may be a fixed number
+    /// of seconds since the epoch, and is checked against the exp, nbf and iat claims of
+    /// the token.
+    pub fn validate<S, C>(&self, validator: &S, clock: C) -> Result<OidcToken, JwtError>
+    where
+        S: JwsValidatorSelector + ?Sized,
+        C: Clock,
+    {
+        self.validate_with_options(validator, clock, &OidcValidationOptions::default())
+    }
+
+    /// Validate this token as [`validate`](Self::validate), using these options to check
+    /// the time claims of the token.
+    pub fn validate_with_options<S, C>(
+        &self,
+        validator: &S,
+        clock: C,
+        options: &OidcValidationOptions,
+    ) -> Result<OidcToken, JwtError>
+    where
+        S: JwsValidatorSelector + ?Sized,
+        C: Clock,
+    {
+        self.validate_inner(validator, clock.now().as_secs(), options)
+            .map(|(tok, _, _)| tok)
+    }
+
+    fn validate_inner<'a, S>(
+        &self,
+        validator: &'a S,
+        curtime: i64,
+        options: &OidcValidationOptions,
+    ) -> Result<(OidcToken, String, Cow<'a, JwsValidator>), JwtError>
+    where
+        S: JwsValidatorSelector + ?Sized,
+    {
+        let validator = validator.select_validator(self.jwsc.get_jwk_kid(), self.jwsc.get_alg())?;
+        let released = self.jwsc.validate(&validator)?;
+
+        let tok: OidcToken = serde_json::from_slice(released.payload())
+            .map_err(|e| JwtError::InvalidJwt(e.into()))?;
+
+        // Parsing the issuer as a url normalises it, so keep the issuer exactly as it was
+        // sent for comparison.
+        let OidcIssuer { iss } = serde_json::from_slice(released.payload())
+            .map_err(|e| JwtError::InvalidJwt(e.into()))?;
+
+        let leeway = i64::try_from(options.leeway.as_secs()).unwrap_or(i64::MAX);
+
+        // The exp is always present, but checking it can be skipped.
+        let exp = (!options.skip_expiry).then_some(tok.exp);
+        check_time_claims(exp, tok.nbf, Some(tok.iat), curtime, leeway)?;
+
+        Ok((tok, iss, validator))
     }
 
     /// Retrieve the Key ID used to sign this jwt, if any.
@@ -176,6 +283,21 @@ impl OidcUnverified {
         self.jwsc.get_jwk_kid()
     }
 
+    /// Retrieve the protected header of this token. These values are not trustworthy until
+    /// the token is validated.
+    pub fn header(&self) -> &ProtectedHeader {
+        self.jwsc.header()
+    }
+
+    /// Parse this token, accepting the critical header parameters that are understood by
+    /// these extensions.
+    pub fn from_str_with_critical(
+        s: &str,
+        critical: &CriticalExtensions,
+    ) -> Result<Self, JwtError> {
+        JwsCompact::from_str_with_critical(s, critical).map(|jwsc| OidcUnverified { jwsc })
+    }
+
     /*
     /// Retrieve the URL which holds the public key used to sign this token if it exists
     /// in the JWS header.
@@ -190,6 +312,187 @@ impl OidcUnverified {
     */
 }
 
+/// A verifier of OpenID Connect ID Tokens, which asserts the claims of the token as
+/// required by a relying party.
+/// `https://openid.net/specs/openid-connect-core-1_0.html#IDTokenValidation`
+#[derive(Debug, Clone)]
+pub struct OidcIdTokenVerifier {
+    issuer: String,
+    client_id: String,
+    nonce: Option<String>,
+    max_age: Option<Duration>,
+    acr_values: Vec<String>,
+    access_token: Option<String>,
+    code: Option<String>,
+    options: OidcValidationOptions,
+}
+
+impl OidcIdTokenVerifier {
+    /// Create a verifier for tokens from this issuer that were issued to this client_id. The
+    /// iss of the token must be exactly this string, as published in the issuer's discovery
+    /// document.
+    pub fn new(issuer: &str, client_id: &str) -> Self {
+        OidcIdTokenVerifier {
+            issuer: issuer.to_string(),
+            client_id: client_id.to_string(),
+            nonce: None,
+            max_age: None,
+            acr_values: Vec::new(),
+            access_token: None,
+            code: None,
+            options: OidcValidationOptions::default(),
+        }
+    }
+
+    /// The nonce that was sent in the authentication request. The token must contain
+    /// the same nonce.
+    pub fn set_nonce(mut self, nonce: &str) -> Self {
+        self.nonce = Some(nonce.to_string());
+        self
+    }
+
+    /// The max_age that was sent in the authentication request. The token must contain an
+    /// auth_time that is no older than this.
+    pub fn set_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Add an acr value that was requested in the authentication request. If any values are
+    /// set, the acr of the token must be one of them.
+    pub fn add_acr_value(mut self, acr: &str) -> Self {
+        self.acr_values.push(acr.to_string());
+        self
+    }
+
+    /// The access token that was issued alongside the token. The token must contain an
+    /// at_hash that matches this access token.
+    pub fn set_access_token(mut self, access_token: &str) -> Self {
+        self.access_token = Some(access_token.to_string());
+        self
+    }
+
+    /// The authorization code that was issued alongside the token. The token must contain a
+    /// c_hash that matches this code.
+    pub fn set_code(mut self, code: &str) -> Self {
+        self.code = Some(code.to_string());
+        self
+    }
+
+    /// Set the options used to validate the time claims of the token.
+    pub fn set_validation_options(mut self, options: OidcValidationOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Using this JwsValidator, assert the correct signature of the token, and then assert
+    /// that the claims of the token are valid for this relying party. The current time is
+    /// read from this clock, which may be a fixed number of seconds since the epoch.
+    pub fn verify<S, C>(
+        &self,
+        token: &OidcUnverified,
+        validator: &S,
+        clock: C,
+    ) -> Result<OidcToken, JwtError>
+    where
+        S: JwsValidatorSelector + ?Sized,
+        C: Clock,
+    {
+        let curtime = clock.now().as_secs();
+        let (tok, iss, validator) = token.validate_inner(validator, curtime, &self.options)?;
+
+        // 2. The issuer must exactly match.
+        if iss != self.issuer {
+            return Err(JwtError::InvalidIssuer {
+                expected: vec![self.issuer.clone()],
+                actual: Some(iss),
+            });
+        }
+
+        // 3. The audience must contain our client_id.
+        if !tok.aud.contains(&self.client_id) {
+            return Err(JwtError::InvalidAudience {
+                expected: vec![self.client_id.clone()],
+                actual: Some(tok.aud),
+            });
+        }
+
+        // 4. If there are multiple audiences, azp must be present.
+        // 5. If azp is present, it must be our client_id.
+        let azp_valid = match &tok.azp {
+            Some(azp) => azp == &self.client_id,
+            None => !tok.aud.is_multiple(),
+        };
+        if !azp_valid {
+            return Err(JwtError::InvalidAuthorizedParty {
+                expected: self.client_id.clone(),
+                actual: tok.azp,
+            });
+        }
+
+        // 11. If a nonce was sent, it must be returned.
+        if let Some(nonce) = &self.nonce {
+            if tok.nonce.as_ref() != Some(nonce) {
+                return Err(JwtError::InvalidNonce {
+                    expected: nonce.clone(),
+                    actual: tok.nonce,
+                });
+            }
+        }
+
+        // 12. If acr was requested, the acr must be one of the requested values.
+        if !self.acr_values.is_empty() {
+            match &tok.acr {
+                Some(acr) if self.acr_values.contains(acr) => {}
+                _ => {
+                    return Err(JwtError::InvalidAcr {
+                        expected: self.acr_values.clone(),
+                        actual: tok.acr,
+                    })
+                }
+            }
+        }
+
+        // 13. If max_age was requested, auth_time must be present and within max_age.
+        if let Some(max_age) = self.max_age {
+            let auth_time = tok.auth_time.ok_or_else(|| JwtError::MissingClaim {
+                claim: "auth_time".to_string(),
+            })?;
+            let leeway = i64::try_from(self.options.leeway.as_secs()).unwrap_or(i64::MAX);
+            let max_age_secs = i64::try_from(max_age.as_secs()).unwrap_or(i64::MAX);
+            if curtime.saturating_sub(auth_time.as_secs()) > max_age_secs.saturating_add(leeway) {
+                return Err(JwtError::AuthTimeTooOld {
+                    auth_time,
+                    max_age,
+                    now: NumericDate::from_secs(curtime),
+                });
+            }
+        }
+
+        // If we were issued an access token or code, the token must carry its hash, and
+        // the hash must match.
+        if let Some(access_token) = &self.access_token {
+            let at_hash = tok.at_hash.as_ref().ok_or_else(|| JwtError::MissingClaim {
+                claim: "at_hash".to_string(),
+            })?;
+            if validator.oidc_token_hash(access_token)? != *at_hash {
+                return Err(JwtError::InvalidAtHash);
+            }
+        }
+
+        if let Some(code) = &self.code {
+            let c_hash = tok.c_hash.as_ref().ok_or_else(|| JwtError::MissingClaim {
+                claim: "c_hash".to_string(),
+            })?;
+            if validator.oidc_token_hash(code)? != *c_hash {
+                return Err(JwtError::InvalidCHash);
+            }
+        }
+
+        Ok(tok)
+    }
+}
+
 impl FromStr for OidcUnverified {
     type Err = JwtError;
 
@@ -214,10 +517,16 @@ impl fmt::Display for OidcSigned {
 
 #[cfg(test)]
 mod tests {
-    use super::{OidcSubject, OidcToken, OidcUnverified};
-    use crate::crypto::{JwsSigner, JwsValidator};
+    use super::{
+        OidcIdTokenVerifier, OidcSubject, OidcToken, OidcUnverified, OidcValidationOptions,
+    };
+    use crate::clock::FixedClock;
+    use crate::crypto::{JwaAlg, JwkKeySet, JwsSigner, JwsValidator};
+    use crate::error::JwtError;
+    use crate::numeric_date::NumericDate;
     use std::convert::TryFrom;
     use std::str::FromStr;
+    use std::time::Duration;
     use url::Url;
 
     #[test]
@@ -225,13 +534,14 @@ mod tests {
         let jwt = OidcToken {
             iss: Url::parse("https://oidc.example.com").unwrap(),
             sub: OidcSubject::S("a unique id".to_string()),
-            aud: "test".to_string(),
-            exp: 0,
-            nbf: Some(0),
-            iat: 0,
+            aud: "test".into(),
+            exp: NumericDate::from_secs(0),
+            nbf: Some(NumericDate::from_secs(0)),
+            iat: NumericDate::from_secs(0),
             auth_time: None,
             nonce: None,
             at_hash: None,
+            c_hash: None,
             acr: None,
             amr: None,
             azp: None,
@@ -260,13 +570,14 @@ mod tests {
         let jwt = OidcToken {
             iss: Url::parse("https://oidc.example.com").unwrap(),
             sub: OidcSubject::S("a unique id".to_string()),
-            aud: "test".to_string(),
-            exp: 0,
-            nbf: Some(0),
-            iat: 0,
+            aud: "test".into(),
+            exp: NumericDate::from_secs(0),
+            nbf: Some(NumericDate::from_secs(0)),
+            iat: NumericDate::from_secs(0),
             auth_time: None,
             nonce: None,
             at_hash: None,
+            c_hash: None,
             acr: None,
             amr: None,
             azp: None,
@@ -291,4 +602,390 @@ mod tests {
 
         assert!(released == jwt);
     }
+
+    #[test]
+    fn test_sign_and_validate_jwk_key_set() {
+        let jwt = OidcToken {
+            iss: Url::parse("https://oidc.example.com").unwrap(),
+            sub: OidcSubject::S("a unique id".to_string()),
+            aud: "test".into(),
+            exp: NumericDate::from_secs(0),
+            nbf: Some(NumericDate::from_secs(0)),
+            iat: NumericDate::from_secs(0),
+            auth_time: None,
+            nonce: None,
+            at_hash: None,
+            c_hash: None,
+            acr: None,
+            amr: None,
+            azp: None,
+            jti: None,
+            s_claims: Default::default(),
+            claims: Default::default(),
+        };
+
+        let jwss = JwsSigner::generate_es256().expect("failed to construct signer.");
+        let other = JwsSigner::generate_es256().expect("failed to construct signer.");
+        let jwks = JwkKeySet {
+            keys: vec![
+                other.public_key_as_jwk(Some("other")).unwrap(),
+                jwss.public_key_as_jwk(Some("current")).unwrap(),
+            ],
+        };
+
+        let jwts = jwt
+            .sign_with_kid(&jwss, "current")
+            .expect("failed to sign jwt");
+
+        let jwtu = OidcUnverified::from_str(&jwts.to_string()).expect("Unable to parse jws/jwt");
+        assert!(jwtu.get_jwk_kid() == Some("current"));
+
+        assert!(jwtu.header().kid() == Some("current"));
+        assert!(jwtu.header().typ() == Some("JWT"));
+        assert!(jwtu.header().alg() == &JwaAlg::ES256);
+        let released = jwtu.validate(&jwks, 0).expect("Unable to validate jwt");
+
+        assert!(released == jwt);
+    }
+
+    #[test]
+    fn test_validate_time_claims() {
+        let jwt = OidcToken {
+            iss: Url::parse("https://oidc.example.com").unwrap(),
+            sub: OidcSubject::S("a unique id".to_string()),
+            aud: "test".into(),
+            exp: NumericDate::from_secs(1000),
+            nbf: Some(NumericDate::from_secs(200)),
+            iat: NumericDate::from_secs(100),
+            auth_time: None,
+            nonce: None,
+            at_hash: None,
+            c_hash: None,
+            acr: None,
+            amr: None,
+            azp: None,
+            jti: None,
+            s_claims: Default::default(),
+            claims: Default::default(),
+        };
+
+        let jwss = JwsSigner::generate_es256().expect("failed to construct signer.");
+        let jws_validator = jwss.get_validator().expect("Unable to create validator");
+
+        let jwtu = jwt.sign(&jwss).expect("failed to sign jwt").invalidate();
+
+        assert!(jwtu.validate(&jws_validator, 200).is_ok());
+        assert!(jwtu.validate(&jws_validator, 1000).is_ok());
+        assert!(
+            jwtu.validate(&jws_validator, 1001)
+                == Err(JwtError::TokenExpired {
+                    exp: NumericDate::from_secs(1000),
+                    now: NumericDate::from_secs(1001),
+                })
+        );
+        assert!(
+            jwtu.validate(&jws_validator, 199)
+                == Err(JwtError::NotYetValid {
+                    nbf: NumericDate::from_secs(200),
+                    now: NumericDate::from_secs(199),
+                })
+        );
+
+        let options = OidcValidationOptions::new().set_leeway(Duration::from_secs(30));
+        assert!(jwtu
+            .validate_with_options(&jws_validator, 1030, &options)
+            .is_ok());
+        assert!(matches!(
+            jwtu.validate_with_options(&jws_validator, 1031, &options),
+            Err(JwtError::TokenExpired { .. })
+        ));
+        assert!(jwtu
+            .validate_with_options(&jws_validator, 170, &options)
+            .is_ok());
+        assert!(matches!(
+            jwtu.validate_with_options(&jws_validator, 169, &options),
+            Err(JwtError::NotYetValid { .. })
+        ));
+
+        // A clock can be advanced across the expiry of the token.
+        let clock = FixedClock::new(NumericDate::from_secs(999));
+        assert!(jwtu.validate(&jws_validator, &clock).is_ok());
+        clock.advance(Duration::from_secs(1));
+        assert!(jwtu.validate(&jws_validator, &clock).is_ok());
+        clock.advance(Duration::from_secs(1));
+        assert!(matches!(
+            jwtu.validate(&jws_validator, &clock),
+            Err(JwtError::TokenExpired { .. })
+        ));
+
+        // Skipping expiry must be explicit.
+        let options = OidcValidationOptions::new().dangerous_skip_expiry();
+        assert!(jwtu
+            .validate_with_options(&jws_validator, i64::MAX, &options)
+            .is_ok());
+
+        // A token issued in the future, without nbf.
+        let jwt = OidcToken { nbf: None, ..jwt };
+        let jwtu = jwt.sign(&jwss).expect("failed to sign jwt").invalidate();
+        assert!(jwtu.validate(&jws_validator, 100).is_ok());
+        assert!(matches!(
+            jwtu.validate(&jws_validator, 99),
+            Err(JwtError::IssuedInFuture { .. })
+        ));
+
+        // An exp of 0 is no longer treated as never expiring.
+        let jwt = OidcToken {
+            exp: NumericDate::from_secs(0),
+            ..jwt
+        };
+        let jwtu = jwt.sign(&jwss).expect("failed to sign jwt").invalidate();
+        assert!(matches!(
+            jwtu.validate(&jws_validator, 100),
+            Err(JwtError::TokenExpired { .. })
+        ));
+    }
+
+    #[test]
+    fn test_id_token_verifier() {
+        let issuer = Url::parse("https://oidc.example.com").unwrap();
+        let jwt = OidcToken {
+            iss: issuer.clone(),
+            sub: OidcSubject::S("a unique id".to_string()),
+            aud: "client".into(),
+            exp: NumericDate::from_secs(1000),
+            nbf: None,
+            iat: NumericDate::from_secs(100),
+            auth_time: Some(NumericDate::from_secs(50)),
+            nonce: Some("nonce".to_string()),
+            at_hash: None,
+            c_hash: None,
+            acr: Some("urn:mace:incommon:iap:silver".to_string()),
+            amr: None,
+            azp: Some("client".to_string()),
+            jti: None,
+            s_claims: Default::default(),
+            claims: Default::default(),
+        };
+
+        let jwss = JwsSigner::generate_es256().expect("failed to construct signer.");
+        let jws_validator = jwss.get_validator().expect("Unable to create validator");
+
+        let verify = |jwt: &OidcToken, verifier: &OidcIdTokenVerifier| {
+            let jwtu = jwt.sign(&jwss).expect("failed to sign jwt").invalidate();
+            verifier.verify(&jwtu, &jws_validator, 200)
+        };
+
+        let verifier = OidcIdTokenVerifier::new(issuer.as_str(), "client")
+            .set_nonce("nonce")
+            .set_max_age(Duration::from_secs(150))
+            .add_acr_value("urn:mace:incommon:iap:bronze")
+            .add_acr_value("urn:mace:incommon:iap:silver");
+
+        let released = verify(&jwt, &verifier).expect("Unable to verify token");
+        assert!(released == jwt);
+
+        // Issuer
+        let other = OidcIdTokenVerifier::new("https://other.example.com/", "client");
+        assert!(matches!(
+            verify(&jwt, &other),
+            Err(JwtError::InvalidIssuer { .. })
+        ));
+
+        // The issuer is compared as a string, not as an equivalent url.
+        for equivalent in [
+            "https://oidc.example.com",
+            "https://OIDC.example.com/",
+            "https://oidc.example.com:443/",
+        ] {
+            let other = OidcIdTokenVerifier::new(equivalent, "client");
+            assert!(matches!(
+                verify(&jwt, &other),
+                Err(JwtError::InvalidIssuer { .. })
+            ));
+        }
+
+        // Audience
+        let other = OidcIdTokenVerifier::new(issuer.as_str(), "other");
+        assert!(matches!(
+            verify(&jwt, &other),
+            Err(JwtError::InvalidAudience { .. })
+        ));
+
+        // Authorized party
+        let bad = OidcToken {
+            azp: Some("other".to_string()),
+            ..jwt.clone()
+        };
+        assert!(matches!(
+            verify(&bad, &verifier),
+            Err(JwtError::InvalidAuthorizedParty { .. })
+        ));
+
+        let multi = OidcToken {
+            aud: vec!["client".to_string(), "other".to_string()].into(),
+            ..jwt.clone()
+        };
+        assert!(verify(&multi, &verifier).is_ok());
+        let bad = OidcToken {
+            azp: None,
+            ..multi.clone()
+        };
+        assert!(matches!(
+            verify(&bad, &verifier),
+            Err(JwtError::InvalidAuthorizedParty { .. })
+        ));
+
+        // Nonce
+        let bad = OidcToken {
+            nonce: None,
+            ..jwt.clone()
+        };
+        assert!(matches!(
+            verify(&bad, &verifier),
+            Err(JwtError::InvalidNonce { .. })
+        ));
+        let other = verifier.clone().set_nonce("other");
+        assert!(matches!(
+            verify(&jwt, &other),
+            Err(JwtError::InvalidNonce { .. })
+        ));
+
+        // Acr
+        let bad = OidcToken {
+            acr: Some("urn:mace:incommon:iap:gold".to_string()),
+            ..jwt.clone()
+        };
+        assert!(matches!(
+            verify(&bad, &verifier),
+            Err(JwtError::InvalidAcr { .. })
+        ));
+
+        // Max age
+        let other = verifier.clone().set_max_age(Duration::from_secs(149));
+        assert!(matches!(
+            verify(&jwt, &other),
+            Err(JwtError::AuthTimeTooOld { .. })
+        ));
+        let bad = OidcToken {
+            auth_time: None,
+            ..jwt.clone()
+        };
+        assert!(matches!(
+            verify(&bad, &verifier),
+            Err(JwtError::MissingClaim { .. })
+        ));
+
+        // Leeway applies to max_age as well as the time claims.
+        let other = other.set_validation_options(
+            OidcValidationOptions::new().set_leeway(Duration::from_secs(1)),
+        );
+        assert!(verify(&jwt, &other).is_ok());
+
+        // Time claims are still checked.
+        let bad = OidcToken {
+            exp: NumericDate::from_secs(199),
+            ..jwt.clone()
+        };
+        assert!(matches!(
+            verify(&bad, &verifier),
+            Err(JwtError::TokenExpired { .. })
+        ));
+    }
+
+    #[test]
+    fn test_id_token_hashes() {
+        let issuer = Url::parse("https://oidc.example.com").unwrap();
+        let access_token = "jHkWEdUXMU1BwAsC4vtUsZwnNnVRuOdmJDWoHL3ohLw";
+        let code = "Qcb0Orv1zh30vL1MPRsbm-diHiMwcLyZvn1arpZv-Jxf_11jnpEX3Tgfvk";
+
+        let jwss = JwsSigner::generate_es384().expect("failed to construct signer.");
+        let jws_validator = jwss.get_validator().expect("Unable to create validator");
+
+        let jwt = OidcToken {
+            iss: issuer.clone(),
+            sub: OidcSubject::S("a unique id".to_string()),
+            aud: "client".into(),
+            exp: NumericDate::from_secs(1000),
+            nbf: None,
+            iat: NumericDate::from_secs(100),
+            auth_time: None,
+            nonce: None,
+            at_hash: None,
+            c_hash: None,
+            acr: None,
+            amr: None,
+            azp: None,
+            jti: None,
+            s_claims: Default::default(),
+            claims: Default::default(),
+        }
+        .set_at_hash(&jwss, access_token)
+        .and_then(|jwt| jwt.set_c_hash(&jwss, code))
+        .expect("Unable to set token hashes");
+
+        // ES384 uses the left half of a SHA-384 hash.
+        assert!(jwt.at_hash.as_ref().map(|h| h.len()) == Some(32));
+        assert!(jwt.c_hash.as_ref().map(|h| h.len()) == Some(32));
+
+        let jwtu = jwt.sign(&jwss).expect("failed to sign jwt").invalidate();
+
+        let verifier = OidcIdTokenVerifier::new(issuer.as_str(), "client")
+            .set_access_token(access_token)
+            .set_code(code);
+        let released = verifier
+            .verify(&jwtu, &jws_validator, 200)
+            .expect("Unable to verify token");
+        assert!(released == jwt);
+
+        let verifier =
+            OidcIdTokenVerifier::new(issuer.as_str(), "client").set_access_token("other");
+        assert!(verifier.verify(&jwtu, &jws_validator, 200) == Err(JwtError::InvalidAtHash));
+
+        let verifier = OidcIdTokenVerifier::new(issuer.as_str(), "client").set_code("other");
+        assert!(verifier.verify(&jwtu, &jws_validator, 200) == Err(JwtError::InvalidCHash));
+
+        // A token without the hashes is rejected when we hold the access token or code.
+        let jwt = OidcToken {
+            at_hash: None,
+            c_hash: None,
+            ..jwt
+        };
+        let jwtu = jwt.sign(&jwss).expect("failed to sign jwt").invalidate();
+
+        let verifier =
+            OidcIdTokenVerifier::new(issuer.as_str(), "client").set_access_token(access_token);
+        assert!(
+            verifier.verify(&jwtu, &jws_validator, 200)
+                == Err(JwtError::MissingClaim {
+                    claim: "at_hash".to_string()
+                })
+        );
+
+        let verifier = OidcIdTokenVerifier::new(issuer.as_str(), "client").set_code(code);
+        assert!(
+            verifier.verify(&jwtu, &jws_validator, 200)
+                == Err(JwtError::MissingClaim {
+                    claim: "c_hash".to_string()
+                })
+        );
+
+        // The hashes are not required when we hold neither.
+        let verifier = OidcIdTokenVerifier::new(issuer.as_str(), "client");
+        assert!(verifier.verify(&jwtu, &jws_validator, 200).is_ok());
+    }
+
+    #[test]
+    fn test_fractional_numeric_dates() {
+        let tok: OidcToken = serde_json::from_str(
+            r#"{"iss":"https://oidc.example.com","sub":"a unique id","aud":"client","exp":1650000300.75,"iat":1650000000.123,"auth_time":1650000000}"#,
+        )
+        .expect("Unable to parse token");
+        assert!(tok.exp.as_secs() == 1650000300);
+        assert!(tok.iat.as_secs() == 1650000000);
+        assert!(tok.auth_time == Some(NumericDate::from_secs(1650000000)));
+
+        let encoded = serde_json::to_string(&tok).expect("Unable to serialise token");
+        assert!(encoded.contains(r#""exp":1650000300,"#));
+        assert!(encoded.contains(r#""iat":1650000000,"#));
+    }
 }